@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Copies an 8-bit 2D texture into an image.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<image::RgbaImage> {
+    let bgra = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        other => bail!("cannot read back {:?} textures", other),
+    };
+    let mut pixels = read_pixels(device, queue, texture)?;
+    if bgra {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    image::RgbaImage::from_raw(texture.width(), texture.height(), pixels)
+        .ok_or_else(|| anyhow!("readback has the wrong size"))
+}
+
+/// Copies a floating point 2D texture into an image, values unclamped.
+pub fn read_texture_hdr(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<image::Rgba32FImage> {
+    let bytes = read_pixels(device, queue, texture)?;
+    let pixels: Vec<f32> = match texture.format() {
+        wgpu::TextureFormat::Rgba16Float => bytes
+            .chunks_exact(2)
+            .map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect(),
+        wgpu::TextureFormat::Rgba32Float => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        other => bail!("cannot read back {:?} textures as HDR", other),
+    };
+    image::Rgba32FImage::from_raw(texture.width(), texture.height(), pixels)
+        .ok_or_else(|| anyhow!("readback has the wrong size"))
+}
+
+/// Bytes of a 2D texture, row after row. Rows are padded to
+/// `COPY_BYTES_PER_ROW_ALIGNMENT` for the copy and unpadded again here.
+fn read_pixels(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>> {
+    let (width, height) = (texture.width(), texture.height());
+    let pixel_size = texture
+        .format()
+        .block_copy_size(None)
+        .ok_or_else(|| anyhow!("cannot read back {:?} textures", texture.format()))?;
+
+    let unpadded = width * pixel_size;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded = unpadded.div_ceil(align) * align;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: padded as u64 * height as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()
+        .context("readback was dropped")?
+        .context("failed to map the readback buffer")?;
+
+    let mut pixels = Vec::with_capacity((unpadded * height) as usize);
+    for row in slice.get_mapped_range().chunks_exact(padded as usize) {
+        pixels.extend_from_slice(&row[..unpadded as usize]);
+    }
+    buffer.unmap();
+    Ok(pixels)
+}
+
+/// Files for raw HDR frames, `render --raw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    /// OpenEXR with 32-bit float RGBA.
+    Exr,
+    /// Radiance RGBE, without alpha.
+    Hdr,
+}
+
+impl RawFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "exr" => Ok(Self::Exr),
+            "hdr" => Ok(Self::Hdr),
+            _ => Err(anyhow!("invalid raw format `{}`, expected exr or hdr", s)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Exr => "exr",
+            Self::Hdr => "hdr",
+        }
+    }
+
+    pub fn save(self, image: image::Rgba32FImage, path: &Path) -> Result<()> {
+        let result = match self {
+            Self::Exr => image.save(path),
+            Self::Hdr => image::DynamicImage::ImageRgba32F(image).to_rgb32f().save(path),
+        };
+        result.with_context(|| format!("failed to write `{}`", path.display()))
+    }
+}
+
+/// `<dir>/shader_toy_<date>_<time>.png`, creating `dir` if needed.
+pub fn save_timestamped(image: &image::RgbaImage, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create `{}`", dir.display()))?;
+    let name = chrono::Local::now().format("shader_toy_%Y%m%d_%H%M%S%.3f.png");
+    let path = dir.join(name.to_string());
+    image
+        .save(&path)
+        .with_context(|| format!("failed to write `{}`", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,431 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{compute::STORAGE_TEXTURE_COUNT, texture::Texture};
+
+/// Number of `iChannel` inputs every pass gets.
+pub const CHANNEL_COUNT: usize = 4;
+
+/// Binding of the first cube texture in group 1, see `create_bind_group_layout`.
+pub const CUBE_BINDING: u32 = CHANNEL_COUNT as u32 * 2;
+/// Binding of the `iChannelResolution` uniform in group 1.
+pub const RESOLUTION_BINDING: u32 = CUBE_BINDING + CHANNEL_COUNT as u32;
+
+/// Cube face file names, in layer order.
+const CUBE_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+const NOISE_SIZE: u32 = 256;
+
+/// What a pass sees in one of its `iChannel` slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelSource {
+    /// The built-in `assets/test.png` texture.
+    Builtin,
+    /// Output of one of the offscreen buffers, 0 = Buffer A.
+    Buffer(usize),
+    /// Storage texture written by the compute shader.
+    Storage(usize),
+    /// Image file on disk.
+    File(PathBuf),
+    /// Directory holding `px`, `nx`, `py`, `ny`, `pz` and `nz` images.
+    Cubemap(PathBuf),
+    /// Procedural RGBA noise.
+    Noise,
+}
+
+impl ChannelSource {
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Some(dir) = s.strip_prefix("cubemap:") {
+            return Ok(Self::Cubemap(PathBuf::from(dir)));
+        }
+        if let Some(index) = s.strip_prefix("storage-") {
+            return match index.parse() {
+                Ok(index) if index < STORAGE_TEXTURE_COUNT => Ok(Self::Storage(index)),
+                _ => Err(anyhow!(
+                    "invalid storage texture `{}`, expected storage-0..{}",
+                    s,
+                    STORAGE_TEXTURE_COUNT - 1
+                )),
+            };
+        }
+        Ok(match s {
+            "builtin" => Self::Builtin,
+            "noise" => Self::Noise,
+            _ => match parse_buffer_name(s) {
+                Some(i) => Self::Buffer(i),
+                None => Self::File(PathBuf::from(s)),
+            },
+        })
+    }
+
+    pub fn is_cube(&self) -> bool {
+        matches!(self, Self::Cubemap(_))
+    }
+}
+
+/// Accepts `a`..`d` and `buffer-a`..`buffer-d`.
+pub fn parse_buffer_name(s: &str) -> Option<usize> {
+    let letter = s.strip_prefix("buffer-").unwrap_or(s);
+    match letter {
+        "a" | "A" => Some(0),
+        "b" | "B" => Some(1),
+        "c" | "C" => Some(2),
+        "d" | "D" => Some(3),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+    /// Trilinear filtering over a mip chain. Buffers have no mips and
+    /// fall back to `Linear`.
+    Mipmap,
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "linear" => Ok(Self::Linear),
+            "mipmap" => Ok(Self::Mipmap),
+            _ => bail!("unknown filter `{}`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl Wrap {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "clamp" => Ok(Self::Clamp),
+            "repeat" => Ok(Self::Repeat),
+            "mirror" => Ok(Self::Mirror),
+            _ => bail!("unknown wrap mode `{}`", s),
+        }
+    }
+
+    pub fn address_mode(self) -> wgpu::AddressMode {
+        match self {
+            Self::Clamp => wgpu::AddressMode::ClampToEdge,
+            Self::Repeat => wgpu::AddressMode::Repeat,
+            Self::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// Per-channel sampling settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelSampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    /// Flip images on upload so `uv.y = 0` is their bottom row.
+    pub vflip: bool,
+}
+
+impl ChannelSampler {
+    /// Shadertoy's defaults for the given source.
+    pub fn default_for(source: &ChannelSource) -> Self {
+        match source {
+            ChannelSource::Buffer(_) | ChannelSource::Storage(_) => Self {
+                filter: Filter::Linear,
+                wrap: Wrap::Clamp,
+                vflip: false,
+            },
+            ChannelSource::Cubemap(_) => Self {
+                filter: Filter::Linear,
+                wrap: Wrap::Clamp,
+                vflip: false,
+            },
+            _ => Self {
+                filter: Filter::Mipmap,
+                wrap: Wrap::Repeat,
+                vflip: true,
+            },
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let (filter, mipmap_filter) = match self.filter {
+            Filter::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+            Filter::Linear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+            Filter::Mipmap => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        };
+        let am = self.wrap.address_mode();
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("channel_sampler"),
+            address_mode_u: am,
+            address_mode_v: am,
+            address_mode_w: am,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            ..Default::default()
+        })
+    }
+}
+
+/// Source of a channel together with how it is sampled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelInput {
+    pub source: ChannelSource,
+    pub sampler: ChannelSampler,
+}
+
+impl ChannelInput {
+    pub fn new(source: ChannelSource) -> Self {
+        let sampler = ChannelSampler::default_for(&source);
+        Self { source, sampler }
+    }
+
+    /// `<source>[,filter=nearest|linear|mipmap][,wrap=clamp|repeat|mirror][,vflip|,novflip]`
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts = s.split(',');
+        let mut input = Self::new(ChannelSource::parse(parts.next().unwrap_or_default())?);
+        for part in parts {
+            match part.split_once('=') {
+                Some(("filter", v)) => input.sampler.filter = Filter::parse(v)?,
+                Some(("wrap", v)) => input.sampler.wrap = Wrap::parse(v)?,
+                None if part == "vflip" => input.sampler.vflip = true,
+                None if part == "novflip" => input.sampler.vflip = false,
+                _ => bail!("unknown channel setting `{}`", part),
+            }
+        }
+        Ok(input)
+    }
+
+    /// Loads the texture for sources that own one. Buffers, storage
+    /// textures and the built-in texture are owned by `Stoy`. `seed` fixes the noise, which
+    /// is different on every run otherwise.
+    pub fn load_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        seed: Option<u64>,
+    ) -> Result<Option<Texture>> {
+        let am = self.sampler.wrap.address_mode();
+        let flip = |img: image::DynamicImage| if self.sampler.vflip { img.flipv() } else { img };
+        let upload = |img: &image::DynamicImage, label: &str| {
+            if self.sampler.filter == Filter::Mipmap {
+                Texture::from_image_mipmapped(device, queue, img, am, Some(label))
+            } else {
+                Texture::from_image(device, queue, img, am, Some(label))
+            }
+        };
+        match &self.source {
+            ChannelSource::Builtin | ChannelSource::Buffer(_) | ChannelSource::Storage(_) => {
+                Ok(None)
+            }
+            ChannelSource::File(path) => {
+                let img = image::open(path)
+                    .with_context(|| format!("failed to load `{}`", path.display()))?;
+                upload(&flip(img), &path.display().to_string()).map(Some)
+            }
+            ChannelSource::Cubemap(dir) => {
+                let faces = CUBE_FACES
+                    .iter()
+                    .map(|face| load_face(dir, face).map(flip))
+                    .collect::<Result<Vec<_>>>()?;
+                Texture::cube_from_images(device, queue, &faces, Some(&dir.display().to_string()))
+                    .map(Some)
+            }
+            ChannelSource::Noise => {
+                let mut rng = StdRng::seed_from_u64(seed.unwrap_or_else(rand::random));
+                let pixels: Vec<u8> = (0..NOISE_SIZE * NOISE_SIZE * 4)
+                    .map(|_| rng.gen::<u8>())
+                    .collect();
+                let img = image::RgbaImage::from_raw(NOISE_SIZE, NOISE_SIZE, pixels)
+                    .map(image::DynamicImage::ImageRgba8)
+                    .ok_or_else(|| anyhow!("noise buffer has the wrong size"))?;
+                upload(&img, "noise").map(Some)
+            }
+        }
+    }
+}
+
+fn load_face(dir: &Path, face: &str) -> Result<image::DynamicImage> {
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read cubemap `{}`", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.file_stem().and_then(|s| s.to_str()) == Some(face) {
+            return image::open(&path)
+                .with_context(|| format!("failed to load `{}`", path.display()));
+        }
+    }
+    bail!("cubemap `{}` has no `{}` face", dir.display(), face)
+}
+
+/// `iChannelResolution`, one `vec4` per channel for std140 array stride.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChannelUniforms {
+    pub resolution: [[f32; 4]; CHANNEL_COUNT],
+}
+
+/// Group 1 layout: `iChannelN` 2D texture at binding `2N` and its sampler at
+/// `2N + 1`, the cube texture at `CUBE_BINDING + N` and the channel
+/// resolutions at `RESOLUTION_BINDING`. Binding 0/1 keep working for shaders
+/// written against the old `t_diffuse`/`s_diffuse` pair.
+pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &layout_entries(),
+        label: Some("channel_bind_group_layout"),
+    })
+}
+
+/// Entries of the channel bind group layout, group 1 of every pass.
+pub fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+    let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    };
+    let mut entries: Vec<_> = (0..CHANNEL_COUNT as u32)
+        .flat_map(|i| {
+            [
+                texture(i * 2, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: i * 2 + 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ]
+        })
+        .collect();
+    entries.extend(
+        (0..CHANNEL_COUNT as u32).map(|i| texture(CUBE_BINDING + i, wgpu::TextureViewDimension::Cube)),
+    );
+    entries.push(wgpu::BindGroupLayoutEntry {
+        binding: RESOLUTION_BINDING,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    });
+    entries
+}
+
+/// Views bound for one channel: the unused one of the pair is a
+/// placeholder of the right dimension.
+pub struct ChannelViews<'a> {
+    pub view_2d: &'a wgpu::TextureView,
+    pub view_cube: &'a wgpu::TextureView,
+    pub sampler: &'a wgpu::Sampler,
+}
+
+pub fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    channels: &[ChannelViews; CHANNEL_COUNT],
+    resolution: &wgpu::Buffer,
+    label: &str,
+) -> wgpu::BindGroup {
+    let mut entries: Vec<_> = channels
+        .iter()
+        .enumerate()
+        .flat_map(|(i, channel)| {
+            [
+                wgpu::BindGroupEntry {
+                    binding: i as u32 * 2,
+                    resource: wgpu::BindingResource::TextureView(channel.view_2d),
+                },
+                wgpu::BindGroupEntry {
+                    binding: i as u32 * 2 + 1,
+                    resource: wgpu::BindingResource::Sampler(channel.sampler),
+                },
+            ]
+        })
+        .collect();
+    entries.extend(channels.iter().enumerate().map(|(i, channel)| wgpu::BindGroupEntry {
+        binding: CUBE_BINDING + i as u32,
+        resource: wgpu::BindingResource::TextureView(channel.view_cube),
+    }));
+    entries.push(wgpu::BindGroupEntry {
+        binding: RESOLUTION_BINDING,
+        resource: resolution.as_entire_binding(),
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some(label),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources() {
+        let parse = |s| ChannelSource::parse(s).unwrap();
+        assert_eq!(parse("builtin"), ChannelSource::Builtin);
+        assert_eq!(parse("noise"), ChannelSource::Noise);
+        assert_eq!(parse("buffer-b"), ChannelSource::Buffer(1));
+        assert_eq!(parse("D"), ChannelSource::Buffer(3));
+        assert_eq!(parse("storage-2"), ChannelSource::Storage(2));
+        assert_eq!(parse("cubemap:sky"), ChannelSource::Cubemap("sky".into()));
+        assert_eq!(parse("wood.png"), ChannelSource::File("wood.png".into()));
+        assert!(ChannelSource::parse("storage-4").is_err());
+        assert!(ChannelSource::parse("storage-x").is_err());
+    }
+
+    #[test]
+    fn defaults_follow_the_source() {
+        let buffer = ChannelInput::parse("buffer-a").unwrap().sampler;
+        assert_eq!(
+            (buffer.filter, buffer.wrap, buffer.vflip),
+            (Filter::Linear, Wrap::Clamp, false)
+        );
+        let cube = ChannelInput::parse("cubemap:sky").unwrap().sampler;
+        assert_eq!(
+            (cube.filter, cube.wrap, cube.vflip),
+            (Filter::Linear, Wrap::Clamp, false)
+        );
+        let image = ChannelInput::parse("wood.png").unwrap().sampler;
+        assert_eq!(
+            (image.filter, image.wrap, image.vflip),
+            (Filter::Mipmap, Wrap::Repeat, true)
+        );
+    }
+
+    #[test]
+    fn settings_override_the_defaults() {
+        let input = ChannelInput::parse("wood.png,filter=nearest,wrap=mirror,novflip").unwrap();
+        assert_eq!(input.source, ChannelSource::File("wood.png".into()));
+        assert_eq!(input.sampler.filter, Filter::Nearest);
+        assert_eq!(input.sampler.wrap, Wrap::Mirror);
+        assert!(!input.sampler.vflip);
+        let input = ChannelInput::parse("buffer-a,filter=mipmap,wrap=repeat,vflip").unwrap();
+        assert_eq!(input.sampler.filter, Filter::Mipmap);
+        assert_eq!(input.sampler.wrap, Wrap::Repeat);
+        assert!(input.sampler.vflip);
+    }
+
+    #[test]
+    fn unknown_settings() {
+        let err = |s| ChannelInput::parse(s).unwrap_err().to_string();
+        assert_eq!(err("noise,filter=cubic"), "unknown filter `cubic`");
+        assert_eq!(err("noise,wrap=border"), "unknown wrap mode `border`");
+        assert_eq!(err("noise,flip"), "unknown channel setting `flip`");
+        assert_eq!(err("noise,mip=1"), "unknown channel setting `mip=1`");
+    }
+}
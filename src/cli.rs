@@ -0,0 +1,990 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    capture::RawFormat,
+    channel::{parse_buffer_name, ChannelSource},
+    compute::ComputeOptions,
+    deterministic::{self, Deterministic, MouseScript},
+    export::{ExportOptions, Loop, Palette},
+    manifest::{self, Manifest},
+    params::Param,
+    pass::{check_cycles, ChannelBinding, PassId, PassSize, TargetFormat, BUFFER_COUNT},
+    poster::PosterOptions,
+    record::RecordOptions,
+    regression,
+    sampling::{self, Sampling, Upscale},
+    shadertoy::ImportOptions,
+    source::SourceOptions,
+    stoy::ShaderLang,
+    tonemap::Operator,
+};
+
+pub const USAGE: &str = "\
+usage: shader_toy [options] [shader.wgsl | shader.glsl | stoy.toml | <project dir>]
+       shader_toy render [options] [shader] [--frames <a>..<b>] [--out <dir> | --record <file>]
+                         [--raw exr|hdr]
+       shader_toy export [options] [shader] --out <file.gif | file.png> [--duration <s>]
+       shader_toy poster [options] [shader] --size <WxH> --out <file.png | file.tif> [--tile <n>]
+       shader_toy test [options] <dir> [--bless]
+       shader_toy import <shader.json> [--out <dir>]
+       shader_toy export-json [options] [shader] --out <file.json>
+
+A stoy.toml manifest, given directly, through the directory holding it, or
+found in the working directory when no shader is given, describes a whole
+project: [passes.image] and [passes.buffer-a..d] with their shader, format,
+size and channels (sources with filter, wrap and vflip), [compute],
+[uniforms], [window] settings and [presets.<name>] of options, which take
+the values of the options of the same name. The options given override it;
+paths are relative to it. The window rebuilds everything when it changes.
+    --preset <name>              add the options of a preset, e.g. the output of an export
+
+render renders frames a..b (default 0..1) without a window and writes them
+as PNG files to <dir> (default: frames). Earlier frames are rendered but not
+written so buffers reach the same state. With --record the frames are
+encoded into a video instead. --raw writes the image pass before tonemapping
+as OpenEXR or Radiance HDR files, rendering it in rgba16f unless --format
+says otherwise.
+
+export renders <s> seconds (default 2) from --time on at --fps and --size
+and writes an animated GIF or PNG without needing ffmpeg.
+    --palette global|frame       one palette for all frames or one per frame (default: global
+                                 for GIF, full color for PNG)
+    --dither                     Floyd-Steinberg dithering against the palette
+    --loop check|trim            check that the frame after the last matches the first, or
+                                 end before the frame in the second half that matches best
+GIF delays are whole hundredths of a second, so 50 or 25 fps play exactly.
+
+poster renders frame 0 of a single-pass shader at any size, e.g. 30000x20000,
+in tiles of at most <n> pixels (default 2048) and streams them into a PNG or
+TIFF file. GLSL shaders see the whole canvas in fragCoord and iResolution;
+WGSL shaders get it through their texture coordinates or by adding the
+`tile_offset` uniform to the fragment position.
+
+test renders every shader in <dir> at fixed times and compares the images
+with the references in <dir>/references, writing the actual and diff images
+of failures to <dir>/failures. --bless overwrites the references instead.
+    --times <t>,...              times to check (default 0,1)
+    --tolerance <n>              largest difference of a channel (default 2)
+    --max-delta-e <x>            largest mean CIE76 color difference (default 1)
+    --references <dir>, --failures <dir>
+
+import turns a Shadertoy JSON file, as its API or export gives it, into a
+project directory (default: the file without its extension) holding a GLSL
+file for each pass, common.glsl with the Common code and a stoy.toml.
+Textures and cubemaps are copied from Shadertoy's media files, downloaded
+next to the JSON file; keyboard, webcam, sound and the like are left out.
+
+export-json writes the passes in Shadertoy's JSON format, the custom
+uniforms as constants in its Common tab and image files as its media of the
+same name, for Shadertoy or `import`.
+
+options:
+    --size <WxH>                 initial window size (default 800x600)
+    --watch <dir>                directory watched for hot reload (default: the shaders' directories)
+    --time <seconds>             initial value of the time uniform (default 0)
+    --deterministic              render frame N the same on every run, implied by render:
+                                 iTime = frame / fps, frozen iDate, seeded noise
+    --fps <n>                    frame rate of --deterministic (default 60)
+    --date <Y-m-dTH:M:S>         iDate of --deterministic (default 2000-01-01T00:00:00)
+    --mouse-script <file>        iMouse of --deterministic, lines of `<frame> <x> <y> [down|up]`
+                                 from the bottom-left corner
+    --seed <n>                   noise seed of --deterministic (default 0)
+    --record <file>              encode the frames into .mp4, .mov, .mkv, .webm or .gif with
+                                 ffmpeg, implies --deterministic; without render the window
+                                 session is recorded until it is closed
+    --codec <name>               ffmpeg encoder (default libx264, libvpx-vp9 for .webm)
+    --crf <n>                    constant rate factor (default 18, 32 for .webm)
+    --pix-fmt <fmt>              output pixel format (default yuv420p)
+    --screenshot-dir <dir>       where F12 saves screenshots (default: screenshots)
+    --screenshot-size <WxH>      screenshot resolution (default: the window size)
+    --buffer-<a-d> <file>        shader of an offscreen buffer pass
+    --buffer-size <buffer>=<WxH | scale>
+                                 fixed size of a buffer, or a fraction of the render size; its
+                                 iResolution and iMouse follow
+    --include <dir>              directory searched for `#include \"file\"` after the including
+                                 file's, may be repeated
+    --common <file>              code in front of every shader of its language, like
+                                 Shadertoy's Common tab
+    --uniform <name>=<value>     custom uniform: a float with a `.`, an int, true or false, or
+                                 2 to 4 comma separated floats for a vec2..vec4; GLSL reads it
+                                 by name, WGSL from a struct of them in declaration order at
+                                 @group(3) @binding(12), bools as u32. A shader line
+                                 `// @param <name> <type> [<min>..<max>] [= <value>]` declares
+                                 one too and shows it on the parameter panel, types float, int,
+                                 bool, vec2..vec4 and color
+    --compute <file.wgsl>        compute shader: its @compute entry points run in order before
+                                 the buffers every frame, `init` only on the first frame and
+                                 after a reset; @group(2) @binding(0) is the same uniforms
+    --dispatch <entry>=<x>[x<y>[x<z>]]
+                                 invocations of an entry point, rounded up to whole workgroups
+                                 (default: one per pixel of the render size)
+    --storage-buffer <n>=<bytes> read-write storage buffer 0..3 of the compute shader at
+                                 @group(3) @binding(n), zeroed on start and reset
+    --storage-texture <n>[=<format>]
+                                 storage texture 0..3 at the render size: written by the compute
+                                 shader as texture_storage_2d<format, write> at @group(3)
+                                 @binding(4 + n), starting from the previous frame, which is
+                                 also readable at @binding(8 + n); render passes sample it as
+                                 channel source storage-n. formats: rgba8, rgba16f (default),
+                                 rgba32f
+    --channel <pass>:<n>=<src>[,<setting>...]
+                                 feed `src` into iChannel<n> of `pass`, e.g. image:0=buffer-a
+                                 passes: image, buffer-a..d
+                                 sources: builtin, buffer-a..d, storage-0..3, noise,
+                                          cubemap:<dir>, <image file>
+                                 settings: filter=nearest|linear|mipmap, wrap=clamp|repeat|mirror,
+                                           vflip, novflip
+    --format <pass>=<format>     target format of a pass: rgba8 (default), rgba16f or rgba32f
+                                 passes: image, buffer-a..d, buffers, all
+    --tonemap none|reinhard|aces tonemapping of a floating point image pass onto the display
+                                 (default none), implies --format image=rgba16f
+    --exposure <stops>           brightness before tonemapping, implies --format image=rgba16f
+    --render-scale <x>           render every pass at x times the size and filter the image to
+                                 it: 2 or 4 anti-alias edges drawn by the shader, which
+                                 multisampling can't since each pass is a single quad, and 0.5
+                                 renders a quarter of the pixels; iResolution is the render size
+    --upscale bilinear|nearest   filter of an image rendered smaller than the window
+                                 (default bilinear)
+    --target-frame-time <ms>     window only: lower the resolution of the image pass while it
+                                 takes longer on the GPU, or the frame interval without GPU
+                                 timestamps; buffers keep theirs so their contents survive
+    --accumulate <n>             render and export only: average n renders of the image pass
+                                 per frame, shifted within the pixel; buffers run once per frame
+    --motion-blur <shutter>      render and export only: spread the samples over this part of
+                                 the frame interval, 0 to 1 (default 16 samples)
+    --hud                        window only: show frame and per-pass GPU times, toggled with F3
+    --profile <file.csv>         window and render only: write the frame, CPU and per-pass GPU
+                                 times of every frame and print their means at the end; without
+                                 GPU timestamps each pass is waited for, which slows the frame
+    -h, --help                   print this message
+
+keys:
+    space                        pause / resume
+    left, right                  step one frame backwards / forwards
+    page up, page down           seek 5 seconds forwards / backwards
+    home                         seek to 0
+    up, down                     speed +/- 0.25, below 0 runs backwards
+    1                            speed 1
+    -                            reverse
+    r                            reset time and frame, clear the buffers
+    f2                           toggle the parameter panel
+    f3                           toggle the timing HUD
+    f12                          save a screenshot";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Shader loaded on start and re-read on every reload. `None` runs the
+    /// built-in shader without hot reload.
+    pub shader: Option<PathBuf>,
+    /// Shaders of Buffer A–D.
+    pub buffers: [Option<PathBuf>; BUFFER_COUNT],
+    /// Sizes of Buffer A–D, the render size if `None`.
+    pub buffer_sizes: [Option<PassSize>; BUFFER_COUNT],
+    pub compute: ComputeOptions,
+    pub channels: Vec<ChannelBinding>,
+    /// Custom uniforms in declaration order.
+    pub uniforms: Vec<Param>,
+    /// Include directories and Common code of every shader.
+    pub sources: SourceOptions,
+    /// Target formats by pass, later entries win.
+    pub formats: Vec<(PassId, TargetFormat)>,
+    pub tonemap: Operator,
+    /// Stops of exposure before tonemapping.
+    pub exposure: f32,
+    pub sampling: Sampling,
+    pub width: u32,
+    pub height: u32,
+    pub watch_dir: Option<PathBuf>,
+    pub start_time: f32,
+    pub screenshot_dir: PathBuf,
+    /// `None` takes screenshots at the window size.
+    pub screenshot_size: Option<(u32, u32)>,
+    /// Set by the `render` command, which runs without a window.
+    pub render: Option<RenderOptions>,
+    /// Set by the `poster` command.
+    pub poster: Option<PosterOptions>,
+    /// Set by the `export` command.
+    pub export: Option<ExportOptions>,
+    /// Set by the `test` command.
+    pub test: Option<regression::Config>,
+    /// Set by the `import` command.
+    pub import: Option<ImportOptions>,
+    /// Set by the `export-json` command, the file written.
+    pub export_json: Option<PathBuf>,
+    /// Reload shaders when they change.
+    pub watch: bool,
+    pub deterministic: Option<Deterministic>,
+    /// Encode the rendered frames into a video.
+    pub record: Option<RecordOptions>,
+    /// Start with the timing HUD shown.
+    pub hud: bool,
+    /// Write the timings of every frame to this CSV file.
+    pub profile: Option<PathBuf>,
+    /// The project manifest the options were read from, if any.
+    pub manifest: Option<PathBuf>,
+    /// Arguments the options were parsed from, parsed again when the
+    /// manifest changes.
+    pub args: Vec<String>,
+}
+
+/// What `parse_flags` leaves for `validate`.
+#[derive(Debug, Default)]
+struct Flags {
+    references: Option<PathBuf>,
+    failures: Option<PathBuf>,
+    /// Whether `--out` was given.
+    out: bool,
+    /// An encoder option, which needs `--record`.
+    encoder: Option<String>,
+    /// Whether an option needs a floating point image pass.
+    hdr: bool,
+    accumulate: Option<u32>,
+    preset: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Frames written, counted from 0.
+    pub frames: Range<u64>,
+    pub out: PathBuf,
+    /// Write the image pass before tonemapping instead of PNG files.
+    pub raw: Option<RawFormat>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            frames: 0..1,
+            out: PathBuf::from("frames"),
+            raw: None,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            shader: None,
+            buffers: Default::default(),
+            buffer_sizes: Default::default(),
+            compute: ComputeOptions::default(),
+            channels: Vec::new(),
+            uniforms: Vec::new(),
+            sources: SourceOptions::default(),
+            formats: Vec::new(),
+            tonemap: Operator::None,
+            exposure: 0.0,
+            sampling: Sampling::default(),
+            width: 800,
+            height: 600,
+            watch_dir: None,
+            start_time: 0.0,
+            screenshot_dir: PathBuf::from("screenshots"),
+            screenshot_size: None,
+            render: None,
+            poster: None,
+            export: None,
+            test: None,
+            import: None,
+            export_json: None,
+            watch: true,
+            deterministic: None,
+            record: None,
+            hud: false,
+            profile: None,
+            manifest: None,
+            args: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the process arguments, printing the usage and exiting on error.
+    pub fn from_env() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("error: {}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Returns `Ok(None)` when help was requested.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>> {
+        let args: Vec<String> = args.into_iter().collect();
+        let given = Self::for_command(&args);
+        let Some((mut options, mut flags)) = Self::parse_flags(&args, given, Flags::default())?
+        else {
+            return Ok(None);
+        };
+        let manifest = options.manifest.clone().or_else(|| {
+            let implicit = options.shaders().next().is_none()
+                && options.test.is_none()
+                && options.import.is_none();
+            let path = Path::new(manifest::FILE_NAME);
+            (implicit && path.is_file()).then(|| path.to_path_buf())
+        });
+        if let Some(path) = manifest {
+            // the options given go on top of the manifest's
+            let mut project = Self::for_command(&args);
+            let mut project_flags = Flags::default();
+            project
+                .apply(
+                    &mut project_flags,
+                    &Manifest::load(&path)?,
+                    flags.preset.as_deref(),
+                )
+                .map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+            (options, flags) =
+                Self::parse_flags(&args, project, project_flags)?.expect("help was handled before");
+            options.manifest = Some(path);
+        } else if flags.preset.is_some() {
+            bail!("--preset needs a manifest, pass a stoy.toml or a directory holding one");
+        }
+        options.args = args;
+        options.validate(flags).map(Some)
+    }
+
+    /// The defaults of the command `args` start with, if any.
+    fn for_command(args: &[String]) -> Self {
+        let mut options = Self::default();
+        match args.first().map(String::as_str) {
+            Some("render") => {
+                options.render = Some(RenderOptions::default());
+                options.watch = false;
+            }
+            Some("poster") => {
+                options.poster = Some(PosterOptions::default());
+                options.watch = false;
+            }
+            Some("export") => {
+                options.export = Some(ExportOptions::default());
+                options.watch = false;
+            }
+            Some("test") => {
+                options.test = Some(regression::Config::new(PathBuf::new()));
+                options.watch = false;
+            }
+            Some("import") => {
+                options.import = Some(ImportOptions::default());
+                options.watch = false;
+            }
+            Some("export-json") => {
+                options.export_json = Some(PathBuf::new());
+                options.watch = false;
+            }
+            _ => (),
+        }
+        options
+    }
+
+    /// Whether a command was given, rather than the window.
+    fn command(&self) -> bool {
+        self.render.is_some()
+            || self.poster.is_some()
+            || self.export.is_some()
+            || self.test.is_some()
+            || self.import.is_some()
+            || self.export_json.is_some()
+    }
+
+    /// Reads every option in `args` on top of `options`, leaving the checks
+    /// that need all of them to `validate`.
+    fn parse_flags(
+        args: &[String],
+        mut options: Self,
+        mut flags: Flags,
+    ) -> Result<Option<(Self, Flags)>> {
+        let mut args = args.iter().skip(usize::from(options.command())).cloned();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                flag if flag.starts_with('-') => {
+                    let mut value = |name: &str| {
+                        args.next()
+                            .ok_or_else(|| anyhow!("missing value for `{}`", name))
+                    };
+                    options.set(&mut flags, flag, &mut value)?;
+                }
+                path if options.test.is_some() => {
+                    let test = options.test.as_mut().unwrap();
+                    if !test.shaders.as_os_str().is_empty() {
+                        bail!("more than one directory given");
+                    }
+                    test.shaders = PathBuf::from(path);
+                }
+                path if options.import.is_some() => {
+                    let import = options.import.as_mut().unwrap();
+                    if !import.json.as_os_str().is_empty() {
+                        bail!("more than one file given");
+                    }
+                    import.json = PathBuf::from(path);
+                }
+                path if is_manifest(Path::new(path)) => {
+                    if options.manifest.is_some() {
+                        bail!("more than one manifest given");
+                    }
+                    options.manifest = Some(manifest::locate(Path::new(path))?);
+                }
+                path => {
+                    if options.shader.is_some() {
+                        bail!("more than one shader given");
+                    }
+                    options.shader = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        Ok(Some((options, flags)))
+    }
+
+    /// Takes the passes, compute shader, uniforms and sources of `manifest`,
+    /// then applies its `[window]` settings and those of `preset`.
+    fn apply(
+        &mut self,
+        flags: &mut Flags,
+        manifest: &Manifest,
+        preset: Option<&str>,
+    ) -> Result<()> {
+        self.sources = manifest.sources.clone();
+        self.shader = manifest.shader.clone();
+        self.buffers = manifest.buffers.clone();
+        self.buffer_sizes = manifest.buffer_sizes;
+        self.formats = manifest.formats.clone();
+        self.channels = manifest.channels.clone();
+        self.compute = manifest.compute.clone();
+        self.uniforms = manifest.uniforms.clone();
+        for setting in manifest.settings(preset)? {
+            let mut taken = false;
+            let mut value = |_: &str| {
+                taken = true;
+                setting
+                    .value
+                    .clone()
+                    .ok_or_else(|| anyhow!("expected a value, not true"))
+            };
+            let flag = format!("--{}", setting.option);
+            self.set(flags, &flag, &mut value)
+                .map_err(|err| anyhow!("{}: {}", setting.at, err))?;
+            if setting.value.is_some() && !taken {
+                bail!("{}: expected true or false", setting.at);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the option `arg`, taking its value from `value` if it has
+    /// one. Shared by the command line and the settings of a manifest.
+    fn set(
+        &mut self,
+        flags: &mut Flags,
+        arg: &str,
+        value: &mut dyn FnMut(&str) -> Result<String>,
+    ) -> Result<()> {
+        let options = self;
+        match arg {
+            "--size" => {
+                let size = parse_size(&value("--size")?)?;
+                match &mut options.test {
+                    Some(test) => (test.width, test.height) = size,
+                    None => (options.width, options.height) = size,
+                }
+            }
+            "--watch" => options.watch_dir = Some(PathBuf::from(value("--watch")?)),
+            "--time" => {
+                let v = value("--time")?;
+                options.start_time = v
+                    .parse()
+                    .map_err(|_| anyhow!("invalid time `{}`", v))?;
+            }
+            "--screenshot-dir" => {
+                options.screenshot_dir = PathBuf::from(value("--screenshot-dir")?)
+            }
+            "--screenshot-size" => {
+                options.screenshot_size = Some(parse_size(&value("--screenshot-size")?)?);
+            }
+            "--out" if options.poster.is_some() => {
+                options.poster.as_mut().unwrap().out = PathBuf::from(value("--out")?);
+                flags.out = true;
+            }
+            "--tile" if options.poster.is_none() => {
+                bail!("`{}` is only valid for `shader_toy poster`", arg)
+            }
+            "--tile" => {
+                let v = value("--tile")?;
+                let tile: u32 = v.parse().map_err(|_| anyhow!("invalid tile size `{}`", v))?;
+                if tile == 0 {
+                    bail!("tile size must be non-zero");
+                }
+                options.poster.as_mut().unwrap().tile = tile;
+            }
+            "--out" if options.import.is_some() => {
+                options.import.as_mut().unwrap().out = Some(PathBuf::from(value("--out")?));
+            }
+            "--out" if options.export_json.is_some() => {
+                options.export_json = Some(PathBuf::from(value("--out")?));
+                flags.out = true;
+            }
+            "--out" if options.export.is_some() => {
+                options.export.as_mut().unwrap().out = PathBuf::from(value("--out")?);
+                flags.out = true;
+            }
+            "--frames" | "--out" if options.render.is_none() => {
+                bail!("`{}` is only valid for `shader_toy render`", arg)
+            }
+            "--frames" => {
+                let frames = parse_frames(&value("--frames")?)?;
+                options.render.as_mut().unwrap().frames = frames;
+            }
+            "--out" => {
+                options.render.as_mut().unwrap().out = PathBuf::from(value("--out")?);
+                flags.out = true;
+            }
+            "--record" => {
+                let path = PathBuf::from(value("--record")?);
+                options.record.get_or_insert_with(Default::default).path = path;
+            }
+            "--codec" => {
+                let codec = value("--codec")?;
+                flags.encoder = Some(arg.to_string());
+                options.record.get_or_insert_with(Default::default).codec = Some(codec);
+            }
+            "--crf" => {
+                let v = value("--crf")?;
+                let crf = v.parse().map_err(|_| anyhow!("invalid crf `{}`", v))?;
+                flags.encoder = Some(arg.to_string());
+                options.record.get_or_insert_with(Default::default).crf = Some(crf);
+            }
+            "--pix-fmt" => {
+                let format = value("--pix-fmt")?;
+                flags.encoder = Some(arg.to_string());
+                options.record.get_or_insert_with(Default::default).pixel_format =
+                    Some(format);
+            }
+            "--duration" | "--palette" | "--dither" | "--loop" if options.export.is_none() => {
+                bail!("`{}` is only valid for `shader_toy export`", arg)
+            }
+            "--duration" => {
+                let v = value("--duration")?;
+                let duration: f32 =
+                    v.parse().map_err(|_| anyhow!("invalid duration `{}`", v))?;
+                if !duration.is_finite() || duration <= 0.0 {
+                    bail!("duration must be positive");
+                }
+                options.export.as_mut().unwrap().duration = duration;
+            }
+            "--palette" => {
+                let palette = Palette::parse(&value("--palette")?)?;
+                options.export.as_mut().unwrap().palette = Some(palette);
+            }
+            "--dither" => options.export.as_mut().unwrap().dither = true,
+            "--loop" => {
+                let seamless = Loop::parse(&value("--loop")?)?;
+                options.export.as_mut().unwrap().seamless = Some(seamless);
+            }
+            "--times" | "--tolerance" | "--max-delta-e" | "--references" | "--failures"
+            | "--bless"
+                if options.test.is_none() =>
+            {
+                bail!("`{}` is only valid for `shader_toy test`", arg)
+            }
+            "--times" => {
+                let v = value("--times")?;
+                options.test.as_mut().unwrap().times = v
+                    .split(',')
+                    .map(|t| t.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| anyhow!("invalid times `{}`", v))?;
+            }
+            "--tolerance" => {
+                let v = value("--tolerance")?;
+                options.test.as_mut().unwrap().tolerance = v
+                    .parse()
+                    .map_err(|_| anyhow!("invalid tolerance `{}`", v))?;
+            }
+            "--max-delta-e" => {
+                let v = value("--max-delta-e")?;
+                options.test.as_mut().unwrap().max_delta_e = v
+                    .parse()
+                    .map_err(|_| anyhow!("invalid color difference `{}`", v))?;
+            }
+            "--references" => flags.references = Some(PathBuf::from(value("--references")?)),
+            "--failures" => flags.failures = Some(PathBuf::from(value("--failures")?)),
+            "--bless" => options.test.as_mut().unwrap().bless = true,
+            "--deterministic" => {
+                options.deterministic.get_or_insert_with(Default::default);
+            }
+            "--fps" => {
+                let v = value("--fps")?;
+                let fps: f64 = v.parse().map_err(|_| anyhow!("invalid fps `{}`", v))?;
+                if !fps.is_finite() || fps <= 0.0 {
+                    bail!("fps must be positive");
+                }
+                match &mut options.test {
+                    Some(test) => test.fps = fps,
+                    None => options.deterministic.get_or_insert_with(Default::default).fps = fps,
+                }
+            }
+            "--date" => {
+                let date = deterministic::parse_date(&value("--date")?)?;
+                options.deterministic.get_or_insert_with(Default::default).date = date;
+            }
+            "--mouse-script" => {
+                let script = MouseScript::load(Path::new(&value("--mouse-script")?))?;
+                options.deterministic.get_or_insert_with(Default::default).mouse = Some(script);
+            }
+            "--seed" => {
+                let v = value("--seed")?;
+                let seed = v.parse().map_err(|_| anyhow!("invalid seed `{}`", v))?;
+                options.deterministic.get_or_insert_with(Default::default).seed = seed;
+            }
+            "--channel" => options.channels.push(ChannelBinding::parse(&value("--channel")?)?),
+            "--compute" => options.compute.shader = Some(PathBuf::from(value("--compute")?)),
+            "--dispatch" => {
+                let dispatch = ComputeOptions::parse_dispatch(&value("--dispatch")?)?;
+                options.compute.dispatch.push(dispatch);
+            }
+            "--storage-buffer" => {
+                let (n, size) = ComputeOptions::parse_buffer(&value("--storage-buffer")?)?;
+                options.compute.buffers[n] = Some(size);
+            }
+            "--storage-texture" => {
+                let (n, format) = ComputeOptions::parse_texture(&value("--storage-texture")?)?;
+                options.compute.textures[n] = Some(format);
+            }
+            "--format" => {
+                let v = value("--format")?;
+                let (pass, format) = v
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("invalid format `{}`, expected <pass>=<format>", v))?;
+                let format = TargetFormat::parse(format)?;
+                let buffers = (0..BUFFER_COUNT).map(PassId::Buffer);
+                match pass {
+                    "buffers" => options.formats.extend(buffers.map(|id| (id, format))),
+                    "all" => options.formats.extend(
+                        buffers.chain([PassId::Image]).map(|id| (id, format)),
+                    ),
+                    pass => options.formats.push((PassId::parse(pass)?, format)),
+                }
+            }
+            "--tonemap" => {
+                options.tonemap = Operator::parse(&value("--tonemap")?)?;
+                flags.hdr = true;
+            }
+            "--exposure" => {
+                let v = value("--exposure")?;
+                options.exposure = v
+                    .parse()
+                    .map_err(|_| anyhow!("invalid exposure `{}`", v))?;
+                flags.hdr = true;
+            }
+            "--render-scale" => {
+                options.sampling.scale = Sampling::parse_scale(&value("--render-scale")?)?;
+            }
+            "--upscale" => options.sampling.upscale = Upscale::parse(&value("--upscale")?)?,
+            "--target-frame-time" => {
+                let v = value("--target-frame-time")?;
+                let ms: f32 = v.parse().map_err(|_| anyhow!("invalid frame time `{}`", v))?;
+                if !ms.is_finite() || ms <= 0.0 {
+                    bail!("frame time must be positive");
+                }
+                options.sampling.frame_time = Some(ms / 1000.0);
+            }
+            "--accumulate" | "--motion-blur"
+                if options.render.is_none() && options.export.is_none() =>
+            {
+                bail!("`{}` is only valid for `shader_toy render` and `shader_toy export`", arg)
+            }
+            "--accumulate" => {
+                let v = value("--accumulate")?;
+                let samples: u32 = v.parse().map_err(|_| anyhow!("invalid samples `{}`", v))?;
+                if samples == 0 {
+                    bail!("samples must be non-zero");
+                }
+                flags.accumulate = Some(samples);
+            }
+            "--motion-blur" => {
+                let v = value("--motion-blur")?;
+                let shutter: f32 = v.parse().map_err(|_| anyhow!("invalid shutter `{}`", v))?;
+                if !(0.0..=1.0).contains(&shutter) {
+                    bail!("shutter must be between 0 and 1");
+                }
+                options.sampling.shutter = shutter;
+            }
+            "--preset" => flags.preset = Some(value("--preset")?),
+            "--uniform" => {
+                let param = Param::parse(&value("--uniform")?)?;
+                match options.uniforms.iter_mut().find(|p| p.name == param.name) {
+                    Some(old) => *old = param,
+                    None => options.uniforms.push(param),
+                }
+            }
+            "--include" => options
+                .sources
+                .include_dirs
+                .push(PathBuf::from(value("--include")?)),
+            "--common" => options.sources.common = Some(PathBuf::from(value("--common")?)),
+            "--buffer-size" => {
+                let v = value("--buffer-size")?;
+                let (buffer, size) = v.split_once('=').ok_or_else(|| {
+                    anyhow!("invalid buffer size `{}`, expected <buffer>=<WxH | scale>", v)
+                })?;
+                let index = parse_buffer_name(buffer)
+                    .ok_or_else(|| anyhow!("unknown buffer `{}`", buffer))?;
+                options.buffer_sizes[index] = Some(PassSize::parse(size)?);
+            }
+            "--hud" => options.hud = true,
+            "--profile" => options.profile = Some(PathBuf::from(value("--profile")?)),
+            "--raw" if options.render.is_none() => {
+                bail!("`{}` is only valid for `shader_toy render`", arg)
+            }
+            "--raw" => {
+                let raw = RawFormat::parse(&value("--raw")?)?;
+                options.render.as_mut().unwrap().raw = Some(raw);
+                flags.hdr = true;
+            }
+            flag if flag.starts_with("--buffer-") => {
+                let index = parse_buffer_name(&flag[2..])
+                    .ok_or_else(|| anyhow!("unknown option `{}`", flag))?;
+                options.buffers[index] = Some(PathBuf::from(value(flag)?));
+            }
+            _ => bail!("unknown option `{}`", arg),
+        }
+        Ok(())
+    }
+
+    /// Checks the options read by `parse_flags` against each other and
+    /// fills in what they imply.
+    fn validate(mut self, flags: Flags) -> Result<Self> {
+        let options = &mut self;
+        let Flags {
+            references,
+            failures,
+            out,
+            encoder,
+            hdr,
+            accumulate,
+            ..
+        } = flags;
+        if let Some(record) = &options.record {
+            if record.path.as_os_str().is_empty() {
+                bail!("`{}` needs --record", encoder.unwrap_or_default());
+            }
+            if out {
+                bail!("--out and --record cannot be combined");
+            }
+            if options.test.is_some()
+                || options.export.is_some()
+                || options.poster.is_some()
+                || options.export_json.is_some()
+            {
+                bail!("--record is only valid for `shader_toy render` and the window");
+            }
+        }
+        if options.poster.is_some() && !out {
+            bail!("no output given, pass --out <file.png | file.tif>");
+        }
+        if let Some(export) = &options.export {
+            if !out {
+                bail!("no output given, pass --out <file.gif | file.png>");
+            }
+            if export.dither && export.palette.is_none() && !is_gif(&export.out) {
+                bail!("--dither needs a palette, pass --palette global");
+            }
+        }
+        options.sampling.samples = match accumulate {
+            Some(samples) => samples,
+            None if options.sampling.shutter > 0.0 => sampling::MOTION_BLUR_SAMPLES,
+            None => 1,
+        };
+        if options.sampling.frame_time.is_some()
+            && (options.render.is_some()
+                || options.poster.is_some()
+                || options.export.is_some()
+                || options.test.is_some())
+        {
+            bail!("--target-frame-time is only valid for the window, it would change the frames");
+        }
+        let headless = options.render.is_some()
+            || options.poster.is_some()
+            || options.export.is_some()
+            || options.test.is_some()
+            || options.export_json.is_some();
+        if options.hud && headless {
+            bail!("--hud is only valid for the window");
+        }
+        if options.profile.is_some() && headless && options.render.is_none() {
+            bail!("--profile is only valid for `shader_toy render` and the window");
+        }
+        match &options.compute.shader {
+            Some(shader) if ShaderLang::from_path(shader) != ShaderLang::Wgsl => {
+                bail!("compute shaders must be WGSL, `{}` is not", shader.display());
+            }
+            Some(_) => (),
+            None if options.compute.declares_storage() || !options.compute.dispatch.is_empty() => {
+                bail!("--dispatch, --storage-buffer and --storage-texture need --compute");
+            }
+            None => (),
+        }
+        let mut reads: [Vec<usize>; BUFFER_COUNT] = Default::default();
+        for binding in &options.channels {
+            match binding.input.source {
+                ChannelSource::Storage(n) if options.compute.textures[n].is_none() => {
+                    bail!("storage-{} is not declared, pass --storage-texture {}", n, n);
+                }
+                ChannelSource::Buffer(b) if options.buffers[b].is_none() => {
+                    bail!(
+                        "{} reads {}, which has no shader",
+                        binding.pass.name(),
+                        PassId::Buffer(b).name()
+                    );
+                }
+                _ => (),
+            }
+            // a pass without a shader ignores its channels, see `Stoy::new`
+            match (binding.pass, &binding.input.source) {
+                (PassId::Buffer(p), ChannelSource::Buffer(b)) if options.buffers[p].is_some() => {
+                    reads[p].push(*b);
+                }
+                _ => (),
+            }
+        }
+        check_cycles(&reads)?;
+        if options.poster.is_some() && options.sampling.scale != 1.0 {
+            bail!("a poster renders at its full size, pass a larger --size instead of --render-scale");
+        }
+        if hdr && options.format(PassId::Image) == TargetFormat::Rgba8 {
+            options.formats.push((PassId::Image, TargetFormat::Rgba16Float));
+        }
+        if options.render.as_ref().is_some_and(|r| r.raw.is_some()) && options.record.is_some() {
+            bail!("--raw and --record cannot be combined");
+        }
+        if options.render.is_some()
+            || options.poster.is_some()
+            || options.export.is_some()
+            || options.record.is_some()
+        {
+            options.deterministic.get_or_insert_with(Default::default);
+        }
+        if options.export_json.is_some() && !out {
+            bail!("no output given, pass --out <file.json>");
+        }
+        if let Some(import) = &options.import {
+            if import.json.as_os_str().is_empty() {
+                bail!("no Shadertoy JSON file given");
+            }
+        }
+        if let Some(test) = &mut options.test {
+            if test.shaders.as_os_str().is_empty() {
+                bail!("no shader directory given");
+            }
+            test.references = references.unwrap_or_else(|| test.shaders.join("references"));
+            test.failures = failures.unwrap_or_else(|| test.shaders.join("failures"));
+        }
+
+        for shader in options.shaders() {
+            if !shader.is_file() {
+                bail!("shader `{}` does not exist", shader.display());
+            }
+        }
+        for dir in &options.sources.include_dirs {
+            if !dir.is_dir() {
+                bail!("include directory `{}` does not exist", dir.display());
+            }
+        }
+        if let Some(common) = &options.sources.common {
+            if !common.is_file() {
+                bail!("common file `{}` does not exist", common.display());
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Target format of `pass`, 8 bits unless `--format` says otherwise.
+    pub fn format(&self, pass: PassId) -> TargetFormat {
+        self.formats
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == pass)
+            .map_or(TargetFormat::Rgba8, |(_, format)| *format)
+    }
+
+    /// Every shader file given, image first.
+    pub fn shaders(&self) -> impl Iterator<Item = &PathBuf> {
+        self.shader
+            .iter()
+            .chain(self.buffers.iter().flatten())
+            .chain(self.compute.shader.iter())
+    }
+
+    /// Directories to watch for changes, empty if hot reload is disabled.
+    pub fn watch_dirs(&self) -> Vec<PathBuf> {
+        if !self.watch {
+            return Vec::new();
+        }
+        if let Some(dir) = &self.watch_dir {
+            return vec![dir.clone()];
+        }
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        let files = self.shaders().chain(&self.sources.common).chain(&self.manifest);
+        for shader in files {
+            let dir = match shader.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+}
+
+/// A positional argument naming a manifest rather than a shader.
+fn is_manifest(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+}
+
+pub fn parse_size(s: &str) -> Result<(u32, u32)> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| anyhow!("invalid size `{}`, expected <W>x<H>", s))?;
+    let w: u32 = w.parse().map_err(|_| anyhow!("invalid width in `{}`", s))?;
+    let h: u32 = h.parse().map_err(|_| anyhow!("invalid height in `{}`", s))?;
+    if w == 0 || h == 0 {
+        bail!("size `{}` must be non-zero", s);
+    }
+    Ok((w, h))
+}
+
+/// `<a>..<b>`, or `<n>` for `0..<n>`.
+pub fn parse_frames(s: &str) -> Result<Range<u64>> {
+    let err = || anyhow!("invalid frames `{}`, expected <a>..<b>", s);
+    let (start, end) = match s.split_once("..") {
+        Some((start, end)) => (start.parse().map_err(|_| err())?, end.parse().map_err(|_| err())?),
+        None => (0, s.parse().map_err(|_| err())?),
+    };
+    if start >= end {
+        bail!("frames `{}` are empty", s);
+    }
+    Ok(start..end)
+}
@@ -0,0 +1,570 @@
+//! Compute passes: the `@compute` entry points of a WGSL file, dispatched
+//! before the buffers every frame, and the storage buffers and textures
+//! they keep across frames. Render passes read the storage textures through
+//! their channels.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+use wgpu::naga;
+
+use crate::{
+    layout,
+    params::{self, Param, Params},
+    pass::TargetFormat,
+    source::{Diagnostic, ShaderSource},
+    texture::Texture,
+    uniform::{self, Uniform},
+    uniforms::uniforms::MainUniforms,
+};
+
+pub const STORAGE_BUFFER_COUNT: usize = 4;
+pub const STORAGE_TEXTURE_COUNT: usize = 4;
+/// Group of the storage bindings, after the ones shared with the render
+/// passes.
+pub const STORAGE_GROUP: u32 = 3;
+/// Binding of the first storage texture in `STORAGE_GROUP`, after the
+/// buffers at bindings `0..STORAGE_BUFFER_COUNT`.
+pub const STORAGE_TEXTURE_BINDING: u32 = STORAGE_BUFFER_COUNT as u32;
+/// Binding of the previous frame of the first storage texture, readable
+/// with `textureLoad`.
+pub const PREVIOUS_BINDING: u32 = STORAGE_TEXTURE_BINDING + STORAGE_TEXTURE_COUNT as u32;
+/// Entry point run on the first frame and after a reset only.
+pub const INIT_ENTRY_POINT: &str = "init";
+
+/// `--compute` and the storage it uses.
+#[derive(Debug, Clone, Default)]
+pub struct ComputeOptions {
+    pub shader: Option<PathBuf>,
+    /// Invocations of entry points by name, one per pixel of the render
+    /// size for the others.
+    pub dispatch: Vec<(String, [u32; 3])>,
+    /// Sizes of the storage buffers in bytes.
+    pub buffers: [Option<u64>; STORAGE_BUFFER_COUNT],
+    pub textures: [Option<TargetFormat>; STORAGE_TEXTURE_COUNT],
+}
+
+impl ComputeOptions {
+    /// `<entry>=<x>[x<y>[x<z>]]`
+    pub fn parse_dispatch(s: &str) -> Result<(String, [u32; 3])> {
+        let err = || anyhow!("invalid dispatch `{}`, expected <entry>=<x>[x<y>[x<z>]]", s);
+        let (entry, size) = s.split_once('=').ok_or_else(err)?;
+        let mut invocations = [1; 3];
+        let parts: Vec<_> = size.split(['x', 'X']).collect();
+        if entry.is_empty() || parts.len() > 3 {
+            return Err(err());
+        }
+        for (v, part) in invocations.iter_mut().zip(parts) {
+            *v = part.parse().map_err(|_| err())?;
+        }
+        Ok((entry.to_string(), invocations))
+    }
+
+    /// `<n>=<bytes>`
+    pub fn parse_buffer(s: &str) -> Result<(usize, u64)> {
+        let err = || anyhow!("invalid storage buffer `{}`, expected <n>=<bytes>", s);
+        let (index, size) = s.split_once('=').ok_or_else(err)?;
+        let index = parse_index(index, STORAGE_BUFFER_COUNT, "storage buffer")?;
+        let size: u64 = size.parse().map_err(|_| err())?;
+        if size == 0 || !size.is_multiple_of(4) {
+            bail!("storage buffer size must be a non-zero multiple of 4 bytes");
+        }
+        Ok((index, size))
+    }
+
+    /// `<n>[=<format>]`
+    pub fn parse_texture(s: &str) -> Result<(usize, TargetFormat)> {
+        let (index, format) = match s.split_once('=') {
+            Some((index, format)) => (index, TargetFormat::parse(format)?),
+            None => (s, TargetFormat::Rgba16Float),
+        };
+        Ok((parse_index(index, STORAGE_TEXTURE_COUNT, "storage texture")?, format))
+    }
+
+    pub fn declares_storage(&self) -> bool {
+        self.buffers.iter().any(Option::is_some) || self.textures.iter().any(Option::is_some)
+    }
+}
+
+fn parse_index(s: &str, count: usize, what: &str) -> Result<usize> {
+    match s.parse() {
+        Ok(index) if index < count => Ok(index),
+        _ => Err(anyhow!("invalid {} `{}`, expected 0..{}", what, s, count - 1)),
+    }
+}
+
+struct EntryPoint {
+    name: String,
+    workgroup_size: [u32; 3],
+    pipeline: wgpu::ComputePipeline,
+}
+
+pub struct Compute {
+    pub shader_path: PathBuf,
+    /// Source of the current pipelines, used to skip unchanged files on
+    /// reload.
+    pub source: ShaderSource,
+    /// Empty until the shader loads.
+    entry_points: Vec<EntryPoint>,
+    dispatch: Vec<(String, [u32; 3])>,
+    buffers: Vec<(u32, wgpu::Buffer)>,
+    /// Ping-pong storage textures like the buffer targets: frame `f` writes
+    /// `textures[n][f % 2]`, which starts as a copy of the other one.
+    textures: [Option<[Texture; 2]>; STORAGE_TEXTURE_COUNT],
+    formats: [Option<wgpu::TextureFormat>; STORAGE_TEXTURE_COUNT],
+    /// Custom uniforms the shader is checked against, bound with the
+    /// storage.
+    params: Vec<Param>,
+    size: (u32, u32),
+    layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// Bound to groups 0 and 1, which only the render passes use.
+    empty: wgpu::BindGroup,
+    /// Storage bind groups for even and odd frames.
+    bind_groups: Vec<wgpu::BindGroup>,
+    /// Whether `init` ran since the storage was created or reset.
+    initialized: bool,
+}
+
+impl Compute {
+    /// Creates the storage at `size`, the shader is loaded by `build`.
+    pub fn new(
+        device: &wgpu::Device,
+        options: &ComputeOptions,
+        uniform_layout: &wgpu::BindGroupLayout,
+        params: &Params,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let shader_path = options
+            .shader
+            .clone()
+            .ok_or_else(|| anyhow!("no compute shader given"))?;
+        let limits = device.limits();
+        if limits.max_compute_invocations_per_workgroup == 0 {
+            bail!("compute shaders are not supported on this device");
+        }
+
+        let mut buffers = Vec::new();
+        for (i, size) in options.buffers.iter().enumerate() {
+            let Some(size) = *size else { continue };
+            if size > limits.max_storage_buffer_binding_size as u64 {
+                bail!(
+                    "storage buffer {} has {} bytes, more than the device's {}",
+                    i,
+                    size,
+                    limits.max_storage_buffer_binding_size
+                );
+            }
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("storage_buffer_{}", i)),
+                size,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            buffers.push((i as u32, buffer));
+        }
+        let formats = std::array::from_fn(|i| {
+            options.textures[i].map(|format| storage_format(device, i, format))
+        });
+
+        let layout_entries = layout_entries(&buffers, &formats);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("storage_bind_group_layout"),
+            entries: &layout_entries,
+        });
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("empty_bind_group_layout"),
+            entries: &[],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("compute_pipeline_layout"),
+            bind_group_layouts: &[&empty_layout, &empty_layout, uniform_layout, &layout],
+            push_constant_ranges: &[],
+        });
+        let empty = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("empty_bind_group"),
+            layout: &empty_layout,
+            entries: &[],
+        });
+
+        let mut compute = Self {
+            shader_path,
+            source: ShaderSource::default(),
+            entry_points: Vec::new(),
+            dispatch: options.dispatch.clone(),
+            buffers,
+            textures: Default::default(),
+            formats,
+            params: params.params.clone(),
+            size,
+            layout_entries,
+            layout,
+            pipeline_layout,
+            empty,
+            bind_groups: Vec::new(),
+            initialized: false,
+        };
+        compute.resize(device, size, params);
+        Ok(compute)
+    }
+
+    /// Validates `source` and builds a pipeline per entry point, keeping
+    /// the current ones on error.
+    pub fn build(&mut self, device: &wgpu::Device, source: ShaderSource) -> Result<(), String> {
+        let path = self.shader_path.display().to_string();
+        let module = naga::front::wgsl::parse_str(&source.code)
+            .map_err(|e| source.emit(&Diagnostic::from_wgsl(&e, &source.code)))?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .map_err(|e| source.emit(&Diagnostic::from_validation(&e)))?;
+
+        let entry_points: Vec<_> = module
+            .entry_points
+            .iter()
+            .filter(|ep| ep.stage == naga::ShaderStage::Compute)
+            .map(|ep| (ep.name.as_str(), ep.workgroup_size))
+            .collect();
+        if entry_points.is_empty() {
+            return Err(format!("{}: no @compute entry point", path));
+        }
+        let stages: Vec<_> = entry_points
+            .iter()
+            .map(|&(name, _)| (naga::ShaderStage::Compute, name))
+            .collect();
+        let groups = [
+            Vec::new(),
+            Vec::new(),
+            vec![uniform::layout_entry()],
+            self.layout_entries.clone(),
+        ];
+        layout::check_module(&module, &info, &stages, &groups).map_err(|e| {
+            format!(
+                "{}: {}\ndeclare storage with --storage-buffer and --storage-texture",
+                path, e
+            )
+        })?;
+        Uniform::<MainUniforms>::check_layout(&module, 2, 0)?;
+        params::check_layout(&module, &self.params)?;
+
+        let max = device.limits().max_compute_workgroups_per_dimension;
+        for (name, invocations) in &self.dispatch {
+            let Some((_, size)) = entry_points.iter().find(|(ep, _)| ep == name) else {
+                eprintln!("{}: --dispatch names no entry point `{}`", path, name);
+                continue;
+            };
+            let workgroups = workgroups(*invocations, *size);
+            if workgroups.iter().any(|&n| n > max) {
+                return Err(format!(
+                    "{}: dispatching `{}` takes {:?} workgroups, more than the device's {} \
+                     per dimension; raise its @workgroup_size",
+                    path, name, workgroups, max
+                ));
+            }
+        }
+
+        // errors `check_module` misses end up here instead of panicking
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compute_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.code.as_str().into()),
+        });
+        let built: Vec<_> = entry_points
+            .iter()
+            .map(|&(name, workgroup_size)| EntryPoint {
+                name: name.to_string(),
+                workgroup_size,
+                pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(name),
+                    layout: Some(&self.pipeline_layout),
+                    module: &shader,
+                    entry_point: Some(name),
+                    compilation_options: Default::default(),
+                    cache: None,
+                }),
+            })
+            .collect();
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(match err {
+                wgpu::Error::Validation { description, .. } => {
+                    format!("failed to create the pipeline: {}", description)
+                }
+                err => format!("failed to create the pipeline: {}", err),
+            });
+        }
+        self.entry_points = built;
+        self.source = source;
+        Ok(())
+    }
+
+    /// Recreates the storage textures at `size`, dropping their contents, so
+    /// `init` runs again on the next dispatch.
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32), params: &Params) {
+        self.size = size;
+        self.initialized = false;
+        self.textures = std::array::from_fn(|i| {
+            let format = self.formats[i]?;
+            let label = format!("storage-{}", i);
+            Some(std::array::from_fn(|_| {
+                Texture::storage(device, size, format, Some(&label))
+            }))
+        });
+        self.bind_groups = (0..2)
+            .map(|parity| self.create_bind_group(device, parity, params))
+            .collect();
+    }
+
+    /// Clears the storage and runs `init` again on the next dispatch.
+    pub fn reset(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        params: &Params,
+    ) {
+        for (_, buffer) in &self.buffers {
+            encoder.clear_buffer(buffer, 0, None);
+        }
+        self.resize(device, self.size, params);
+    }
+
+    /// Storage texture `n` as written on a frame of the given parity.
+    pub fn texture(&self, n: usize, parity: usize) -> Option<&Texture> {
+        self.textures.get(n)?.as_ref().map(|pair| &pair[parity])
+    }
+
+    /// Runs the entry points in the order they are declared, `init` only
+    /// on the first frame. Each storage texture starts as a copy of the
+    /// previous frame.
+    pub fn dispatch(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        parity: usize,
+        uniforms: &wgpu::BindGroup,
+    ) {
+        for pair in self.textures.iter().flatten() {
+            encoder.copy_texture_to_texture(
+                pair[1 - parity].texture.as_image_copy(),
+                pair[parity].texture.as_image_copy(),
+                pair[parity].texture.size(),
+            );
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.empty, &[]);
+        pass.set_bind_group(1, &self.empty, &[]);
+        pass.set_bind_group(2, uniforms, &[]);
+        pass.set_bind_group(STORAGE_GROUP, &self.bind_groups[parity], &[]);
+        for entry_point in &self.entry_points {
+            if entry_point.name == INIT_ENTRY_POINT && self.initialized {
+                continue;
+            }
+            let invocations = self
+                .dispatch
+                .iter()
+                .find(|(name, _)| *name == entry_point.name)
+                .map_or([self.size.0, self.size.1, 1], |(_, invocations)| *invocations);
+            let [x, y, z] = workgroups(invocations, entry_point.workgroup_size);
+            if x * y * z > 0 {
+                pass.set_pipeline(&entry_point.pipeline);
+                pass.dispatch_workgroups(x, y, z);
+            }
+        }
+        self.initialized = true;
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        parity: usize,
+        params: &Params,
+    ) -> wgpu::BindGroup {
+        let mut entries: Vec<_> = self
+            .buffers
+            .iter()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: *binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: params::PARAMS_BINDING,
+            resource: params.buffer.as_entire_binding(),
+        });
+        for (i, pair) in self.textures.iter().enumerate() {
+            let Some(pair) = pair else { continue };
+            entries.push(wgpu::BindGroupEntry {
+                binding: STORAGE_TEXTURE_BINDING + i as u32,
+                resource: wgpu::BindingResource::TextureView(&pair[parity].view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: PREVIOUS_BINDING + i as u32,
+                resource: wgpu::BindingResource::TextureView(&pair[1 - parity].view),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("storage_{}", parity)),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+}
+
+/// `STORAGE_GROUP` layout: each declared storage buffer at its index, each
+/// storage texture at `STORAGE_TEXTURE_BINDING + n`, write only, with its
+/// previous frame at `PREVIOUS_BINDING + n`, and the custom uniforms.
+fn layout_entries(
+    buffers: &[(u32, wgpu::Buffer)],
+    formats: &[Option<wgpu::TextureFormat>; STORAGE_TEXTURE_COUNT],
+) -> Vec<wgpu::BindGroupLayoutEntry> {
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty,
+        count: None,
+    };
+    let mut entries: Vec<_> = buffers
+        .iter()
+        .map(|(binding, _)| {
+            entry(
+                *binding,
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+            )
+        })
+        .collect();
+    entries.push(params::layout_entry());
+    for (i, format) in formats.iter().enumerate() {
+        let Some(format) = *format else { continue };
+        entries.push(entry(
+            STORAGE_TEXTURE_BINDING + i as u32,
+            wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+        ));
+        entries.push(entry(
+            PREVIOUS_BINDING + i as u32,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+        ));
+    }
+    entries
+}
+
+/// Format of storage texture `n`. Channels filter what they sample, so
+/// 32-bit floats fall back to 16 bits where they cannot be filtered.
+fn storage_format(device: &wgpu::Device, n: usize, format: TargetFormat) -> wgpu::TextureFormat {
+    match format.float() {
+        None => wgpu::TextureFormat::Rgba8Unorm,
+        Some(wgpu::TextureFormat::Rgba32Float)
+            if !device.features().contains(wgpu::Features::FLOAT32_FILTERABLE) =>
+        {
+            eprintln!(
+                "storage-{}: rgba32f textures cannot be filtered on this device, using rgba16f",
+                n
+            );
+            wgpu::TextureFormat::Rgba16Float
+        }
+        Some(float) => float,
+    }
+}
+
+/// Workgroups covering `invocations`, rounded up.
+fn workgroups(invocations: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    std::array::from_fn(|i| invocations[i].div_ceil(workgroup_size[i].max(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch() {
+        let parse = |s| ComputeOptions::parse_dispatch(s).unwrap();
+        assert_eq!(parse("step=64"), ("step".to_string(), [64, 1, 1]));
+        assert_eq!(parse("step=64x32"), ("step".to_string(), [64, 32, 1]));
+        assert_eq!(parse("step=4X4x4"), ("step".to_string(), [4, 4, 4]));
+        for s in [
+            "step",
+            "=64",
+            "step=",
+            "step=1x2x3x4",
+            "step=-1",
+            "step=ax1",
+        ] {
+            assert_eq!(
+                ComputeOptions::parse_dispatch(s).unwrap_err().to_string(),
+                format!("invalid dispatch `{}`, expected <entry>=<x>[x<y>[x<z>]]", s)
+            );
+        }
+    }
+
+    #[test]
+    fn storage_buffers() {
+        assert_eq!(ComputeOptions::parse_buffer("3=1024").unwrap(), (3, 1024));
+        for (s, err) in [
+            (
+                "1024",
+                "invalid storage buffer `1024`, expected <n>=<bytes>",
+            ),
+            (
+                "1=big",
+                "invalid storage buffer `1=big`, expected <n>=<bytes>",
+            ),
+            ("4=16", "invalid storage buffer `4`, expected 0..3"),
+            (
+                "0=0",
+                "storage buffer size must be a non-zero multiple of 4 bytes",
+            ),
+            (
+                "0=6",
+                "storage buffer size must be a non-zero multiple of 4 bytes",
+            ),
+        ] {
+            assert_eq!(
+                ComputeOptions::parse_buffer(s).unwrap_err().to_string(),
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn storage_textures() {
+        assert_eq!(
+            ComputeOptions::parse_texture("0").unwrap(),
+            (0, TargetFormat::Rgba16Float)
+        );
+        assert_eq!(
+            ComputeOptions::parse_texture("2=rgba32f").unwrap(),
+            (2, TargetFormat::Rgba32Float)
+        );
+        assert_eq!(
+            ComputeOptions::parse_texture("a").unwrap_err().to_string(),
+            "invalid storage texture `a`, expected 0..3"
+        );
+        assert!(ComputeOptions::parse_texture("1=rgb").is_err());
+    }
+
+    #[test]
+    fn workgroups_cover_the_invocations() {
+        assert_eq!(workgroups([64, 64, 1], [8, 8, 1]), [8, 8, 1]);
+        assert_eq!(workgroups([65, 1, 1], [64, 1, 1]), [2, 1, 1]);
+        assert_eq!(workgroups([1920, 1080, 1], [16, 16, 1]), [120, 68, 1]);
+        // no workgroups for no invocations, a size of 0 counts as 1
+        assert_eq!(workgroups([0, 5, 5], [8, 0, 1]), [0, 5, 5]);
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::NaiveDateTime;
+
+/// Format of `--date`.
+pub const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Settings that make frame `N` render the same pixels on every run:
+/// time advances by exactly `1 / fps` per frame, `iDate` is frozen, the
+/// mouse follows a script and random inputs are seeded.
+#[derive(Debug, Clone)]
+pub struct Deterministic {
+    pub fps: f64,
+    pub date: NaiveDateTime,
+    /// `None` leaves the mouse untouched at the origin.
+    pub mouse: Option<MouseScript>,
+    pub seed: u64,
+}
+
+impl Default for Deterministic {
+    fn default() -> Self {
+        Self {
+            fps: 60.0,
+            date: NaiveDateTime::parse_from_str("2000-01-01T00:00:00", DATE_FORMAT)
+                .expect("valid default date"),
+            mouse: None,
+            seed: 0,
+        }
+    }
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, DATE_FORMAT)
+        .map_err(|_| anyhow!("invalid date `{}`, expected YYYY-MM-DDTHH:MM:SS", s))
+}
+
+/// Mouse input by frame. Each line of the script is
+/// `<frame> <x> <y> [down|up]` in pixels from the bottom-left corner; the
+/// mouse stays where the last line before a frame put it. Empty lines and
+/// lines starting with `#` are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct MouseScript {
+    events: Vec<MouseEvent>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MouseEvent {
+    frame: i32,
+    x: f32,
+    y: f32,
+    /// `Some(true)` presses the button, `Some(false)` releases it.
+    button: Option<bool>,
+}
+
+impl MouseScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read `{}`", path.display()))?;
+        Self::parse(&src).with_context(|| format!("in `{}`", path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut events: Vec<MouseEvent> = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || anyhow!("line {}: expected `<frame> <x> <y> [down|up]`", i + 1);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts.len() > 4 {
+                return Err(err());
+            }
+            let event = MouseEvent {
+                frame: parts[0].parse().map_err(|_| err())?,
+                x: parts[1].parse().map_err(|_| err())?,
+                y: parts[2].parse().map_err(|_| err())?,
+                button: match parts.get(3) {
+                    None => None,
+                    Some(&"down") => Some(true),
+                    Some(&"up") => Some(false),
+                    Some(_) => return Err(err()),
+                },
+            };
+            if events.last().is_some_and(|last| last.frame > event.frame) {
+                bail!("line {}: frames must not decrease", i + 1);
+            }
+            events.push(event);
+        }
+        Ok(Self { events })
+    }
+
+    /// `iMouse` at `frame`, following Shadertoy: `xy` is the position while
+    /// the button was last down, `z` the click position, negated once the
+    /// button is up, and `w` the click y, negated after the click frame.
+    pub fn mouse(&self, frame: i32) -> [f32; 4] {
+        let mut drag = [0.0, 0.0];
+        let mut click = [0.0, 0.0];
+        let mut pressed = false;
+        let mut clicked_on = None;
+        for event in self.events.iter().take_while(|e| e.frame <= frame) {
+            match event.button {
+                Some(true) if !pressed => {
+                    pressed = true;
+                    click = [event.x, event.y];
+                    clicked_on = Some(event.frame);
+                }
+                Some(false) => pressed = false,
+                _ => (),
+            }
+            if pressed {
+                drag = [event.x, event.y];
+            }
+        }
+        [
+            drag[0],
+            drag[1],
+            if pressed { click[0] } else { -click[0] },
+            if clicked_on == Some(frame) { click[1] } else { -click[1] },
+        ]
+    }
+
+    /// Cursor position at `frame`, pressed or not.
+    pub fn position(&self, frame: i32) -> [f32; 2] {
+        self.events
+            .iter()
+            .take_while(|e| e.frame <= frame)
+            .last()
+            .map_or([0.0, 0.0], |e| [e.x, e.y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "
+        # move, click, drag, release, move
+        0 10 20
+        5 30 40 down
+        8 50 60
+
+        12 70 80 up
+        20 90 100
+    ";
+
+    #[test]
+    fn click_drag_release() {
+        let script = MouseScript::parse(SCRIPT).unwrap();
+        // not clicked yet
+        assert_eq!(script.mouse(0), [0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(script.mouse(4), [0.0, 0.0, 0.0, 0.0]);
+        // the click frame has both z and w positive
+        assert_eq!(script.mouse(5), [30.0, 40.0, 30.0, 40.0]);
+        // held: w turns negative
+        assert_eq!(script.mouse(6), [30.0, 40.0, 30.0, -40.0]);
+        assert_eq!(script.mouse(8), [50.0, 60.0, 30.0, -40.0]);
+        // released: xy stay at the last drag, z turns negative
+        assert_eq!(script.mouse(12), [50.0, 60.0, -30.0, -40.0]);
+        assert_eq!(script.mouse(20), [50.0, 60.0, -30.0, -40.0]);
+        assert_eq!(script.position(4), [10.0, 20.0]);
+        assert_eq!(script.position(20), [90.0, 100.0]);
+    }
+
+    #[test]
+    fn invalid_scripts() {
+        let err = |s| MouseScript::parse(s).unwrap_err().to_string();
+        let expected = "line 1: expected `<frame> <x> <y> [down|up]`";
+        assert_eq!(err("0 1"), expected);
+        assert_eq!(err("0 1 2 held"), expected);
+        assert_eq!(err("0 1 x"), expected);
+        assert_eq!(err("5 1 2\n4 1 2"), "line 2: frames must not decrease");
+    }
+}
@@ -0,0 +1,281 @@
+//! Animated GIF and APNG export without external tools, for short loops.
+//! GIF goes through the encoder of `image`, which has none for APNG, so
+//! that is written with `png`.
+
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use color_quant::NeuQuant;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::ColorMap,
+    Delay, Frame, RgbaImage,
+};
+
+use crate::{cli::Options, headless::Headless};
+
+/// NeuQuant sampling factor, 1 is slowest and best, 30 fastest.
+const QUANTIZE_SPEED: i32 = 10;
+/// Pixels sampled to train a global palette.
+const PALETTE_SAMPLES: usize = 1 << 20;
+/// Mean difference of a channel, out of 255, below which two frames count
+/// as the same for `--loop`.
+const LOOP_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// One palette for the whole animation, no flickering between frames.
+    Global,
+    /// A palette per frame, better colors when the frames differ a lot.
+    Frame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loop {
+    /// Fails unless the frame after the last matches the first.
+    Check,
+    /// Ends the animation before the frame that best matches the first, in
+    /// its second half.
+    Trim,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// `.gif`, or `.png`/`.apng` for an animated PNG.
+    pub out: PathBuf,
+    /// Seconds exported from `--time` on.
+    pub duration: f32,
+    /// `None` uses a global palette for GIF and full color for APNG.
+    pub palette: Option<Palette>,
+    pub dither: bool,
+    pub seamless: Option<Loop>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            out: PathBuf::from("loop.gif"),
+            duration: 2.0,
+            palette: None,
+            dither: false,
+            seamless: None,
+        }
+    }
+}
+
+impl Palette {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "global" => Ok(Self::Global),
+            "frame" => Ok(Self::Frame),
+            _ => Err(anyhow!("invalid palette `{}`, expected global or frame", s)),
+        }
+    }
+}
+
+impl Loop {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "check" => Ok(Self::Check),
+            "trim" => Ok(Self::Trim),
+            _ => Err(anyhow!("invalid loop mode `{}`, expected check or trim", s)),
+        }
+    }
+}
+
+enum Format {
+    Gif,
+    Apng,
+}
+
+/// `shader_toy export`: renders `export.duration` seconds and encodes them.
+pub fn run(options: &Options, export: &ExportOptions) -> Result<()> {
+    let format = match export.out.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("gif") => Format::Gif,
+        Some(ext) if ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("apng") => {
+            Format::Apng
+        }
+        _ => bail!(
+            "cannot export `{}`, expected .gif, .png or .apng",
+            export.out.display()
+        ),
+    };
+    if matches!(format, Format::Apng) && export.palette == Some(Palette::Frame) {
+        bail!("an animated PNG has a single palette, use --palette global");
+    }
+    if options.width > u16::MAX as u32 || options.height > u16::MAX as u32 {
+        bail!("{}x{} is too large for an animation", options.width, options.height);
+    }
+
+    let fps = options.deterministic.as_ref().map_or(60.0, |d| d.fps);
+    let count = (export.duration as f64 * fps).round() as u64;
+    if count == 0 {
+        bail!("{} seconds at {} fps is no frames", export.duration, fps);
+    }
+    // one more frame to compare with the first
+    let end = if export.seamless.is_some() { count + 1 } else { count };
+    let mut frames = Vec::with_capacity(end as usize);
+    Headless::new()?.render(options, 0..end, |_, image| {
+        frames.push(image);
+        Ok(())
+    })?;
+
+    match export.seamless {
+        Some(Loop::Check) => {
+            let difference = mean_difference(&frames[0], &frames[count as usize]);
+            if difference > LOOP_THRESHOLD {
+                bail!(
+                    "not a seamless loop, the frame after the last differs from the first by {:.2}; \
+                     adjust --duration or use --loop trim",
+                    difference
+                );
+            }
+            frames.truncate(count as usize);
+        }
+        Some(Loop::Trim) => {
+            let (best, difference) = (count as usize / 2..=count as usize)
+                .filter(|&i| i > 0)
+                .map(|i| (i, mean_difference(&frames[0], &frames[i])))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .expect("at least one candidate");
+            if difference > LOOP_THRESHOLD {
+                eprintln!(
+                    "warning: no frame matches the first, the closest differs by {:.2}",
+                    difference
+                );
+            }
+            frames.truncate(best);
+            println!("Trimmed to {} frames ({:.3} s)", best, best as f64 / fps);
+        }
+        None => (),
+    }
+
+    let file = File::create(&export.out)
+        .with_context(|| format!("failed to create `{}`", export.out.display()))?;
+    let writer = BufWriter::new(file);
+    match format {
+        Format::Gif => write_gif(writer, &frames, fps, export)?,
+        Format::Apng => write_apng(writer, &frames, fps, export)?,
+    }
+    println!("Exported {} frames to {}", frames.len(), export.out.display());
+    Ok(())
+}
+
+fn write_gif(
+    writer: impl std::io::Write,
+    frames: &[RgbaImage],
+    fps: f64,
+    export: &ExportOptions,
+) -> Result<()> {
+    // GIF delays are in hundredths of a second
+    let delay = (100.0 / fps).round().max(1.0);
+    if (delay - 100.0 / fps).abs() > 1e-6 {
+        eprintln!(
+            "warning: GIF plays {} fps at {} fps, pick a divisor of 100 like 50 or 25",
+            fps,
+            100.0 / delay
+        );
+    }
+    let global = match export.palette.unwrap_or(Palette::Global) {
+        Palette::Global => Some(global_palette(frames)),
+        Palette::Frame => None,
+    };
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+    for frame in frames {
+        let local;
+        let quantizer = match &global {
+            Some(quantizer) => quantizer,
+            None => {
+                local = NeuQuant::new(QUANTIZE_SPEED, 256, frame.as_raw());
+                &local
+            }
+        };
+        // 256 colors at most, which the encoder keeps as they are
+        let frame = quantize(frame, quantizer, export.dither);
+        let delay = Delay::from_numer_denom_ms(delay as u32 * 10, 1);
+        encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+fn write_apng(
+    writer: impl std::io::Write,
+    frames: &[RgbaImage],
+    fps: f64,
+    export: &ExportOptions,
+) -> Result<()> {
+    let (width, height) = frames[0].dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    // a delay of 100 / (100 fps) seconds keeps fractional rates exact
+    let denominator = (fps * 100.0).round();
+    if denominator > u16::MAX as f64 {
+        bail!("{} fps is too high for an animated PNG", fps);
+    }
+    encoder.set_frame_delay(100, denominator as u16)?;
+
+    let quantizer = export.palette.map(|_| global_palette(frames));
+    match &quantizer {
+        Some(quantizer) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(quantizer.color_map_rgb());
+        }
+        None => encoder.set_color(png::ColorType::Rgb),
+    }
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        let data = match &quantizer {
+            Some(quantizer) => indices(frame, quantizer, export.dither),
+            None => frame.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        };
+        writer.write_image_data(&data)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// A 256 color palette trained on pixels spread over all frames.
+fn global_palette(frames: &[RgbaImage]) -> NeuQuant {
+    let total: usize = frames.iter().map(|f| f.as_raw().len() / 4).sum();
+    let step = total.div_ceil(PALETTE_SAMPLES).max(1);
+    let samples: Vec<u8> = frames
+        .iter()
+        .flat_map(|f| f.as_raw().chunks_exact(4))
+        .step_by(step)
+        .flatten()
+        .copied()
+        .collect();
+    NeuQuant::new(QUANTIZE_SPEED, 256, &samples)
+}
+
+/// `frame` in the colors of the palette, after Floyd-Steinberg dithering
+/// if asked.
+fn quantize(frame: &RgbaImage, quantizer: &NeuQuant, dither: bool) -> RgbaImage {
+    let mut frame = frame.clone();
+    // the dithering of `image` reads the neighbours of every pixel
+    if dither && frame.width() > 1 && frame.height() > 1 {
+        image::imageops::dither(&mut frame, quantizer);
+    } else {
+        for pixel in frame.pixels_mut() {
+            quantizer.map_color(pixel);
+        }
+    }
+    frame
+}
+
+/// Palette index of every pixel, after Floyd-Steinberg dithering if asked.
+fn indices(frame: &RgbaImage, quantizer: &NeuQuant, dither: bool) -> Vec<u8> {
+    image::imageops::index_colors(&quantize(frame, quantizer, dither), quantizer).into_raw()
+}
+
+/// Mean absolute difference of the color channels, from 0 to 255.
+fn mean_difference(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    let sum: u64 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(a, b)| (0..3).map(|i| a[i].abs_diff(b[i]) as u64).sum::<u64>())
+        .sum();
+    sum as f32 / (a.width() * a.height() * 3).max(1) as f32
+}
@@ -0,0 +1,140 @@
+//! 8x16 bitmap font for the error overlay and the HUD, rasterized from DejaVu Sans Mono
+//! Bold. Each glyph is 16 rows, the high bit of a row is its leftmost pixel.
+
+pub const GLYPH_WIDTH: u32 = 8;
+pub const GLYPH_HEIGHT: u32 = 16;
+
+/// Glyphs of the printable ASCII range, starting at `' '`.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 16]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x10, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '!'
+    [0x00, 0x00, 0x24, 0x64, 0x64, 0x64, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x00, 0x00, 0x00, 0x12, 0x16, 0x7f, 0x3e, 0x24, 0xfe, 0xfe, 0x68, 0x48, 0x00, 0x00, 0x00, 0x00], // '#'
+    [0x00, 0x00, 0x00, 0x18, 0x3c, 0x78, 0x70, 0x3c, 0x1e, 0x16, 0x7e, 0x7c, 0x10, 0x00, 0x00, 0x00], // '$'
+    [0x00, 0x00, 0x00, 0x70, 0xd0, 0xd0, 0x66, 0x18, 0x4e, 0x0b, 0x0b, 0x0e, 0x00, 0x00, 0x00, 0x00], // '%'
+    [0x00, 0x00, 0x1c, 0x3c, 0x60, 0x30, 0x30, 0x7b, 0xcf, 0xce, 0x6e, 0x7f, 0x00, 0x00, 0x00, 0x00], // '&'
+    [0x00, 0x00, 0x10, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x00, 0x00, 0x0c, 0x08, 0x18, 0x18, 0x10, 0x30, 0x10, 0x18, 0x18, 0x18, 0x08, 0x0c, 0x00, 0x00], // '('
+    [0x00, 0x00, 0x30, 0x10, 0x18, 0x18, 0x18, 0x08, 0x08, 0x18, 0x18, 0x18, 0x30, 0x30, 0x00, 0x00], // ')'
+    [0x00, 0x00, 0x10, 0x58, 0x7e, 0x3c, 0x7e, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '*'
+    [0x00, 0x00, 0x00, 0x00, 0x10, 0x18, 0x18, 0xfe, 0x7e, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x30, 0x00, 0x00], // ','
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x3c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '.'
+    [0x00, 0x00, 0x02, 0x06, 0x04, 0x0c, 0x08, 0x18, 0x10, 0x30, 0x20, 0x60, 0x60, 0x00, 0x00, 0x00], // '/'
+    [0x00, 0x00, 0x18, 0x3c, 0x66, 0x66, 0x66, 0x7e, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // '0'
+    [0x00, 0x00, 0x18, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // '1'
+    [0x00, 0x00, 0x38, 0x7c, 0x06, 0x06, 0x0c, 0x1c, 0x38, 0x30, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // '2'
+    [0x00, 0x00, 0x38, 0x7c, 0x06, 0x06, 0x3c, 0x1c, 0x06, 0x06, 0x7e, 0x7c, 0x00, 0x00, 0x00, 0x00], // '3'
+    [0x00, 0x00, 0x0c, 0x0c, 0x1c, 0x3c, 0x2c, 0x6c, 0x7e, 0x7e, 0x0c, 0x0c, 0x00, 0x00, 0x00, 0x00], // '4'
+    [0x00, 0x00, 0x3c, 0x7c, 0x60, 0x60, 0x7c, 0x4e, 0x06, 0x06, 0x6e, 0x7c, 0x00, 0x00, 0x00, 0x00], // '5'
+    [0x00, 0x00, 0x1c, 0x3e, 0x60, 0x60, 0x7c, 0x76, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // '6'
+    [0x00, 0x00, 0x7e, 0x7e, 0x06, 0x0c, 0x0c, 0x18, 0x18, 0x18, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00], // '7'
+    [0x00, 0x00, 0x18, 0x7c, 0x66, 0x66, 0x3c, 0x3c, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // '8'
+    [0x00, 0x00, 0x18, 0x7c, 0x66, 0x66, 0x66, 0x7e, 0x3e, 0x06, 0x4c, 0x7c, 0x00, 0x00, 0x00, 0x00], // '9'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // ':'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x00, 0x00, 0x18, 0x18, 0x10, 0x30, 0x00, 0x00], // ';'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x3c, 0x60, 0x70, 0x1e, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00], // '<'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x00, 0x00, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '='
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x3c, 0x0e, 0x0e, 0x78, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00], // '>'
+    [0x00, 0x00, 0x18, 0x7e, 0x06, 0x06, 0x0c, 0x18, 0x18, 0x00, 0x10, 0x18, 0x00, 0x00, 0x00, 0x00], // '?'
+    [0x00, 0x00, 0x00, 0x1c, 0x76, 0x42, 0xde, 0x92, 0xb2, 0x92, 0xde, 0x40, 0x72, 0x1e, 0x00, 0x00], // '@'
+    [0x00, 0x00, 0x18, 0x38, 0x3c, 0x3c, 0x2c, 0x66, 0x7e, 0x7e, 0x66, 0xc3, 0x00, 0x00, 0x00, 0x00], // 'A'
+    [0x00, 0x00, 0x78, 0x7e, 0x66, 0x66, 0x7c, 0x7e, 0x66, 0x66, 0x7e, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'B'
+    [0x00, 0x00, 0x0c, 0x3e, 0x72, 0x60, 0x60, 0x60, 0x60, 0x60, 0x3e, 0x1e, 0x00, 0x00, 0x00, 0x00], // 'C'
+    [0x00, 0x00, 0x70, 0x7c, 0x6e, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x78, 0x00, 0x00, 0x00, 0x00], // 'D'
+    [0x00, 0x00, 0x7e, 0x7e, 0x60, 0x60, 0x7e, 0x7e, 0x60, 0x60, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'E'
+    [0x00, 0x00, 0x7e, 0x7e, 0x60, 0x60, 0x7e, 0x7e, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00], // 'F'
+    [0x00, 0x00, 0x1c, 0x3e, 0x72, 0x60, 0x60, 0x6e, 0x66, 0x62, 0x3e, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'G'
+    [0x00, 0x00, 0x46, 0x66, 0x66, 0x66, 0x7e, 0x7e, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'H'
+    [0x00, 0x00, 0x7c, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'I'
+    [0x00, 0x00, 0x1c, 0x3c, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0c, 0x7c, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'J'
+    [0x00, 0x00, 0x42, 0x66, 0x6c, 0x78, 0x78, 0x78, 0x6c, 0x6e, 0x66, 0x67, 0x00, 0x00, 0x00, 0x00], // 'K'
+    [0x00, 0x00, 0x20, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'L'
+    [0x00, 0x00, 0x66, 0x66, 0x6e, 0x7e, 0x7e, 0x5a, 0x52, 0x42, 0x42, 0x42, 0x00, 0x00, 0x00, 0x00], // 'M'
+    [0x00, 0x00, 0x62, 0x66, 0x76, 0x76, 0x76, 0x5e, 0x4e, 0x4e, 0x4e, 0x46, 0x00, 0x00, 0x00, 0x00], // 'N'
+    [0x00, 0x00, 0x18, 0x7c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'O'
+    [0x00, 0x00, 0x78, 0x7e, 0x66, 0x66, 0x66, 0x7e, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00], // 'P'
+    [0x00, 0x00, 0x18, 0x7c, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x3c, 0x0e, 0x04, 0x00, 0x00], // 'Q'
+    [0x00, 0x00, 0x78, 0x7e, 0x66, 0x66, 0x6e, 0x7c, 0x6c, 0x66, 0x66, 0x63, 0x00, 0x00, 0x00, 0x00], // 'R'
+    [0x00, 0x00, 0x1c, 0x7e, 0x60, 0x60, 0x78, 0x3c, 0x06, 0x06, 0x6e, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'S'
+    [0x00, 0x00, 0x7e, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'T'
+    [0x00, 0x00, 0x42, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'U'
+    [0x00, 0x00, 0x42, 0x66, 0x66, 0x66, 0x66, 0x24, 0x3c, 0x3c, 0x3c, 0x38, 0x00, 0x00, 0x00, 0x00], // 'V'
+    [0x00, 0x00, 0x81, 0xc3, 0xc3, 0xdb, 0xda, 0x7a, 0x7e, 0x6e, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'W'
+    [0x00, 0x00, 0x42, 0x66, 0x6e, 0x3c, 0x18, 0x18, 0x3c, 0x3c, 0x66, 0xc6, 0x00, 0x00, 0x00, 0x00], // 'X'
+    [0x00, 0x00, 0x42, 0x66, 0x66, 0x3c, 0x3c, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'Y'
+    [0x00, 0x00, 0x7e, 0x7e, 0x06, 0x0c, 0x1c, 0x18, 0x30, 0x70, 0x7e, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'Z'
+    [0x00, 0x00, 0x1c, 0x18, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1c, 0x1c, 0x00, 0x00], // '['
+    [0x00, 0x00, 0x40, 0x60, 0x20, 0x30, 0x10, 0x18, 0x18, 0x08, 0x0c, 0x04, 0x06, 0x00, 0x00, 0x00], // '\\'
+    [0x00, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x38, 0x38, 0x00, 0x00], // ']'
+    [0x00, 0x00, 0x18, 0x3c, 0x3c, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00], // '_'
+    [0x00, 0x20, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x06, 0x3e, 0x7e, 0x66, 0x6e, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'a'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x7c, 0x76, 0x66, 0x66, 0x66, 0x7e, 0x7c, 0x00, 0x00, 0x00, 0x00], // 'b'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x72, 0x60, 0x60, 0x60, 0x36, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'c'
+    [0x00, 0x00, 0x06, 0x06, 0x06, 0x7e, 0x6e, 0x66, 0x66, 0x66, 0x7e, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'd'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x66, 0x7e, 0x60, 0x76, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'e'
+    [0x00, 0x00, 0x0e, 0x1e, 0x18, 0x7e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // 'f'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x6e, 0x66, 0x66, 0x66, 0x7e, 0x3e, 0x06, 0x7e, 0x38, 0x00], // 'g'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x7c, 0x7e, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'h'
+    [0x00, 0x18, 0x18, 0x08, 0x00, 0x78, 0x18, 0x18, 0x18, 0x18, 0x1c, 0x7f, 0x00, 0x00, 0x00, 0x00], // 'i'
+    [0x00, 0x08, 0x08, 0x08, 0x00, 0x38, 0x18, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x78, 0x70, 0x00], // 'j'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x66, 0x6c, 0x78, 0x78, 0x6c, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'k'
+    [0x00, 0x00, 0x70, 0x70, 0x30, 0x30, 0x30, 0x30, 0x30, 0x38, 0x1c, 0x1e, 0x00, 0x00, 0x00, 0x00], // 'l'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xfe, 0xda, 0xda, 0xda, 0xda, 0xda, 0xda, 0x00, 0x00, 0x00, 0x00], // 'm'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x7e, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'n'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x3c, 0x00, 0x00, 0x00, 0x00], // 'o'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7c, 0x76, 0x66, 0x66, 0x66, 0x7e, 0x7c, 0x60, 0x60, 0x60, 0x00], // 'p'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x6e, 0x66, 0x66, 0x66, 0x7e, 0x3e, 0x06, 0x06, 0x06, 0x00], // 'q'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x38, 0x30, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00], // 'r'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3c, 0x60, 0x70, 0x3c, 0x06, 0x46, 0x7c, 0x00, 0x00, 0x00, 0x00], // 's'
+    [0x00, 0x00, 0x00, 0x30, 0x38, 0x7e, 0x38, 0x30, 0x30, 0x10, 0x1c, 0x1e, 0x00, 0x00, 0x00, 0x00], // 't'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7e, 0x3e, 0x00, 0x00, 0x00, 0x00], // 'u'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x2c, 0x3c, 0x3c, 0x18, 0x00, 0x00, 0x00, 0x00], // 'v'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xc3, 0xc3, 0xda, 0x5a, 0x7e, 0x6e, 0x66, 0x00, 0x00, 0x00, 0x00], // 'w'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x3c, 0x3c, 0x18, 0x3c, 0x6e, 0x66, 0x00, 0x00, 0x00, 0x00], // 'x'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x3c, 0x3c, 0x18, 0x18, 0x18, 0x70, 0x60, 0x00], // 'y'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x0e, 0x0c, 0x18, 0x30, 0x70, 0x7e, 0x00, 0x00, 0x00, 0x00], // 'z'
+    [0x00, 0x00, 0x0e, 0x18, 0x18, 0x18, 0x18, 0x70, 0x70, 0x18, 0x18, 0x18, 0x18, 0x0e, 0x00, 0x00], // '{'
+    [0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x00, 0x00, 0x70, 0x38, 0x18, 0x18, 0x18, 0x1c, 0x0e, 0x18, 0x18, 0x18, 0x18, 0x70, 0x00, 0x00], // '}'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7a, 0x5e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// Rows of `c`. Box drawing characters used by codespan diagnostics map to
+/// the characters of `Chars::ascii()`, anything else outside ASCII becomes `?`.
+pub fn glyph(c: char) -> &'static [u8; 16] {
+    let c = match c {
+        '│' => '|',
+        '─' | '┌' => '-',
+        '╭' => '/',
+        '╰' => '\\',
+        '═' => '=',
+        '·' => '.',
+        '\t' => ' ',
+        c => c,
+    };
+    match c {
+        ' '..='~' => &GLYPHS[c as usize - ' ' as usize],
+        _ => &GLYPHS['?' as usize - ' ' as usize],
+    }
+}
+
+/// Draws `text` on one line of an RGBA image `width` pixels wide, from the
+/// top-left corner at `x`, `y`. The text is not clipped.
+pub fn draw_text(pixels: &mut [u8], width: u32, x: u32, y: u32, text: &str, color: [u8; 4]) {
+    for (col, c) in text.chars().enumerate() {
+        let x0 = x + col as u32 * GLYPH_WIDTH;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                if bits & (0x80 >> dx) != 0 {
+                    let i = (((y + row as u32) * width + x0 + dx) * 4) as usize;
+                    pixels[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
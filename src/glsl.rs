@@ -0,0 +1,185 @@
+use std::fmt::Write;
+
+use wgpu::naga;
+
+use crate::{
+    channel::{CHANNEL_COUNT, CUBE_BINDING, RESOLUTION_BINDING},
+    params::{self, Param},
+    source::{Diagnostic, ShaderSource},
+};
+
+/// What the generated declarations depend on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Interface {
+    /// Channels bound to a cubemap, declared as `samplerCube`.
+    pub cube_channels: [bool; CHANNEL_COUNT],
+    /// Set for the image pass: `fragCoord` starts at the bottom of the
+    /// surface like on Shadertoy. Buffers keep the texture's row order so
+    /// `fragCoord / iResolution` reads back the texel written there.
+    pub flip_y: bool,
+    /// Custom uniforms, declared by name.
+    pub params: Vec<Param>,
+}
+
+/// Declarations placed in front of Shadertoy-style GLSL. The uniform block
+/// mirrors `MainUniforms`, group 1 holds the channels, see
+/// `channel::create_bind_group_layout`, and group 3 the custom uniforms.
+fn prelude(interface: &Interface) -> String {
+    let mut src = String::from(
+        "\
+#version 450
+
+layout(set = 2, binding = 0) uniform Uniforms {
+    float time;
+    float time_delta;
+    vec2 resolution;
+    vec2 mouse_position;
+    vec2 zoom;
+    int frame;
+    float frame_rate;
+    float sample_rate;
+    vec4 mouse;
+    vec4 date;
+    vec2 tile_offset;
+} stoy_u;
+
+#define iTime stoy_u.time
+#define iTimeDelta stoy_u.time_delta
+#define iResolution vec3(stoy_u.resolution, 1.0)
+#define iFrame stoy_u.frame
+#define iFrameRate stoy_u.frame_rate
+#define iSampleRate stoy_u.sample_rate
+#define iMouse stoy_u.mouse
+#define iDate stoy_u.date
+
+",
+    );
+
+    for i in 0..CHANNEL_COUNT {
+        let texture = i * 2;
+        let cube = CUBE_BINDING as usize + i;
+        let _ = writeln!(
+            src,
+            "layout(set = 1, binding = {texture}) uniform texture2D stoy_channel{i}_texture;\n\
+             layout(set = 1, binding = {sampler}) uniform sampler stoy_channel{i}_sampler;\n\
+             layout(set = 1, binding = {cube}) uniform textureCube stoy_channel{i}_cube;",
+            sampler = texture + 1,
+        );
+        if interface.cube_channels[i] {
+            let _ = writeln!(
+                src,
+                "#define iChannel{i} samplerCube(stoy_channel{i}_cube, stoy_channel{i}_sampler)"
+            );
+        } else {
+            let _ = writeln!(
+                src,
+                "#define iChannel{i} sampler2D(stoy_channel{i}_texture, stoy_channel{i}_sampler)"
+            );
+        }
+    }
+
+    let _ = write!(
+        src,
+        "
+layout(set = 1, binding = {RESOLUTION_BINDING}) uniform ChannelUniforms {{
+    vec4 resolution[{CHANNEL_COUNT}];
+}} stoy_channels;
+
+#define iChannelResolution vec3[{CHANNEL_COUNT}](stoy_channels.resolution[0].xyz, stoy_channels.resolution[1].xyz, stoy_channels.resolution[2].xyz, stoy_channels.resolution[3].xyz)
+
+layout(location = 0) out vec4 stoy_frag_color;
+
+"
+    );
+    src.push_str(&params::glsl_declarations(&interface.params));
+    src
+}
+
+/// Calls the user's `mainImage`.
+fn epilogue(interface: &Interface) -> &'static str {
+    if interface.flip_y {
+        "
+
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    vec2 coord = gl_FragCoord.xy + stoy_u.tile_offset;
+    mainImage(color, vec2(coord.x, iResolution.y - coord.y));
+    stoy_frag_color = color;
+}
+"
+    } else {
+        "
+
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(color, gl_FragCoord.xy + stoy_u.tile_offset);
+    stoy_frag_color = color;
+}
+"
+    }
+}
+
+/// Entry point of the generated fragment shader.
+pub const ENTRY_POINT: &str = "main";
+
+/// Wraps a `mainImage` shader into a complete GLSL fragment shader.
+pub fn wrap(user_code: &str, interface: &Interface) -> String {
+    wrap_with_offset(user_code, interface).0
+}
+
+/// Also returns where `user_code` starts in the generated source.
+fn wrap_with_offset(user_code: &str, interface: &Interface) -> (String, usize) {
+    let mut src = prelude(interface);
+    let offset = src.len();
+    src.push_str(user_code);
+    src.push_str(epilogue(interface));
+    (src, offset)
+}
+
+/// Parses a `mainImage` shader through naga's GLSL frontend. Errors are
+/// reported against the files of `source`, not the generated code.
+pub fn parse(source: &ShaderSource, interface: &Interface) -> Result<naga::Module, String> {
+    let (src, start) = wrap_with_offset(&source.code, interface);
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    naga::front::glsl::Frontend::default()
+        .parse(&options, &src)
+        .map_err(|errors| {
+            errors
+                .errors
+                .iter()
+                .map(|err| {
+                    source.emit(&Diagnostic {
+                        message: err.kind.to_string(),
+                        labels: vec![(user_span(err.meta, start, source.code.len()), String::new())],
+                        notes: Vec::new(),
+                    })
+                })
+                .collect()
+        })
+}
+
+/// Formats a validation error of a module returned by [`parse`] against
+/// the files of `source`.
+pub fn emit_validation_error(
+    err: &naga::WithSpan<naga::valid::ValidationError>,
+    source: &ShaderSource,
+    interface: &Interface,
+) -> String {
+    let start = prelude(interface).len();
+    let mut diagnostic = Diagnostic::from_validation(err);
+    for (span, _) in &mut diagnostic.labels {
+        *span = user_span(*span, start, source.code.len());
+    }
+    source.emit(&diagnostic)
+}
+
+/// Moves a span of the generated source onto the user code starting at
+/// `start`. Spans in the generated declarations become undefined.
+fn user_span(span: naga::Span, start: usize, len: usize) -> naga::Span {
+    match span.to_range() {
+        Some(range) if range.start >= start && range.end <= start + len => {
+            naga::Span::new((range.start - start) as u32, (range.end - start) as u32)
+        }
+        _ => naga::Span::UNDEFINED,
+    }
+}
@@ -1,14 +1,8 @@
-use std::{num::NonZeroU32, sync::Arc};
+use std::{num::NonZeroU32, path::Path, sync::Arc};
 
-use bytemuck::Contiguous;
-use wgpu::{SurfaceTexture, TextureFormat};
-use winit::{
-    dpi::PhysicalSize,
-    event::*,
-    window::{self, Window},
-};
+use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::{input_manager::InputEvent, stoy::Stoy};
+use crate::{cli::Options, input_manager::InputEvent, record::Recorder, stoy::Stoy};
 
 #[allow(dead_code)]
 pub struct GpuState {
@@ -18,10 +12,12 @@ pub struct GpuState {
     config: wgpu::SurfaceConfiguration,
     window: Arc<Window>,
     engine: Stoy,
+    /// Last title set, only changed when the playback status does.
+    title: String,
 }
 
 impl GpuState {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, options: &Options) -> Self {
         let window = Arc::new(window);
 
         let size = window.inner_size();
@@ -50,7 +46,10 @@ impl GpuState {
                 &wgpu::DeviceDescriptor {
                     label: None,
                     memory_hints: wgpu::MemoryHints::default(),
-                    required_features: wgpu::Features::empty(),
+                    // lets 32-bit float buffers be sampled like the others, and
+                    // times the passes for the HUD and --target-frame-time
+                    required_features: adapter.features()
+                        & (wgpu::Features::FLOAT32_FILTERABLE | wgpu::Features::TIMESTAMP_QUERY),
                     required_limits: wgpu::Limits::default(),
                 },
                 None,
@@ -58,8 +57,6 @@ impl GpuState {
             .await
             .unwrap();
 
-        let limits = device.limits();
-
         let surface_caps = surface.get_capabilities(&adapter);
 
         let surface_format = surface_caps
@@ -74,15 +71,22 @@ impl GpuState {
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
-            width: size.width.max(800),
-            height: size.height.max(600), // setting this because Fullscreen does not work on web: https://developer.mozilla.org/en-US/docs/Glossary/Transient_activation
+            // a minimized window reports 0, which a surface cannot have
+            width: size.width.max(1),
+            height: size.height.max(1),
             present_mode: wgpu::PresentMode::Fifo,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&device, &config);
-        let engine = Stoy::new(&device, &queue, &config.format);
+        let engine = Stoy::new(
+            &device,
+            &queue,
+            &config.format,
+            (config.width, config.height),
+            options,
+        );
         Self {
             surface,
             device,
@@ -90,6 +94,7 @@ impl GpuState {
             config,
             window,
             engine,
+            title: String::new(),
         }
     }
 
@@ -102,9 +107,12 @@ impl GpuState {
         false
     }
     pub fn update(&mut self, dt: instant::Duration) {
-//        println!("delta: {}", dt.as_secs_f32());
-  //      println!("fps: {}", (1.0 / dt.as_secs_f32()));
-        self.engine.update(&mut self.queue, (self.config.width, self.config.height));
+        self.engine.update(&mut self.queue, (self.config.width, self.config.height), dt);
+        let title = format!("shader_toy  {}", self.engine.status());
+        if title != self.title {
+            self.window.set_title(&title);
+            self.title = title;
+        }
     }
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         let (width, height) = match (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) {
@@ -116,10 +124,32 @@ impl GpuState {
         self.config.height = height.into();
 
         self.surface.configure(&self.device, &self.config);
+        self.engine
+            .resize(&self.device, (self.config.width, self.config.height));
+    }
+
+    /// Records every new frame until `finish_recording`.
+    pub fn record(&mut self, recorder: Recorder) {
+        self.engine.record(recorder);
+    }
+
+    pub fn finish_recording(&mut self) {
+        self.engine.finish_recording();
+    }
+
+    /// Writes the timings of every frame to `path` until `finish_profile`.
+    pub fn profile(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.engine.profile(path)
+    }
+
+    pub fn finish_profile(&mut self) {
+        if let Err(err) = self.engine.finish_profile() {
+            eprintln!("error: {:#}", err);
+        }
     }
 
     pub fn render(&mut self) {
         self.engine
-            .render(&self.surface, &self.device, &mut self.queue, &self.config.format);
+            .render(&self.surface, &self.device, &mut self.queue);
     }
 }
@@ -0,0 +1,351 @@
+use std::{ops::Range, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    capture,
+    cli::{Options, RenderOptions},
+    record::Recorder,
+    stoy::Stoy,
+};
+
+/// Format of the offscreen target, read back as is.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Device for rendering without a window or surface.
+pub struct Headless {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Headless {
+    pub fn new() -> Result<Self> {
+        let (device, queue) = pollster::block_on(request_device())?;
+        Ok(Self { device, queue })
+    }
+
+    /// Renders frames `0..end` of the shaders in `options` and hands the
+    /// ones in `frames` to `frame_done`. Fails if a shader does not load
+    /// instead of falling back to the built-in one.
+    pub fn render(
+        &mut self,
+        options: &Options,
+        frames: Range<u64>,
+        frame_done: impl FnMut(u64, image::RgbaImage) -> Result<()>,
+    ) -> Result<()> {
+        self.render_with(
+            options,
+            frames,
+            |_, device, queue, texture| capture::read_texture(device, queue, texture),
+            frame_done,
+        )
+    }
+
+    /// Like `render`, with the floating point image pass before tonemapping.
+    pub fn render_hdr(
+        &mut self,
+        options: &Options,
+        frames: Range<u64>,
+        frame_done: impl FnMut(u64, image::Rgba32FImage) -> Result<()>,
+    ) -> Result<()> {
+        self.render_with(
+            options,
+            frames,
+            |stoy, device, queue, _| stoy.read_hdr(device, queue),
+            frame_done,
+        )
+    }
+
+    /// Renders the frames and reads each one in `frames` back with `read`,
+    /// which gets the tonemapped output texture.
+    fn render_with<T>(
+        &mut self,
+        options: &Options,
+        frames: Range<u64>,
+        read: impl Fn(&Stoy, &wgpu::Device, &wgpu::Queue, &wgpu::Texture) -> Result<T>,
+        mut frame_done: impl FnMut(u64, T) -> Result<()>,
+    ) -> Result<()> {
+        let (device, queue) = (&self.device, &mut self.queue);
+        let size = (options.width, options.height);
+        let max = device.limits().max_texture_dimension_2d;
+        if size.0 > max || size.1 > max {
+            bail!("{}x{} exceeds the maximum texture size {}", size.0, size.1, max);
+        }
+
+        let fps = options.deterministic.as_ref().map_or(60.0, |d| d.fps);
+        let frame_time = Duration::from_secs_f64(1.0 / fps);
+        let mut stoy = Stoy::new(device, queue, &FORMAT, size, options);
+        let errors = stoy.errors();
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"));
+        }
+        if let Some(path) = &options.profile {
+            stoy.profile(path)?;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("headless"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        for frame in 0..frames.end {
+            stoy.update(queue, size, frame_time);
+            stoy.render_to(device, queue, &view);
+            if frame >= frames.start {
+                frame_done(frame, read(&stoy, device, queue, &texture)?)?;
+            }
+        }
+        stoy.finish_profile()
+    }
+
+    pub fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Renders frame 0 of a single-pass shader on a canvas of the size in
+    /// `options` in tiles of at most `tile` pixels, and hands each row of
+    /// tiles to `strip_done` as RGB rows. Only one strip is in memory at a
+    /// time.
+    pub fn render_tiles(
+        &mut self,
+        options: &Options,
+        tile: u32,
+        mut strip_done: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        if options.buffers.iter().any(Option::is_some) || options.compute.shader.is_some() {
+            bail!(
+                "tiled rendering only supports single-pass shaders, buffers and compute \
+                 passes would run per tile and leave seams"
+            );
+        }
+        let (device, queue) = (&self.device, &mut self.queue);
+        let canvas = (options.width, options.height);
+        let tile = tile.min(device.limits().max_texture_dimension_2d);
+
+        let fps = options.deterministic.as_ref().map_or(60.0, |d| d.fps);
+        // its targets only ever hold a tile, the canvas is in the uniforms
+        let size = (tile.min(canvas.0), tile.min(canvas.1));
+        let mut stoy = Stoy::new(device, queue, &FORMAT, size, options);
+        let errors = stoy.errors();
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"));
+        }
+        stoy.update(queue, canvas, Duration::from_secs_f64(1.0 / fps));
+
+        let rows = canvas.1.div_ceil(tile);
+        for (row, y) in (0..canvas.1).step_by(tile as usize).enumerate() {
+            let height = tile.min(canvas.1 - y);
+            let stride = canvas.0 as usize * 3;
+            let mut strip = vec![0; stride * height as usize];
+            for x in (0..canvas.0).step_by(tile as usize) {
+                let width = tile.min(canvas.0 - x);
+                let image = stoy.render_tile(device, queue, canvas, (x, y), (width, height))?;
+                for (i, line) in image.rows().enumerate() {
+                    let start = i * stride + x as usize * 3;
+                    let rgb = line.flat_map(|p| [p[0], p[1], p[2]]);
+                    for (dst, src) in strip[start..start + width as usize * 3].iter_mut().zip(rgb) {
+                        *dst = src;
+                    }
+                }
+            }
+            strip_done(&strip)?;
+            println!("tile row {}/{}", row + 1, rows);
+        }
+        Ok(())
+    }
+}
+
+/// `shader_toy render`: renders `render.frames` into PNG files, or into a
+/// video with `--record`.
+pub fn run(options: &Options, render: &RenderOptions) -> Result<()> {
+    if let Some(record) = &options.record {
+        let fps = options.deterministic.as_ref().map_or(60.0, |d| d.fps);
+        let mut recorder = Recorder::start(record, (options.width, options.height), fps)?;
+        let mut headless = Headless::new()?;
+        headless.render(options, render.frames.clone(), |_, image| recorder.write(&image))?;
+        return recorder.finish();
+    }
+
+    let mut headless = Headless::new()?;
+    std::fs::create_dir_all(&render.out)
+        .with_context(|| format!("failed to create `{}`", render.out.display()))?;
+    if let Some(raw) = render.raw {
+        return headless.render_hdr(options, render.frames.clone(), |frame, image| {
+            let path = render
+                .out
+                .join(format!("frame_{:05}.{}", frame, raw.extension()));
+            raw.save(image, &path)?;
+            println!("{}", path.display());
+            Ok(())
+        });
+    }
+    headless.render(options, render.frames.clone(), |frame, image| {
+        let path = render.out.join(format!("frame_{:05}.png", frame));
+        image
+            .save(&path)
+            .with_context(|| format!("failed to write `{}`", path.display()))?;
+        println!("{}", path.display());
+        Ok(())
+    })
+}
+
+/// Any adapter will do, including a software one, since nothing is
+/// presented.
+async fn request_device() -> Result<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    let mut adapter = None;
+    for force_fallback_adapter in [false, true] {
+        adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter,
+                compatible_surface: None,
+            })
+            .await;
+        if adapter.is_some() {
+            break;
+        }
+    }
+    let adapter = adapter.ok_or_else(|| anyhow!("no graphics adapter found"))?;
+    let info = adapter.get_info();
+    println!("adapter: {} ({:?}, {:?})", info.name, info.backend, info.device_type);
+
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                memory_hints: wgpu::MemoryHints::default(),
+                // lets 32-bit float buffers be sampled like the others, and
+                // times the passes for --profile
+                required_features: adapter.features()
+                    & (wgpu::Features::FLOAT32_FILTERABLE | wgpu::Features::TIMESTAMP_QUERY),
+                required_limits: adapter.limits(),
+            },
+            None,
+        )
+        .await
+        .context("failed to create the device")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A poster wider than a texture sees the whole canvas in iResolution,
+    /// so a ramp over it runs on across the tiles.
+    #[test]
+    fn poster_ramp_spans_the_canvas() {
+        let mut headless = Headless::new().unwrap();
+        let max = headless.max_texture_size();
+        let canvas = (max + max / 4, 2);
+        let dir = std::env::temp_dir().join(format!("shader_toy_ramp_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shader = dir.join("ramp.glsl");
+        std::fs::write(
+            &shader,
+            "void mainImage(out vec4 c, in vec2 p) { c = vec4(vec3(p.x / iResolution.x), 1.0); }\n",
+        )
+        .unwrap();
+        let args = [
+            "poster".to_string(),
+            shader.display().to_string(),
+            "--size".to_string(),
+            format!("{}x{}", canvas.0, canvas.1),
+            "--out".to_string(),
+            dir.join("ramp.png").display().to_string(),
+        ];
+        let options = Options::parse(args).unwrap().unwrap();
+        let tile = 4096;
+        let mut rows = Vec::new();
+        headless
+            .render_tiles(&options, tile, |strip| {
+                rows.extend_from_slice(strip);
+                Ok(())
+            })
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let linear = |x: u32| {
+            let c = rows[x as usize * 3] as f32 / 255.0;
+            match c <= 0.04045 {
+                true => c / 12.92,
+                false => ((c + 0.055) / 1.055).powf(2.4),
+            }
+        };
+        // both sides of every tile boundary, and past the texture size
+        let boundaries = (tile..canvas.0).step_by(tile as usize);
+        let xs = boundaries.flat_map(|x| [x - 1, x]);
+        for x in xs.chain([0, max, canvas.0 - 1]) {
+            let expected = (x as f32 + 0.5) / canvas.0 as f32;
+            assert!(
+                (linear(x) - expected).abs() < 0.01,
+                "x = {}: {} instead of {}",
+                x,
+                linear(x),
+                expected
+            );
+        }
+    }
+
+    /// Under `--deterministic` iFrameRate is the rate the frames play back
+    /// at, not the one the window renders them at.
+    #[test]
+    fn deterministic_frame_rate() {
+        let dir = std::env::temp_dir().join(format!("shader_toy_fps_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shader = dir.join("fps.glsl");
+        std::fs::write(
+            &shader,
+            "void mainImage(out vec4 c, in vec2 p) { c = vec4(vec3(iFrameRate), 1.0); }\n",
+        )
+        .unwrap();
+        let args = [
+            shader.to_str().unwrap(),
+            "--deterministic",
+            "--fps",
+            "25",
+            "--format",
+            "image=rgba32f",
+        ];
+        let mut options = Options::parse(args.map(String::from)).unwrap().unwrap();
+        options.watch = false;
+        let Headless { device, mut queue } = Headless::new().unwrap();
+        let size = (4, 4);
+        let mut stoy = Stoy::new(&device, &queue, &FORMAT, size, &options);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // a frame of the window, which took 10 ms
+        stoy.update(&mut queue, size, Duration::from_millis(10));
+        stoy.render_to(&device, &mut queue, &view);
+        let image = stoy.read_hdr(&device, &queue).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(image.get_pixel(0, 0)[0], 25.0);
+    }
+}
@@ -0,0 +1,101 @@
+//! Frame timings drawn over the top-right corner of the window, toggled
+//! with F3.
+
+use crate::{
+    font::{self, GLYPH_HEIGHT, GLYPH_WIDTH},
+    overlay::{BACKGROUND, PADDING, TEXT},
+    profiler::{Profiler, Source, HISTORY},
+};
+
+/// Height of the frame time graph in pixels, one column per frame.
+const GRAPH_HEIGHT: u32 = 48;
+/// Frame time at the top of the graph, unless a frame took longer.
+const GRAPH_MS: f32 = 33.3;
+/// Columns of text.
+const COLS: u32 = 30;
+const GRAPH_LINE: [u8; 4] = [96, 96, 96, 255];
+const FAST: [u8; 4] = [112, 208, 112, 255];
+const SLOW: [u8; 4] = [232, 200, 80, 255];
+const SLOWER: [u8; 4] = [255, 112, 112, 255];
+
+/// The HUD as RGBA pixels and its size: mean times over the history, the
+/// GPU time of each pass and a graph of the frame intervals.
+pub fn rasterize(profiler: &Profiler) -> (Vec<u8>, (u32, u32)) {
+    let history = profiler.history();
+    let count = history.len().max(1) as f32;
+    let mean = |f: &dyn Fn(&crate::profiler::FrameTimes) -> f32| {
+        history.iter().map(f).sum::<f32>() / count * 1000.0
+    };
+    let frame = mean(&|t| t.interval);
+    let cpu = mean(&|t| t.cpu);
+
+    let mut lines = vec![
+        format!("frame {:7.2} ms {:5.0} fps", frame, 1000.0 / frame.max(1e-3)),
+        format!("cpu   {:7.2} ms", cpu),
+    ];
+    let source = match profiler.source {
+        Source::Timestamps => "timestamps",
+        Source::CpuWait => "cpu wait",
+    };
+    let measured: Vec<&Vec<f32>> = history.iter().filter_map(|t| t.passes.as_ref()).collect();
+    if measured.is_empty() {
+        lines.push(format!("gpu   ({})", source));
+    } else {
+        let pass = |i: usize| {
+            measured.iter().map(|p| p[i]).sum::<f32>() / measured.len() as f32 * 1000.0
+        };
+        let total: f32 = (0..profiler.names().len()).map(pass).sum();
+        lines.push(format!("gpu   {:7.2} ms ({})", total, source));
+        for (i, name) in profiler.names().iter().enumerate() {
+            lines.push(format!("  {:<9} {:6.2} ms", name, pass(i)));
+        }
+    }
+
+    let width = (COLS * GLYPH_WIDTH).max(HISTORY as u32) + 2 * PADDING;
+    let text_height = lines.len() as u32 * GLYPH_HEIGHT;
+    let height = text_height + PADDING + GRAPH_HEIGHT + 2 * PADDING;
+    let mut pixels: Vec<u8> = BACKGROUND
+        .iter()
+        .copied()
+        .cycle()
+        .take((width * height * 4) as usize)
+        .collect();
+    for (row, line) in lines.iter().enumerate() {
+        let line: String = line.chars().take(COLS as usize).collect();
+        let y = PADDING + row as u32 * GLYPH_HEIGHT;
+        font::draw_text(&mut pixels, width, PADDING, y, &line, TEXT);
+    }
+
+    // frame intervals as bars from the bottom, newest on the right
+    let top = PADDING + text_height + PADDING;
+    let max_ms = history
+        .iter()
+        .map(|t| t.interval * 1000.0)
+        .fold(GRAPH_MS, f32::max);
+    let mut set = |x: u32, y: u32, color: [u8; 4]| {
+        let i = (((top + y) * width + PADDING + x) * 4) as usize;
+        pixels[i..i + 4].copy_from_slice(&color);
+    };
+    for ms in [1000.0 / 60.0, 1000.0 / 30.0] {
+        let y = GRAPH_HEIGHT - 1 - ((ms / max_ms * GRAPH_HEIGHT as f32) as u32).min(GRAPH_HEIGHT - 1);
+        for x in 0..HISTORY as u32 {
+            set(x, y, GRAPH_LINE);
+        }
+    }
+    let start = HISTORY - history.len();
+    for (i, times) in history.iter().enumerate() {
+        let ms = times.interval * 1000.0;
+        let bar = ((ms / max_ms * GRAPH_HEIGHT as f32).ceil() as u32).min(GRAPH_HEIGHT);
+        let color = if ms <= 1000.0 / 55.0 {
+            FAST
+        } else if ms <= 1000.0 / 28.0 {
+            SLOW
+        } else {
+            SLOWER
+        };
+        for y in GRAPH_HEIGHT - bar..GRAPH_HEIGHT {
+            set((start + i) as u32, y, color);
+        }
+    }
+    (pixels, (width, height))
+}
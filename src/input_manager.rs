@@ -1,7 +1,4 @@
-use winit::{
-    event::{DeviceEvent, TouchPhase, WindowEvent},
-    window,
-};
+use winit::event::{DeviceEvent, ElementState, MouseButton, TouchPhase, WindowEvent};
 
 #[derive(Debug, Default)]
 pub struct InputManager {
@@ -11,6 +8,17 @@ pub struct InputManager {
     pub y: f64,
     pub wx: f32,
     pub wy: f32,
+    /// Left button held down.
+    pub pressed: bool,
+    /// Cursor position while the left button was last held down.
+    pub drag_x: f64,
+    pub drag_y: f64,
+    /// Cursor position of the last left click.
+    pub click_x: f64,
+    pub click_y: f64,
+    /// Set by a left click until `take_click` is called.
+    clicked: bool,
+    #[allow(dead_code)]
     sensitivity: f32,
 }
 
@@ -23,17 +31,20 @@ impl InputManager {
     pub fn process_events(&mut self, event: InputEvent) {
         use InputEvent::{Device, Window};
         match event {
-            Device(device_event) => match device_event {
-                DeviceEvent::MouseMotion { delta } => {
+            Device(device_event) => {
+                if let DeviceEvent::MouseMotion { delta } = device_event {
                     self.delta_x = delta.0 as f32;
                     self.delta_y = delta.1 as f32;
                 }
-                _ => (),
-            },
+            }
             Window(window_event) => match window_event {
                 WindowEvent::CursorMoved { position, .. } => {
                     self.x = position.x;
                     self.y = position.y;
+                    if self.pressed {
+                        self.drag_x = position.x;
+                        self.drag_y = position.y;
+                    }
                     println!("Cursor position: x: {}, y: {}", position.x,position.y);
                 }
                 WindowEvent::MouseWheel { delta, phase, .. } => {
@@ -49,6 +60,20 @@ impl InputManager {
                         self.wy += wy;
                     }
                 }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    self.pressed = *state == ElementState::Pressed;
+                    if self.pressed {
+                        self.clicked = true;
+                        self.click_x = self.x;
+                        self.click_y = self.y;
+                        self.drag_x = self.x;
+                        self.drag_y = self.y;
+                    }
+                }
                 _ => {
                     if *window_event != WindowEvent::RedrawRequested {
                         println!("Event: {:?}", window_event);
@@ -57,4 +82,9 @@ impl InputManager {
             },
         }
     }
+
+    /// Whether the left button was clicked since the last call.
+    pub fn take_click(&mut self) -> bool {
+        std::mem::take(&mut self.clicked)
+    }
 }
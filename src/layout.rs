@@ -0,0 +1,419 @@
+use wgpu::naga;
+
+use crate::{channel, params, uniform};
+
+/// Bind group layouts of the pipeline layout shared by all passes: the
+/// camera, the channels, `MainUniforms` and the custom uniforms.
+pub fn pass_layout_entries() -> Vec<Vec<wgpu::BindGroupLayoutEntry>> {
+    vec![
+        vec![uniform::layout_entry()],
+        channel::layout_entries(),
+        vec![uniform::layout_entry()],
+        vec![params::layout_entry()],
+    ]
+}
+
+/// Checks what wgpu would otherwise only report while building the
+/// pipeline: every entry point in `entry_points` exists with the right
+/// stage, and every resource they use is in `groups` with a matching type
+/// and visibility.
+pub fn check_module(
+    module: &naga::Module,
+    info: &naga::valid::ModuleInfo,
+    entry_points: &[(naga::ShaderStage, &str)],
+    groups: &[Vec<wgpu::BindGroupLayoutEntry>],
+) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for &(stage, name) in entry_points {
+        let Some(index) = module.entry_points.iter().position(|ep| ep.name == name) else {
+            errors.push(format!("missing {} entry point `{}`", stage_name(stage), name));
+            continue;
+        };
+        let entry_point = &module.entry_points[index];
+        if entry_point.stage != stage {
+            errors.push(format!(
+                "entry point `{}` is a {} shader, expected a {} shader",
+                name,
+                stage_name(entry_point.stage),
+                stage_name(stage)
+            ));
+            continue;
+        }
+
+        let function = info.get_entry_point(index);
+        for (handle, var) in module.global_variables.iter() {
+            let Some(binding) = &var.binding else { continue };
+            if function[handle].is_empty() {
+                continue;
+            }
+            let var_name = var.name.as_deref().unwrap_or("<unnamed>");
+            let location = format!(
+                "`{}` at @group({}) @binding({})",
+                var_name, binding.group, binding.binding
+            );
+            let entry = groups
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|e| e.binding == binding.binding));
+            let Some(entry) = entry else {
+                errors.push(format!(
+                    "`{}` uses {}, which is not in the pipeline layout",
+                    name, location
+                ));
+                continue;
+            };
+            if !binding_matches(module, var, &entry.ty) {
+                errors.push(format!(
+                    "`{}` uses {} as {}, but the pipeline layout binds {} there",
+                    name,
+                    location,
+                    describe_var(module, var),
+                    describe_binding(&entry.ty)
+                ));
+            } else if !entry.visibility.contains(shader_stages(stage)) {
+                errors.push(format!(
+                    "`{}` uses {}, which is not visible to the {} stage",
+                    name,
+                    location,
+                    stage_name(stage)
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "shader does not match the pipeline layout:\n  {}",
+            errors.join("\n  ")
+        ))
+    }
+}
+
+fn binding_matches(
+    module: &naga::Module,
+    var: &naga::GlobalVariable,
+    ty: &wgpu::BindingType,
+) -> bool {
+    use naga::{AddressSpace, ImageClass, TypeInner};
+    use wgpu::{BindingType, BufferBindingType, SamplerBindingType, TextureSampleType};
+
+    match (var.space, &module.types[var.ty].inner, ty) {
+        (
+            AddressSpace::Uniform,
+            _,
+            BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                ..
+            },
+        ) => true,
+        (
+            AddressSpace::Storage { access },
+            _,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                ..
+            },
+        ) => !*read_only || !access.contains(naga::StorageAccess::STORE),
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+            BindingType::Texture {
+                view_dimension,
+                sample_type,
+                multisampled,
+            },
+        ) => {
+            let class_matches = match (class, sample_type) {
+                (ImageClass::Sampled { kind, multi }, _) => {
+                    *multi == *multisampled
+                        && matches!(
+                            (kind, sample_type),
+                            (naga::ScalarKind::Float, TextureSampleType::Float { .. })
+                                | (naga::ScalarKind::Sint, TextureSampleType::Sint)
+                                | (naga::ScalarKind::Uint, TextureSampleType::Uint)
+                        )
+                }
+                (ImageClass::Depth { multi }, TextureSampleType::Depth) => {
+                    *multi == *multisampled
+                }
+                _ => false,
+            };
+            class_matches && view_dimension_matches(*dim, *arrayed, *view_dimension)
+        }
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class: ImageClass::Storage { .. },
+            },
+            BindingType::StorageTexture { view_dimension, .. },
+        ) => view_dimension_matches(*dim, *arrayed, *view_dimension),
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }, BindingType::Sampler(ty)) => {
+            *comparison == (*ty == SamplerBindingType::Comparison)
+        }
+        _ => false,
+    }
+}
+
+fn view_dimension_matches(
+    dim: naga::ImageDimension,
+    arrayed: bool,
+    view_dimension: wgpu::TextureViewDimension,
+) -> bool {
+    use naga::ImageDimension as Dim;
+    use wgpu::TextureViewDimension as View;
+
+    matches!(
+        (dim, arrayed, view_dimension),
+        (Dim::D1, false, View::D1)
+            | (Dim::D2, false, View::D2)
+            | (Dim::D2, true, View::D2Array)
+            | (Dim::D3, false, View::D3)
+            | (Dim::Cube, false, View::Cube)
+            | (Dim::Cube, true, View::CubeArray)
+    )
+}
+
+/// WGSL spelling of a shader resource.
+fn describe_var(module: &naga::Module, var: &naga::GlobalVariable) -> String {
+    use naga::{ImageClass, TypeInner};
+
+    match (var.space, &module.types[var.ty].inner) {
+        (naga::AddressSpace::Uniform, _) => "var<uniform>".to_string(),
+        (naga::AddressSpace::Storage { .. }, _) => "var<storage>".to_string(),
+        (_, TypeInner::Image { dim, arrayed, class }) => {
+            let dim = dim_name(*dim, *arrayed);
+            match class {
+                ImageClass::Sampled { kind, multi } => {
+                    let scalar = match kind {
+                        naga::ScalarKind::Sint => "i32",
+                        naga::ScalarKind::Uint => "u32",
+                        _ => "f32",
+                    };
+                    format!("texture{}_{}<{}>", multisampled(*multi), dim, scalar)
+                }
+                ImageClass::Depth { multi } => {
+                    format!("texture_depth{}_{}", multisampled(*multi), dim)
+                }
+                ImageClass::Storage { .. } => format!("texture_storage_{}", dim),
+            }
+        }
+        (_, TypeInner::Sampler { comparison: true }) => "sampler_comparison".to_string(),
+        (_, TypeInner::Sampler { comparison: false }) => "sampler".to_string(),
+        (space, _) => format!("var<{:?}>", space),
+    }
+}
+
+/// Same as `describe_var`, for a layout entry.
+fn describe_binding(ty: &wgpu::BindingType) -> String {
+    use wgpu::{BindingType, BufferBindingType, SamplerBindingType, TextureSampleType};
+
+    match ty {
+        BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            ..
+        } => "var<uniform>".to_string(),
+        BindingType::Buffer { .. } => "var<storage>".to_string(),
+        BindingType::Texture {
+            view_dimension,
+            sample_type,
+            multisampled: multi,
+        } => {
+            let dim = view_name(*view_dimension);
+            match sample_type {
+                TextureSampleType::Float { .. } => {
+                    format!("texture{}_{}<f32>", multisampled(*multi), dim)
+                }
+                TextureSampleType::Sint => format!("texture{}_{}<i32>", multisampled(*multi), dim),
+                TextureSampleType::Uint => format!("texture{}_{}<u32>", multisampled(*multi), dim),
+                TextureSampleType::Depth => {
+                    format!("texture_depth{}_{}", multisampled(*multi), dim)
+                }
+            }
+        }
+        BindingType::StorageTexture { view_dimension, .. } => {
+            format!("texture_storage_{}", view_name(*view_dimension))
+        }
+        BindingType::Sampler(SamplerBindingType::Comparison) => "sampler_comparison".to_string(),
+        BindingType::Sampler(_) => "sampler".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn multisampled(multi: bool) -> &'static str {
+    if multi {
+        "_multisampled"
+    } else {
+        ""
+    }
+}
+
+fn dim_name(dim: naga::ImageDimension, arrayed: bool) -> &'static str {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => "1d",
+        (naga::ImageDimension::D2, false) => "2d",
+        (naga::ImageDimension::D2, true) => "2d_array",
+        (naga::ImageDimension::D3, _) => "3d",
+        (naga::ImageDimension::Cube, false) => "cube",
+        (naga::ImageDimension::Cube, true) => "cube_array",
+    }
+}
+
+fn view_name(view_dimension: wgpu::TextureViewDimension) -> &'static str {
+    match view_dimension {
+        wgpu::TextureViewDimension::D1 => "1d",
+        wgpu::TextureViewDimension::D2 => "2d",
+        wgpu::TextureViewDimension::D2Array => "2d_array",
+        wgpu::TextureViewDimension::D3 => "3d",
+        wgpu::TextureViewDimension::Cube => "cube",
+        wgpu::TextureViewDimension::CubeArray => "cube_array",
+    }
+}
+
+fn stage_name(stage: naga::ShaderStage) -> &'static str {
+    match stage {
+        naga::ShaderStage::Vertex => "vertex",
+        naga::ShaderStage::Fragment => "fragment",
+        naga::ShaderStage::Compute => "compute",
+    }
+}
+
+fn shader_stages(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENT: &[(naga::ShaderStage, &str)] = &[(naga::ShaderStage::Fragment, "fs_main")];
+
+    /// A texture and a sampler only the fragment stage sees.
+    fn groups() -> Vec<Vec<wgpu::BindGroupLayoutEntry>> {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+        let texture = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let sampler = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+        vec![vec![entry(0, texture), entry(1, sampler)]]
+    }
+
+    fn validate(code: &str) -> (naga::Module, naga::valid::ModuleInfo) {
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .unwrap();
+        (module, info)
+    }
+
+    fn check(code: &str, entry_points: &[(naga::ShaderStage, &str)]) -> Result<(), String> {
+        let (module, info) = validate(code);
+        check_module(&module, &info, entry_points, &groups())
+    }
+
+    #[test]
+    fn matching_module() {
+        let code = "@group(0) @binding(0) var t: texture_2d<f32>;
+@group(0) @binding(1) var s: sampler;
+@fragment fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(t, s, vec2(0.5));
+}";
+        assert_eq!(check(code, FRAGMENT), Ok(()));
+    }
+
+    #[test]
+    fn binding_mismatch() {
+        let code = "@group(0) @binding(0) var t: texture_cube<f32>;
+@group(0) @binding(1) var s: sampler;
+@group(1) @binding(0) var<uniform> u: vec4<f32>;
+@fragment fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(t, s, vec3(1.0)) + u;
+}";
+        assert_eq!(
+            check(code, FRAGMENT),
+            Err("shader does not match the pipeline layout:\n  \
+                 `fs_main` uses `t` at @group(0) @binding(0) as texture_cube<f32>, \
+                 but the pipeline layout binds texture_2d<f32> there\n  \
+                 `fs_main` uses `u` at @group(1) @binding(0), which is not in the pipeline layout"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn unused_bindings_are_not_checked() {
+        let code = "@group(0) @binding(0) var t: texture_cube<f32>;
+@fragment fn fs_main() -> @location(0) vec4<f32> {
+    return vec4(1.0);
+}";
+        assert_eq!(check(code, FRAGMENT), Ok(()));
+    }
+
+    #[test]
+    fn visibility() {
+        let code = "@group(0) @binding(0) var t: texture_2d<f32>;
+@group(0) @binding(1) var s: sampler;
+@vertex fn vs_main() -> @builtin(position) vec4<f32> {
+    return textureSampleLevel(t, s, vec2(0.5), 0.0);
+}";
+        let vertex = &[(naga::ShaderStage::Vertex, "vs_main")];
+        let err = check(code, vertex).unwrap_err();
+        assert!(
+            err.ends_with(
+                "`vs_main` uses `t` at @group(0) @binding(0), \
+                 which is not visible to the vertex stage\n  \
+                 `vs_main` uses `s` at @group(0) @binding(1), \
+                 which is not visible to the vertex stage"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn entry_points() {
+        let code = "@vertex fn fs_main() -> @builtin(position) vec4<f32> {
+    return vec4(1.0);
+}";
+        let entry_points = [
+            (naga::ShaderStage::Vertex, "vs_main"),
+            (naga::ShaderStage::Fragment, "fs_main"),
+        ];
+        assert_eq!(
+            check(code, &entry_points),
+            Err("shader does not match the pipeline layout:\n  \
+                 missing vertex entry point `vs_main`\n  \
+                 entry point `fs_main` is a vertex shader, expected a fragment shader"
+                .to_string())
+        );
+    }
+    #[test]
+    fn builtin_shader_matches_the_pass_layout() {
+        let (module, info) = validate(include_str!("./shaders/sprite.wgsl"));
+        let entry_points = [
+            (naga::ShaderStage::Vertex, "vs_main"),
+            (naga::ShaderStage::Fragment, "fs_main"),
+        ];
+        let groups = pass_layout_entries();
+        assert_eq!(check_module(&module, &info, &entry_points, &groups), Ok(()));
+    }
+}
@@ -1,22 +1,114 @@
+use cli::Options;
 use window::App;
 use winit::event_loop::{ControlFlow, EventLoop};
 
+mod capture;
+mod channel;
+mod compute;
+pub mod cli;
+mod deterministic;
+mod export;
+mod font;
+mod glsl;
 mod gpu;
+mod headless;
+mod hud;
 mod input_manager;
+mod layout;
+mod manifest;
+mod overlay;
+mod panel;
+mod params;
+mod pass;
+mod playback;
+mod poster;
+mod profiler;
 mod quad;
+mod record;
+pub mod regression;
+mod sampling;
+mod shadertoy;
+mod source;
 mod sprite;
 mod stoy;
 mod texture;
+mod timer;
+mod tonemap;
 mod uniform;
 mod window;
 mod uniforms;
 
-pub fn run() {
+pub fn run(options: Options) {
+    if let Some(import) = &options.import {
+        if let Err(err) = shadertoy::import(import) {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(out) = &options.export_json {
+        if let Err(err) = shadertoy::export(&options, out) {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(test) = &options.test {
+        match regression::run(test) {
+            Ok(report) => {
+                println!("{}", report);
+                if !report.failures.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            Err(err) => {
+                eprintln!("error: {:#}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(render) = &options.render {
+        if let Err(err) = headless::run(&options, render) {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(poster) = &options.poster {
+        if let Err(err) = poster::run(&options, poster) {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(export) = &options.export {
+        if let Err(err) = export::run(&options, export) {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let recorder = match &options.record {
+        Some(record) => {
+            let fps = options.deterministic.as_ref().map_or(60.0, |d| d.fps);
+            match record::Recorder::start(record, (options.width, options.height), fps) {
+                Ok(recorder) => Some(recorder),
+                Err(err) => {
+                    eprintln!("error: {:#}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
     let event_loop = EventLoop::new().unwrap();
 
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::default();
+    let mut app = App::new(options, recorder);
 
     
     event_loop.run_app(&mut app).unwrap();
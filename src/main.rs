@@ -1,6 +1,6 @@
-use shader_toy::run;
+use shader_toy::{cli::Options, run};
 
 fn main() {
-    run();
+    run(Options::from_env());
     // println!("Hello world");
 }
@@ -0,0 +1,626 @@
+//! `stoy.toml`, a shader project in one file: its passes with their
+//! channels, compute shader, custom uniforms, window settings, export
+//! presets and a description. The manifest fills in the options before the
+//! ones given are applied, which override it. Paths are relative to the
+//! manifest.
+//!
+//! ```toml
+//! common = "common.glsl"
+//! include = ["lib"]
+//!
+//! [info]
+//! name = "Ripples"
+//! tags = ["water", "simulation"]
+//!
+//! [window]
+//! size = "1280x720"
+//! tonemap = "aces"
+//!
+//! [uniforms]
+//! speed = 1.5
+//! tint = [1.0, 0.5, 0.2]
+//!
+//! [passes.image]
+//! shader = "image.glsl"
+//! channels = { 0 = "buffer-a", 1 = { source = "noise.png", filter = "nearest" } }
+//!
+//! [passes.buffer-a]
+//! shader = "sim.glsl"
+//! format = "rgba16f"
+//! size = 0.5
+//! channels = { 0 = "buffer-a" }
+//!
+//! [presets.gif]
+//! out = "out.gif"
+//! duration = 4
+//! fps = 25
+//! dither = true
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::{
+    channel::{ChannelInput, ChannelSource, Filter, Wrap, CHANNEL_COUNT},
+    compute::ComputeOptions,
+    params::{Param, ParamValue},
+    pass::{ChannelBinding, PassId, PassSize, TargetFormat, BUFFER_COUNT},
+    source::SourceOptions,
+};
+
+/// Manifest looked for in a project directory, and in the working
+/// directory when no shader is given.
+pub const FILE_NAME: &str = "stoy.toml";
+
+/// Options `[window]` accepts.
+const WINDOW_OPTIONS: &[&str] = &[
+    "size",
+    "time",
+    "render-scale",
+    "upscale",
+    "tonemap",
+    "exposure",
+    "target-frame-time",
+    "hud",
+    "deterministic",
+    "fps",
+    "date",
+    "seed",
+    "mouse-script",
+    "screenshot-dir",
+    "screenshot-size",
+];
+
+/// Options whose value is a path, taken relative to the manifest.
+const PATH_OPTIONS: &[&str] = &[
+    "mouse-script",
+    "screenshot-dir",
+    "out",
+    "record",
+    "profile",
+    "references",
+    "failures",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    /// `--common`
+    common: Option<PathBuf>,
+    /// `--include` directories.
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    info: Info,
+    #[serde(default)]
+    window: toml::Table,
+    #[serde(default)]
+    uniforms: toml::Table,
+    #[serde(default)]
+    passes: BTreeMap<String, PassTable>,
+    compute: Option<ComputeTable>,
+    #[serde(default)]
+    presets: BTreeMap<String, toml::Table>,
+}
+
+/// What the project is, which becomes the info of a Shadertoy export.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Info {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PassTable {
+    shader: PathBuf,
+    format: Option<String>,
+    /// `"<W>x<H>"` or a fraction of the render size.
+    size: Option<toml::Value>,
+    /// Sources by channel index, with optional sampler settings.
+    #[serde(default)]
+    channels: toml::Table,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ComputeTable {
+    shader: PathBuf,
+    /// Invocations by entry point.
+    #[serde(default)]
+    dispatch: toml::Table,
+    /// Sizes in bytes by index.
+    #[serde(default)]
+    storage_buffers: toml::Table,
+    /// Formats by index.
+    #[serde(default)]
+    storage_textures: toml::Table,
+}
+
+/// A `[window]` or preset entry, applied like the command line option of
+/// the same name.
+#[derive(Debug, Clone)]
+pub struct Setting {
+    /// The option without its `--`.
+    pub option: String,
+    /// `None` for a flag.
+    pub value: Option<String>,
+    /// Where it was set, for errors.
+    pub at: String,
+}
+
+pub struct Manifest {
+    pub info: Info,
+    pub sources: SourceOptions,
+    /// Shader of the image pass.
+    pub shader: Option<PathBuf>,
+    pub buffers: [Option<PathBuf>; BUFFER_COUNT],
+    pub buffer_sizes: [Option<PassSize>; BUFFER_COUNT],
+    pub formats: Vec<(PassId, TargetFormat)>,
+    pub channels: Vec<ChannelBinding>,
+    pub compute: ComputeOptions,
+    pub uniforms: Vec<Param>,
+    window: Vec<Setting>,
+    presets: BTreeMap<String, Vec<Setting>>,
+}
+
+impl Manifest {
+    /// Reads and checks the manifest at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read `{}`: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, dir).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str, dir: &Path) -> Result<Self> {
+        let file: File = toml::from_str(text)?;
+        let mut manifest = Self {
+            info: file.info,
+            sources: SourceOptions::default(),
+            shader: None,
+            buffers: Default::default(),
+            buffer_sizes: Default::default(),
+            formats: Vec::new(),
+            channels: Vec::new(),
+            compute: ComputeOptions::default(),
+            uniforms: Vec::new(),
+            window: Vec::new(),
+            presets: BTreeMap::new(),
+        };
+
+        if let Some(common) = &file.common {
+            let common = existing(dir, common).map_err(|e| anyhow!("common: {}", e))?;
+            manifest.sources.common = Some(common);
+        }
+        for include in &file.include {
+            let resolved = dir.join(include);
+            if !resolved.is_dir() {
+                bail!("include: `{}` is not a directory", resolved.display());
+            }
+            manifest.sources.include_dirs.push(resolved);
+        }
+
+        for (key, value) in &file.window {
+            if !WINDOW_OPTIONS.contains(&key.as_str()) {
+                bail!(
+                    "[window] has no setting `{}`, expected one of: {}",
+                    key,
+                    WINDOW_OPTIONS.join(", ")
+                );
+            }
+            let at = format!("window.{}", key);
+            manifest.window.extend(setting(key, value, dir, at)?);
+        }
+
+        for (name, value) in &file.uniforms {
+            let value = uniform_value(value).ok_or_else(|| {
+                anyhow!(
+                    "uniforms.{}: expected a number, a boolean or an array of 2 to 4 numbers",
+                    name
+                )
+            })?;
+            let param = Param::new(name, value).map_err(|e| anyhow!("uniforms.{}: {}", name, e))?;
+            manifest.uniforms.push(param);
+        }
+
+        for (key, table) in &file.passes {
+            let id = PassId::parse(key).map_err(|e| anyhow!("passes: {}", e))?;
+            let shader = existing(dir, &table.shader)
+                .map_err(|e| anyhow!("passes.{}.shader: {}", key, e))?;
+            match id {
+                PassId::Buffer(b) => manifest.buffers[b] = Some(shader),
+                _ => manifest.shader = Some(shader),
+            }
+            if let Some(format) = &table.format {
+                let format = TargetFormat::parse(format)
+                    .map_err(|e| anyhow!("passes.{}.format: {}", key, e))?;
+                manifest.formats.push((id, format));
+            }
+            if let Some(size) = &table.size {
+                let PassId::Buffer(b) = id else {
+                    bail!("passes.image.size: the image pass renders at the window size, set window.render-scale instead");
+                };
+                let size = scalar(size).ok_or_else(|| {
+                    anyhow!("passes.{}.size: expected \"<W>x<H>\" or a scale", key)
+                })?;
+                let size =
+                    PassSize::parse(&size).map_err(|e| anyhow!("passes.{}.size: {}", key, e))?;
+                manifest.buffer_sizes[b] = Some(size);
+            }
+            for (channel, value) in &table.channels {
+                let at = format!("passes.{}.channels.{}", key, channel);
+                let channel = match channel.parse::<usize>() {
+                    Ok(n) if n < CHANNEL_COUNT => n,
+                    _ => bail!("{}: channels are 0..{}", at, CHANNEL_COUNT - 1),
+                };
+                let input = channel_input(value, dir).map_err(|e| anyhow!("{}: {}", at, e))?;
+                manifest.channels.push(ChannelBinding {
+                    pass: id,
+                    channel,
+                    input,
+                });
+            }
+        }
+
+        if let Some(compute) = &file.compute {
+            let shader =
+                existing(dir, &compute.shader).map_err(|e| anyhow!("compute.shader: {}", e))?;
+            manifest.compute.shader = Some(shader);
+            let sections = [
+                ("dispatch", &compute.dispatch),
+                ("storage-buffers", &compute.storage_buffers),
+                ("storage-textures", &compute.storage_textures),
+            ];
+            for (section, table) in sections {
+                for (key, value) in table {
+                    let at = || format!("compute.{}.{}", section, key);
+                    let value = scalar(value)
+                        .ok_or_else(|| anyhow!("{}: expected a string or a number", at()))?;
+                    let arg = format!("{}={}", key, value);
+                    let compute = &mut manifest.compute;
+                    match section {
+                        "dispatch" => ComputeOptions::parse_dispatch(&arg)
+                            .map(|dispatch| compute.dispatch.push(dispatch)),
+                        "storage-buffers" => ComputeOptions::parse_buffer(&arg)
+                            .map(|(n, size)| compute.buffers[n] = Some(size)),
+                        _ => ComputeOptions::parse_texture(&arg)
+                            .map(|(n, format)| compute.textures[n] = Some(format)),
+                    }
+                    .map_err(|e| anyhow!("{}: {}", at(), e))?;
+                }
+            }
+        }
+
+        for (name, table) in &file.presets {
+            let mut preset = Vec::new();
+            for (key, value) in table {
+                let at = format!("presets.{}.{}", name, key);
+                preset.extend(setting(key, value, dir, at)?);
+            }
+            manifest.presets.insert(name.clone(), preset);
+        }
+
+        Ok(manifest)
+    }
+
+    /// The `[window]` settings, followed by those of `preset`.
+    pub fn settings(&self, preset: Option<&str>) -> Result<Vec<Setting>> {
+        let mut settings = self.window.clone();
+        if let Some(name) = preset {
+            let Some(preset) = self.presets.get(name) else {
+                let names: Vec<_> = self.presets.keys().map(String::as_str).collect();
+                bail!(
+                    "no preset `{}`, the manifest has: {}",
+                    name,
+                    if names.is_empty() {
+                        "none".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                );
+            };
+            settings.extend(preset.iter().cloned());
+        }
+        Ok(settings)
+    }
+}
+
+/// The manifest a positional argument names: the file itself, or
+/// `stoy.toml` in a directory.
+pub fn locate(path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    let manifest = path.join(FILE_NAME);
+    if !manifest.is_file() {
+        bail!("`{}` holds no {}", path.display(), FILE_NAME);
+    }
+    Ok(manifest)
+}
+
+/// `path` relative to the manifest directory, which must be a file.
+fn existing(dir: &Path, path: &Path) -> Result<PathBuf> {
+    let resolved = dir.join(path);
+    if !resolved.is_file() {
+        bail!("`{}` does not exist", resolved.display());
+    }
+    Ok(resolved)
+}
+
+/// The setting of option `key` from a TOML value: `true` is a flag on its
+/// own and `false` leaves it out, arrays are comma separated.
+fn setting(key: &str, value: &toml::Value, dir: &Path, at: String) -> Result<Option<Setting>> {
+    let value = match value {
+        toml::Value::Boolean(true) => None,
+        toml::Value::Boolean(false) => return Ok(None),
+        toml::Value::Array(items) => Some(
+            items
+                .iter()
+                .map(scalar)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| anyhow!("{}: expected an array of strings or numbers", at))?
+                .join(","),
+        ),
+        value => Some(
+            scalar(value)
+                .ok_or_else(|| anyhow!("{}: expected a string, a number or a boolean", at))?,
+        ),
+    };
+    let value = match value {
+        Some(value) if PATH_OPTIONS.contains(&key) => Some(dir.join(value).display().to_string()),
+        value => value,
+    };
+    Ok(Some(Setting {
+        option: key.to_string(),
+        value,
+        at,
+    }))
+}
+
+/// A string or a number as written on the command line.
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        _ => None,
+    }
+}
+
+/// The value of a TOML value: integers stay integers and arrays become
+/// float vectors.
+fn uniform_value(value: &toml::Value) -> Option<ParamValue> {
+    let float = |value: &toml::Value| match value {
+        toml::Value::Integer(i) => Some(*i as f32),
+        toml::Value::Float(f) => Some(*f as f32),
+        _ => None,
+    };
+    Some(match value {
+        toml::Value::Boolean(b) => ParamValue::Bool(*b),
+        toml::Value::Integer(i) => ParamValue::Int(i32::try_from(*i).ok()?),
+        toml::Value::Float(_) => ParamValue::Float(float(value)?),
+        toml::Value::Array(items) => {
+            let v = items.iter().map(float).collect::<Option<Vec<_>>>()?;
+            match v[..] {
+                [x, y] => ParamValue::Vec2([x, y]),
+                [x, y, z] => ParamValue::Vec3([x, y, z]),
+                [x, y, z, w] => ParamValue::Vec4([x, y, z, w]),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// The input of a channel: a source, or a table of the source and its
+/// sampler settings. Files and cubemaps must exist.
+fn channel_input(value: &toml::Value, dir: &Path) -> Result<ChannelInput> {
+    let (source, settings) = match value {
+        toml::Value::String(source) => (source.as_str(), None),
+        toml::Value::Table(table) => {
+            let source = table
+                .get("source")
+                .and_then(toml::Value::as_str)
+                .ok_or_else(|| anyhow!("missing `source`"))?;
+            (source, Some(table))
+        }
+        _ => bail!("expected a source or a table with one"),
+    };
+    let source = match ChannelSource::parse(source)? {
+        ChannelSource::File(path) => ChannelSource::File(existing(dir, &path)?),
+        ChannelSource::Cubemap(path) => {
+            let resolved = dir.join(&path);
+            if !resolved.is_dir() {
+                bail!("`{}` is not a directory", resolved.display());
+            }
+            ChannelSource::Cubemap(resolved)
+        }
+        source => source,
+    };
+    let mut input = ChannelInput::new(source);
+    for (key, value) in settings.into_iter().flatten() {
+        let sampler = &mut input.sampler;
+        match (key.as_str(), value) {
+            ("source", _) => (),
+            ("filter", toml::Value::String(v)) => sampler.filter = Filter::parse(v)?,
+            ("wrap", toml::Value::String(v)) => sampler.wrap = Wrap::parse(v)?,
+            ("vflip", toml::Value::Boolean(vflip)) => sampler.vflip = *vflip,
+            ("filter" | "wrap" | "vflip", _) => bail!("invalid value of `{}`", key),
+            _ => bail!(
+                "unknown setting `{}`, expected source, filter, wrap or vflip",
+                key
+            ),
+        }
+    }
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::cli::Options;
+
+    const SHADER: &str = "void mainImage(out vec4 c, in vec2 p) { c = vec4(1.0); }\n";
+
+    /// A project directory holding `image.glsl` and `a.glsl`.
+    fn project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shader_toy_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["image.glsl", "a.glsl"] {
+            fs::write(dir.join(file), SHADER).unwrap();
+        }
+        dir
+    }
+
+    fn parse_error(name: &str, text: &str) -> String {
+        let dir = project(name);
+        let err = Manifest::parse(text, &dir).err().unwrap().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        err
+    }
+
+    /// Options of the project `text` describes, with `args` on top.
+    fn options(name: &str, text: &str, args: &[&str]) -> Result<Options> {
+        let dir = project(name);
+        fs::write(dir.join(FILE_NAME), text).unwrap();
+        let mut all = vec![dir.display().to_string()];
+        all.extend(args.iter().map(|a| a.to_string()));
+        let options = Options::parse(all).map(Option::unwrap);
+        fs::remove_dir_all(&dir).unwrap();
+        options
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let err = parse_error("unknown_window", "[window]\nsise = \"4x4\"\n");
+        assert!(
+            err.starts_with("[window] has no setting `sise`, expected one of: "),
+            "{}",
+            err
+        );
+        let err = parse_error("unknown_pass", "[passes.image]\nshadr = \"image.glsl\"\n");
+        assert!(err.contains("unknown field `shadr`"), "{}", err);
+        let err = parse_error(
+            "unknown_pass_name",
+            "[passes.buffer-e]\nshader = \"a.glsl\"\n",
+        );
+        assert_eq!(err, "passes: unknown pass `buffer-e`");
+        let text = "[passes.image]\nshader = \"image.glsl\"\nchannels = { 0 = { source = \"buffer-a\", filtr = \"nearest\" } }\n";
+        let err = parse_error("unknown_channel", text);
+        assert_eq!(
+            err,
+            "passes.image.channels.0: unknown setting `filtr`, expected source, filter, wrap or vflip"
+        );
+    }
+
+    #[test]
+    fn bad_formats() {
+        let text = "[passes.buffer-a]\nshader = \"a.glsl\"\nformat = \"rgb9\"\n";
+        let err = parse_error("bad_format", text);
+        assert!(err.starts_with("passes.buffer-a.format: "), "{}", err);
+        let text = "[passes.buffer-a]\nshader = \"a.glsl\"\nsize = true\n";
+        let err = parse_error("bad_size", text);
+        assert_eq!(err, "passes.buffer-a.size: expected \"<W>x<H>\" or a scale");
+        let err = parse_error("bad_uniform", "[uniforms]\ntint = \"red\"\n");
+        assert_eq!(
+            err,
+            "uniforms.tint: expected a number, a boolean or an array of 2 to 4 numbers"
+        );
+        let text = "[passes.image]\nshader = \"image.glsl\"\nchannels = { 4 = \"buffer-a\" }\n";
+        let err = parse_error("bad_channel", text);
+        assert_eq!(err, "passes.image.channels.4: channels are 0..3");
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = project("missing_files");
+        let err = Manifest::parse("[passes.image]\nshader = \"gone.glsl\"\n", &dir)
+            .err()
+            .unwrap()
+            .to_string();
+        let expected = format!(
+            "passes.image.shader: `{}` does not exist",
+            dir.join("gone.glsl").display()
+        );
+        let include = Manifest::parse("include = [\"lib\"]\n", &dir)
+            .err()
+            .unwrap()
+            .to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(err, expected);
+        assert_eq!(
+            include,
+            format!(
+                "include: `{}` is not a directory",
+                dir.join("lib").display()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_buffers() {
+        let text = "[passes.image]\nshader = \"image.glsl\"\nchannels = { 0 = \"buffer-b\" }\n";
+        let err = options("missing_buffer", text, &[]).err().unwrap();
+        assert_eq!(err.to_string(), "image reads buffer-b, which has no shader");
+    }
+
+    #[test]
+    fn buffer_cycles() {
+        let text = "[passes.image]\nshader = \"image.glsl\"\n\
+            [passes.buffer-a]\nshader = \"a.glsl\"\nchannels = { 0 = \"buffer-a\", 1 = \"buffer-b\" }\n\
+            [passes.buffer-b]\nshader = \"a.glsl\"\nchannels = { 0 = \"buffer-a\" }\n";
+        let err = options("buffer_cycle", text, &[]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "buffers read each other in a cycle: buffer-a -> buffer-b -> buffer-a; \
+             only a buffer reading itself gets its previous frame"
+        );
+        // a buffer reading itself gets its previous frame
+        let text = "[passes.image]\nshader = \"image.glsl\"\n\
+            [passes.buffer-a]\nshader = \"a.glsl\"\nchannels = { 0 = \"buffer-a\" }\n";
+        assert!(options("buffer_self", text, &[]).is_ok());
+    }
+
+    #[test]
+    fn preset_overrides() {
+        let text = "[passes.image]\nshader = \"image.glsl\"\n\
+            [window]\nsize = \"16x8\"\ntime = 2\n\
+            [presets.small]\nsize = \"4x2\"\n";
+        let window = options("preset_none", text, &[]).unwrap();
+        assert_eq!(
+            (window.width, window.height, window.start_time),
+            (16, 8, 2.0)
+        );
+        // the preset goes on top of [window], keeping what it does not set
+        let preset = options("preset_small", text, &["--preset", "small"]).unwrap();
+        assert_eq!(
+            (preset.width, preset.height, preset.start_time),
+            (4, 2, 2.0)
+        );
+        // and the command line on top of both
+        let args = ["--preset", "small", "--size", "8x4"];
+        let given = options("preset_given", text, &args).unwrap();
+        assert_eq!((given.width, given.height), (8, 4));
+        let err = options("preset_unknown", text, &["--preset", "big"])
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .ends_with("no preset `big`, the manifest has: small"),
+            "{}",
+            err
+        );
+    }
+}
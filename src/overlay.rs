@@ -0,0 +1,317 @@
+use crate::{
+    font::{self, GLYPH_HEIGHT, GLYPH_WIDTH},
+    stoy::IMAGE_BLEND,
+};
+
+/// Space around the text, in pixels.
+pub const PADDING: u32 = 8;
+pub const BACKGROUND: [u8; 4] = [16, 16, 16, 216];
+pub const TEXT: [u8; 4] = [232, 232, 232, 255];
+const ERROR_TEXT: [u8; 4] = [255, 112, 112, 255];
+const NOTE_TEXT: [u8; 4] = [128, 200, 255, 255];
+
+/// Shader errors drawn over the top-left corner of the window, one entry
+/// per pass or file by name. An entry stays until it reloads successfully. Other
+/// panels like the HUD are drawn with `draw_image`.
+pub struct Overlay {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    messages: Vec<(String, String)>,
+    /// Rasterized `messages` and the surface size they were cropped to.
+    target: Option<OverlayTarget>,
+    dirty: bool,
+}
+
+struct OverlayTarget {
+    bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+    surface_size: (u32, u32),
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("overlay_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("overlay_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/overlay.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("overlay"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendState::ALPHA_BLENDING.color,
+                        alpha: IMAGE_BLEND.alpha,
+                    }),
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("overlay_sampler"),
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            layout,
+            sampler,
+            messages: Vec::new(),
+            target: None,
+            dirty: false,
+        }
+    }
+
+    /// Shows `message` for `source`, replacing its previous one.
+    pub fn set(&mut self, source: &str, message: String) {
+        match self.messages.iter_mut().find(|(name, _)| name == source) {
+            Some((_, old)) if *old == message => return,
+            Some((_, old)) => *old = message,
+            None => self.messages.push((source.to_string(), message)),
+        }
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self, source: &str) {
+        let len = self.messages.len();
+        self.messages.retain(|(name, _)| name != source);
+        self.dirty |= self.messages.len() != len;
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|(_, message)| message.as_str())
+    }
+
+    /// Draws the messages on top of `view`, which is expected to hold the
+    /// finished frame.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        surface_size: (u32, u32),
+    ) {
+        if self.messages.is_empty() {
+            return;
+        }
+        let stale = self
+            .target
+            .as_ref()
+            .is_none_or(|t| t.surface_size != surface_size);
+        if self.dirty || stale {
+            self.target = self.rasterize(device, queue, surface_size);
+            self.dirty = false;
+        }
+        let Some(target) = &self.target else { return };
+        self.draw_layer(encoder, view, &target.bind_group, (0, 0), target.size);
+    }
+
+    /// Draws an RGBA image of `size` on top of `view` at `origin`, in pixels
+    /// from the top-left corner. Skipped unless it fits in `surface_size`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        surface_size: (u32, u32),
+        pixels: &[u8],
+        size: (u32, u32),
+        origin: (u32, u32),
+    ) {
+        if origin.0 + size.0 > surface_size.0 || origin.1 + size.1 > surface_size.1 {
+            return;
+        }
+        let bind_group = self.upload(device, queue, pixels, size);
+        self.draw_layer(encoder, view, &bind_group, origin, size);
+    }
+
+    fn draw_layer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        bind_group: &wgpu::BindGroup,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let (x, y) = (origin.0 as f32, origin.1 as f32);
+        rpass.set_viewport(x, y, size.0 as f32, size.1 as f32, 0.0, 1.0);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    /// Renders the messages into a texture no larger than the surface,
+    /// cutting off lines and columns that do not fit.
+    fn rasterize(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_size: (u32, u32),
+    ) -> Option<OverlayTarget> {
+        let text = self
+            .messages
+            .iter()
+            .map(|(_, message)| message.trim_end())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let lines: Vec<&str> = text.lines().collect();
+
+        let max_cols = surface_size.0.saturating_sub(2 * PADDING) / GLYPH_WIDTH;
+        let max_rows = surface_size.1.saturating_sub(2 * PADDING) / GLYPH_HEIGHT;
+        let cols = lines
+            .iter()
+            .map(|line| line.chars().count() as u32)
+            .max()
+            .unwrap_or(0)
+            .min(max_cols);
+        let rows = (lines.len() as u32).min(max_rows);
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+
+        let width = cols * GLYPH_WIDTH + 2 * PADDING;
+        let height = rows * GLYPH_HEIGHT + 2 * PADDING;
+        let mut pixels: Vec<u8> = BACKGROUND
+            .iter()
+            .copied()
+            .cycle()
+            .take((width * height * 4) as usize)
+            .collect();
+
+        for (row, line) in lines.iter().take(rows as usize).enumerate() {
+            let line: String = line.chars().take(cols as usize).collect();
+            let y = PADDING + row as u32 * GLYPH_HEIGHT;
+            font::draw_text(&mut pixels, width, PADDING, y, &line, line_color(&line));
+        }
+
+        Some(OverlayTarget {
+            bind_group: self.upload(device, queue, &pixels, (width, height)),
+            size: (width, height),
+            surface_size,
+        })
+    }
+
+    /// Uploads RGBA `pixels` of `(width, height)` into a texture bound for
+    /// drawing.
+    fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pixels: &[u8],
+        (width, height): (u32, u32),
+    ) -> wgpu::BindGroup {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("overlay"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("overlay_bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+/// Highlights the headline of each diagnostic and its notes.
+fn line_color(line: &str) -> [u8; 4] {
+    let trimmed = line.trim_start();
+    if line.starts_with("error") {
+        ERROR_TEXT
+    } else if trimmed.starts_with("= ") {
+        NOTE_TEXT
+    } else {
+        TEXT
+    }
+}
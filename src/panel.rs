@@ -0,0 +1,383 @@
+//! Sliders, colour pickers, checkboxes and vec2 pads for the params
+//! annotated with `// @param`, drawn over the bottom-left corner of the
+//! window and toggled with F2. Immediate mode: every frame lays out the
+//! widgets, applies the mouse to them and draws them.
+
+use crate::{
+    font::{self, GLYPH_HEIGHT, GLYPH_WIDTH},
+    overlay::{BACKGROUND, PADDING, TEXT},
+    params::{self, Annotation, Param, ParamValue},
+};
+
+/// Columns of the name in front of each widget.
+const LABEL_COLS: u32 = 12;
+/// Columns of the value after a slider.
+const VALUE_COLS: u32 = 10;
+const SLIDER_WIDTH: u32 = 160;
+const TRACK_HEIGHT: u32 = 6;
+const HANDLE_WIDTH: u32 = 4;
+const PAD_SIZE: u32 = 96;
+const CHECK_SIZE: u32 = 12;
+const ROW_HEIGHT: u32 = GLYPH_HEIGHT + 4;
+const TRACK: [u8; 4] = [64, 64, 64, 255];
+const FILL: [u8; 4] = [112, 160, 232, 255];
+const ACTIVE: [u8; 4] = [160, 200, 255, 255];
+const DIM_TEXT: [u8; 4] = [160, 160, 160, 255];
+
+/// The mouse as the panel sees it, and where it was last drawn.
+#[derive(Debug)]
+pub struct Panel {
+    pub shown: bool,
+    cursor: (f32, f32),
+    /// Left button held down since a press on the panel.
+    down: bool,
+    /// Pressed on the panel since the last frame.
+    pressed: bool,
+    /// Annotation and component being dragged.
+    active: Option<(usize, usize)>,
+    /// Origin and size of the last frame, `None` if nothing was drawn.
+    rect: Option<((u32, u32), (u32, u32))>,
+}
+
+enum Kind {
+    /// Of a component between the ends of the range, with the colours at
+    /// its ends for a colour channel.
+    Slider {
+        range: (f32, f32),
+        gradient: Option<([u8; 4], [u8; 4])>,
+    },
+    Check,
+    /// Both components of a vec2, y up.
+    Pad {
+        range: (f32, f32),
+    },
+}
+
+struct Widget {
+    annotation: usize,
+    component: usize,
+    kind: Kind,
+    /// Position and size in the panel.
+    rect: (u32, u32, u32, u32),
+}
+
+/// A frame of the panel.
+pub struct Frame {
+    pub pixels: Vec<u8>,
+    pub size: (u32, u32),
+    /// Top-left corner in the window.
+    pub origin: (u32, u32),
+    /// Whether a value changed, which needs uploading.
+    pub changed: bool,
+}
+
+impl Panel {
+    pub fn new(shown: bool) -> Self {
+        Self {
+            shown,
+            cursor: (0.0, 0.0),
+            down: false,
+            pressed: false,
+            active: None,
+            rect: None,
+        }
+    }
+
+    pub fn move_cursor(&mut self, x: f64, y: f64) {
+        self.cursor = (x as f32, y as f32);
+    }
+
+    /// Takes a press of the left button on the panel, which the shaders do
+    /// not see.
+    pub fn press(&mut self) -> bool {
+        let (x, y) = self.cursor;
+        let on_panel = self.rect.is_some_and(|((ox, oy), (w, h))| {
+            x >= ox as f32 && y >= oy as f32 && x < (ox + w) as f32 && y < (oy + h) as f32
+        });
+        if self.shown && on_panel {
+            self.down = true;
+            self.pressed = true;
+        }
+        self.down
+    }
+
+    /// Takes the release of a press `press` took.
+    pub fn release(&mut self) -> bool {
+        std::mem::take(&mut self.down)
+    }
+
+    /// Lays out a widget per annotation at the bottom-left corner of
+    /// `surface_size`, applies the mouse to `params` and draws them. `None`
+    /// when hidden, without annotations or when the panel does not fit.
+    pub fn frame(
+        &mut self,
+        params: &mut [Param],
+        annotations: &[Annotation],
+        surface_size: (u32, u32),
+    ) -> Option<Frame> {
+        self.rect = None;
+        let pressed = std::mem::take(&mut self.pressed);
+        if !self.down {
+            self.active = None;
+        }
+        if !self.shown || annotations.is_empty() {
+            return None;
+        }
+        // the param of each annotation
+        let index: Vec<Option<usize>> = annotations
+            .iter()
+            .map(|a| params.iter().position(|p| p.name == a.param.name))
+            .collect();
+
+        // layout
+        let widget_x = PADDING + LABEL_COLS * GLYPH_WIDTH;
+        let value_x = widget_x + SLIDER_WIDTH + GLYPH_WIDTH;
+        let width = value_x + VALUE_COLS * GLYPH_WIDTH + PADDING;
+        let mut widgets = Vec::new();
+        let mut labels: Vec<(u32, u32, String, [u8; 4])> = Vec::new();
+        // rows of values, filled in once the mouse is applied
+        let mut values: Vec<(u32, u32, usize)> = Vec::new();
+        let mut swatches = Vec::new();
+        let mut y = PADDING;
+        for (a, annotation) in annotations.iter().enumerate() {
+            let Some(i) = index[a] else { continue };
+            let name: String = annotation
+                .param
+                .name
+                .chars()
+                .take(LABEL_COLS as usize - 1)
+                .collect();
+            labels.push((PADDING, y, name, TEXT));
+            let slider = |y: u32, component: usize, gradient| Widget {
+                annotation: a,
+                component,
+                kind: Kind::Slider {
+                    range: annotation.range,
+                    gradient,
+                },
+                rect: (widget_x, y, SLIDER_WIDTH, ROW_HEIGHT),
+            };
+            match params[i].value {
+                ParamValue::Bool(_) => {
+                    let top = y + (ROW_HEIGHT - CHECK_SIZE) / 2;
+                    widgets.push(Widget {
+                        annotation: a,
+                        component: 0,
+                        kind: Kind::Check,
+                        rect: (widget_x, top, CHECK_SIZE, CHECK_SIZE),
+                    });
+                    y += ROW_HEIGHT;
+                }
+                ParamValue::Float(_) | ParamValue::Int(_) => {
+                    widgets.push(slider(y, 0, None));
+                    values.push((value_x, y, a));
+                    y += ROW_HEIGHT;
+                }
+                ParamValue::Vec2(_) => {
+                    values.push((widget_x, y, a));
+                    y += ROW_HEIGHT;
+                    widgets.push(Widget {
+                        annotation: a,
+                        component: 0,
+                        kind: Kind::Pad {
+                            range: annotation.range,
+                        },
+                        rect: (widget_x, y, PAD_SIZE, PAD_SIZE),
+                    });
+                    y += PAD_SIZE + ROW_HEIGHT - GLYPH_HEIGHT;
+                }
+                value if annotation.color => {
+                    swatches.push((widget_x, y + 2, a));
+                    values.push((widget_x + 5 * GLYPH_WIDTH, y, a));
+                    y += ROW_HEIGHT;
+                    for (c, channel) in ["r", "g", "b"].into_iter().enumerate() {
+                        let mut lo = [value.component(0), value.component(1), value.component(2)];
+                        let mut hi = lo;
+                        lo[c] = 0.0;
+                        hi[c] = 1.0;
+                        labels.push((PADDING + GLYPH_WIDTH, y, channel.to_string(), DIM_TEXT));
+                        widgets.push(slider(y, c, Some((rgba(lo), rgba(hi)))));
+                        y += ROW_HEIGHT;
+                    }
+                }
+                value => {
+                    values.push((widget_x, y, a));
+                    y += ROW_HEIGHT;
+                    let names = ["x", "y", "z", "w"];
+                    for (c, name) in names.iter().enumerate().take(params::components(value)) {
+                        labels.push((PADDING + GLYPH_WIDTH, y, name.to_string(), DIM_TEXT));
+                        widgets.push(slider(y, c, None));
+                        y += ROW_HEIGHT;
+                    }
+                }
+            }
+        }
+        let height = y + PADDING;
+        if width > surface_size.0 || height > surface_size.1 {
+            return None;
+        }
+        let origin = (0, surface_size.1 - height);
+        self.rect = Some((origin, (width, height)));
+
+        // input
+        let cursor = (
+            self.cursor.0 - origin.0 as f32,
+            self.cursor.1 - origin.1 as f32,
+        );
+        let inside = |(x, y, w, h): (u32, u32, u32, u32)| {
+            cursor.0 >= x as f32
+                && cursor.1 >= y as f32
+                && cursor.0 < (x + w) as f32
+                && cursor.1 < (y + h) as f32
+        };
+        let mut changed = false;
+        if pressed {
+            if let Some(widget) = widgets.iter().find(|w| inside(w.rect)) {
+                let i = index[widget.annotation].unwrap();
+                match widget.kind {
+                    Kind::Check => {
+                        let ParamValue::Bool(v) = &mut params[i].value else {
+                            unreachable!()
+                        };
+                        *v = !*v;
+                        changed = true;
+                    }
+                    _ => self.active = Some((widget.annotation, widget.component)),
+                }
+            }
+        }
+        let active = widgets
+            .iter()
+            .find(|w| Some((w.annotation, w.component)) == self.active);
+        if let Some(widget) = active {
+            let i = index[widget.annotation].unwrap();
+            let (x, y, w, h) = widget.rect;
+            let t =
+                |at: f32, start: u32, len: u32| ((at - start as f32) / len as f32).clamp(0.0, 1.0);
+            let old = params[i].value;
+            let value = &mut params[i].value;
+            match widget.kind {
+                Kind::Slider { range, .. } => {
+                    let x = lerp(range, t(cursor.0, x, w));
+                    value.set_component(widget.component, x);
+                }
+                Kind::Pad { range } => {
+                    value.set_component(0, lerp(range, t(cursor.0, x, w)));
+                    value.set_component(1, lerp(range, 1.0 - t(cursor.1, y, h)));
+                }
+                Kind::Check => (),
+            }
+            changed |= *value != old;
+        }
+
+        // drawing
+        let mut pixels: Vec<u8> = BACKGROUND
+            .iter()
+            .copied()
+            .cycle()
+            .take((width * height * 4) as usize)
+            .collect();
+        let mut fill = |x: u32, y: u32, w: u32, h: u32, color: [u8; 4]| {
+            for row in y..y + h {
+                for col in x..x + w {
+                    let i = ((row * width + col) * 4) as usize;
+                    pixels[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        };
+        for widget in &widgets {
+            let value = params[index[widget.annotation].unwrap()].value;
+            let is_active = Some((widget.annotation, widget.component)) == self.active;
+            let (x, y, w, h) = widget.rect;
+            match widget.kind {
+                Kind::Slider { range, gradient } => {
+                    let top = y + (h - TRACK_HEIGHT) / 2;
+                    let t = unlerp(range, value.component(widget.component));
+                    let filled = (t * (w - HANDLE_WIDTH) as f32) as u32;
+                    match gradient {
+                        Some((lo, hi)) => {
+                            for col in 0..w {
+                                let t = col as f32 / (w - 1) as f32;
+                                let color = std::array::from_fn(|c| {
+                                    (lo[c] as f32 + (hi[c] as f32 - lo[c] as f32) * t) as u8
+                                });
+                                fill(x + col, top, 1, TRACK_HEIGHT, color);
+                            }
+                        }
+                        None => {
+                            fill(x, top, w, TRACK_HEIGHT, TRACK);
+                            fill(x, top, filled, TRACK_HEIGHT, FILL);
+                        }
+                    }
+                    let handle = if is_active { ACTIVE } else { TEXT };
+                    fill(x + filled, y + 2, HANDLE_WIDTH, h - 4, handle);
+                }
+                Kind::Check => {
+                    fill(x, y, w, h, TRACK);
+                    if value.component(0) != 0.0 {
+                        fill(x + 2, y + 2, w - 4, h - 4, FILL);
+                    }
+                }
+                Kind::Pad { range } => {
+                    fill(x, y, w, h, TRACK);
+                    fill(x + w / 2, y, 1, h, BACKGROUND);
+                    fill(x, y + h / 2, w, 1, BACKGROUND);
+                    let px = (unlerp(range, value.component(0)) * (w - 5) as f32) as u32;
+                    let py = ((1.0 - unlerp(range, value.component(1))) * (h - 5) as f32) as u32;
+                    fill(x + px, y + py, 5, 5, if is_active { ACTIVE } else { FILL });
+                }
+            }
+        }
+        for (x, y, a) in swatches {
+            let value = params[index[a].unwrap()].value;
+            let color = rgba([value.component(0), value.component(1), value.component(2)]);
+            fill(x, y, 4 * GLYPH_WIDTH, GLYPH_HEIGHT - 4, color);
+        }
+        for (x, y, a) in values {
+            let annotation = &annotations[a];
+            let value = params[index[a].unwrap()].value;
+            labels.push((x, y, format_value(value, annotation.color), DIM_TEXT));
+        }
+        for (x, y, text, color) in labels {
+            let cols = ((width - PADDING - x) / GLYPH_WIDTH) as usize;
+            let text: String = text.chars().take(cols).collect();
+            font::draw_text(&mut pixels, width, x, y + 2, &text, color);
+        }
+
+        Some(Frame {
+            pixels,
+            size: (width, height),
+            origin,
+            changed,
+        })
+    }
+}
+
+fn lerp((min, max): (f32, f32), t: f32) -> f32 {
+    min + (max - min) * t
+}
+
+/// Where `x` lies in the range, clamped to it.
+fn unlerp((min, max): (f32, f32), x: f32) -> f32 {
+    ((x - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+fn rgba(color: [f32; 3]) -> [u8; 4] {
+    let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    [r, g, b, 255]
+}
+
+fn format_value(value: ParamValue, color: bool) -> String {
+    match value {
+        ParamValue::Vec3(v) if color => {
+            let [r, g, b, _] = rgba(v);
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        }
+        ParamValue::Float(v) => format!("{:.3}", v),
+        ParamValue::Int(v) => v.to_string(),
+        ParamValue::Bool(v) => v.to_string(),
+        ParamValue::Vec2([x, y]) => format!("{:.2}, {:.2}", x, y),
+        ParamValue::Vec3(v) => format!("{:.2}, {:.2}, {:.2}", v[0], v[1], v[2]),
+        ParamValue::Vec4(v) => format!("{:.2}, {:.2}, {:.2}, {:.2}", v[0], v[1], v[2], v[3]),
+    }
+}
@@ -0,0 +1,671 @@
+//! Custom uniforms given with `--uniform`, in a manifest or annotated in a
+//! shader with `// @param`, bound to every render pass and the compute
+//! shader as one struct, `Params`.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+use wgpu::{naga, util::DeviceExt};
+
+use crate::{
+    compute::{PREVIOUS_BINDING, STORAGE_GROUP, STORAGE_TEXTURE_COUNT},
+    source::{Diagnostic, ShaderSource},
+    uniform::{self, Field},
+};
+
+/// Group of the custom uniforms, which the compute shader shares with its
+/// storage.
+pub const PARAMS_GROUP: u32 = STORAGE_GROUP;
+/// Binding of the custom uniforms, after the storage of the compute shader
+/// so both kinds of shader declare them the same.
+pub const PARAMS_BINDING: u32 = PREVIOUS_BINDING + STORAGE_TEXTURE_COUNT as u32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamValue {
+    Float(f32),
+    Int(i32),
+    /// `bool` in GLSL and `u32` in WGSL, uniforms cannot hold booleans.
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl ParamValue {
+    /// `true`, `false`, an integer, a float with a `.` or an exponent, or 2
+    /// to 4 comma separated floats.
+    pub fn parse(s: &str) -> Result<Self> {
+        let err = || {
+            anyhow!(
+                "invalid value `{}`, expected a number, true, false or 2 to 4 comma separated numbers",
+                s
+            )
+        };
+        match s {
+            "true" => return Ok(Self::Bool(true)),
+            "false" => return Ok(Self::Bool(false)),
+            _ => (),
+        }
+        if s.contains(',') {
+            let v: Vec<f32> = s
+                .split(',')
+                .map(|c| c.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| err())?;
+            return match v[..] {
+                [x, y] => Ok(Self::Vec2([x, y])),
+                [x, y, z] => Ok(Self::Vec3([x, y, z])),
+                [x, y, z, w] => Ok(Self::Vec4([x, y, z, w])),
+                _ => Err(err()),
+            };
+        }
+        if let Ok(i) = s.parse() {
+            return Ok(Self::Int(i));
+        }
+        s.parse().map(Self::Float).map_err(|_| err())
+    }
+
+    /// Type of the member in the GLSL uniform block.
+    fn glsl_type(self) -> &'static str {
+        match self {
+            Self::Float(_) => "float",
+            Self::Int(_) => "int",
+            Self::Bool(_) => "uint",
+            Self::Vec2(_) => "vec2",
+            Self::Vec3(_) => "vec3",
+            Self::Vec4(_) => "vec4",
+        }
+    }
+
+    /// Size and alignment in the uniform address space.
+    fn size_align(self) -> (u32, u32) {
+        match self {
+            Self::Float(_) | Self::Int(_) | Self::Bool(_) => (4, 4),
+            Self::Vec2(_) => (8, 8),
+            Self::Vec3(_) => (12, 16),
+            Self::Vec4(_) => (16, 16),
+        }
+    }
+
+    /// Component `i` of a number or a vector.
+    pub fn component(self, i: usize) -> f32 {
+        match self {
+            Self::Float(v) => v,
+            Self::Int(v) => v as f32,
+            Self::Bool(v) => v as u32 as f32,
+            Self::Vec2(v) => v[i],
+            Self::Vec3(v) => v[i],
+            Self::Vec4(v) => v[i],
+        }
+    }
+
+    /// Sets component `i` of a number or a vector, rounding for an int.
+    pub fn set_component(&mut self, i: usize, x: f32) {
+        match self {
+            Self::Float(v) => *v = x,
+            Self::Int(v) => *v = x.round() as i32,
+            Self::Bool(v) => *v = x != 0.0,
+            Self::Vec2(v) => v[i] = x,
+            Self::Vec3(v) => v[i] = x,
+            Self::Vec4(v) => v[i] = x,
+        }
+    }
+
+    /// Whether `other` has the same type.
+    fn same_type(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Self::Float(v) => v.to_le_bytes().to_vec(),
+            Self::Int(v) => v.to_le_bytes().to_vec(),
+            Self::Bool(v) => (v as u32).to_le_bytes().to_vec(),
+            Self::Vec2(v) => bytemuck::cast_slice(&v).to_vec(),
+            Self::Vec3(v) => bytemuck::cast_slice(&v).to_vec(),
+            Self::Vec4(v) => bytemuck::cast_slice(&v).to_vec(),
+        }
+    }
+}
+
+/// A member of `Params` and its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub value: ParamValue,
+}
+
+impl Param {
+    /// `<name>=<value>`
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid uniform `{}`, expected <name>=<value>", s))?;
+        Self::new(name, ParamValue::parse(value)?)
+    }
+
+    /// Fails unless `name` is an identifier clear of the generated ones.
+    pub fn new(name: &str, value: ParamValue) -> Result<Self> {
+        check_name(name)?;
+        Ok(Self {
+            name: name.to_string(),
+            value,
+        })
+    }
+}
+
+/// A param declared by a comment in a shader and shown in the panel:
+/// `// @param <name> <type> [<min>..<max>] [= <value>]`, where the type is
+/// float, int, bool, vec2, vec3, vec4 or color, a vec3 picked as a colour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// The param with its default value.
+    pub param: Param,
+    /// Range of each component, 0..1 unless given, 0..10 for an int.
+    pub range: (f32, f32),
+    pub color: bool,
+}
+
+impl Annotation {
+    /// The annotation of a line of code, `None` without one.
+    pub fn parse_line(line: &str) -> Option<Result<Self>> {
+        let rest = line.trim_start().strip_prefix("//")?.trim_start();
+        let rest = rest.strip_prefix("@param")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(Self::parse(rest.trim()))
+    }
+
+    /// `<name> <type> [<min>..<max>] [= <value>]`
+    fn parse(s: &str) -> Result<Self> {
+        let err = || {
+            anyhow!(
+                "invalid @param `{}`, expected <name> <type> [<min>..<max>] [= <value>]",
+                s
+            )
+        };
+        let (declaration, value) = match s.split_once('=') {
+            Some((declaration, value)) => (declaration, Some(value.trim())),
+            None => (s, None),
+        };
+        let mut words = declaration.split_whitespace();
+        let (Some(name), Some(ty)) = (words.next(), words.next()) else {
+            return Err(err());
+        };
+        check_name(name)?;
+        let range = match words.next() {
+            Some(range) => {
+                let (min, max) = range.split_once("..").ok_or_else(err)?;
+                let min: f32 = min.parse().map_err(|_| err())?;
+                let max: f32 = max.parse().map_err(|_| err())?;
+                if min.partial_cmp(&max) != Some(std::cmp::Ordering::Less) {
+                    bail!("empty range `{}` of `{}`", range, name);
+                }
+                Some((min, max))
+            }
+            None => None,
+        };
+        if words.next().is_some() {
+            return Err(err());
+        }
+
+        let (default, color) = match ty {
+            "float" => (ParamValue::Float(0.0), false),
+            "int" => (ParamValue::Int(0), false),
+            "bool" => (ParamValue::Bool(false), false),
+            "vec2" => (ParamValue::Vec2([0.0; 2]), false),
+            "vec3" => (ParamValue::Vec3([0.0; 3]), false),
+            "vec4" => (ParamValue::Vec4([0.0; 4]), false),
+            "color" => (ParamValue::Vec3([1.0; 3]), true),
+            _ => bail!(
+                "unknown type `{}` of `{}`, expected float, int, bool, vec2, vec3, vec4 or color",
+                ty,
+                name
+            ),
+        };
+        if color && range.is_some() {
+            bail!("`{}` is a color, its channels range over 0..1", name);
+        }
+        let range = range.unwrap_or(match default {
+            ParamValue::Int(_) => (0.0, 10.0),
+            _ => (0.0, 1.0),
+        });
+        let value = match value {
+            Some(value) => parse_typed(value, default)
+                .ok_or_else(|| anyhow!("invalid value `{}` of {} `{}`", value, ty, name))?,
+            // zero, or as close to it as the range goes
+            None if color => default,
+            None => {
+                let mut value = default;
+                for i in 0..components(default) {
+                    value.set_component(i, 0.0f32.clamp(range.0, range.1));
+                }
+                value
+            }
+        };
+        Ok(Self {
+            param: Param {
+                name: name.to_string(),
+                value,
+            },
+            range,
+            color,
+        })
+    }
+}
+
+/// Number of components the panel edits, 0 for a bool.
+pub fn components(value: ParamValue) -> usize {
+    match value {
+        ParamValue::Bool(_) => 0,
+        ParamValue::Float(_) | ParamValue::Int(_) => 1,
+        ParamValue::Vec2(_) => 2,
+        ParamValue::Vec3(_) => 3,
+        ParamValue::Vec4(_) => 4,
+    }
+}
+
+/// `s` as a value of the type of `like`: a number, true or false, or
+/// comma separated numbers for a vector.
+fn parse_typed(s: &str, like: ParamValue) -> Option<ParamValue> {
+    let mut value = like;
+    match &mut value {
+        ParamValue::Int(v) => *v = s.parse().ok()?,
+        ParamValue::Bool(v) => *v = s.parse().ok()?,
+        _ => {
+            let v: Vec<f32> = s
+                .split(',')
+                .map(|c| c.trim().parse().ok())
+                .collect::<Option<_>>()?;
+            if v.len() != components(like) {
+                return None;
+            }
+            for (i, x) in v.into_iter().enumerate() {
+                value.set_component(i, x);
+            }
+        }
+    }
+    Some(value)
+}
+
+/// The `// @param` annotations of `source`, with errors rendered against
+/// its files.
+pub fn annotations(source: &ShaderSource) -> Result<Vec<Annotation>, String> {
+    let mut annotations = Vec::new();
+    let mut errors = String::new();
+    let mut offset = 0;
+    for line in source.code.split_inclusive('\n') {
+        match Annotation::parse_line(line) {
+            Some(Ok(annotation)) => annotations.push(annotation),
+            Some(Err(err)) => {
+                let end = offset + line.trim_end().len();
+                errors += &source.emit(&Diagnostic {
+                    message: err.to_string(),
+                    labels: vec![(naga::Span::new(offset as u32, end as u32), String::new())],
+                    notes: Vec::new(),
+                });
+            }
+            None => (),
+        }
+        offset += line.len();
+    }
+    match errors.is_empty() {
+        true => Ok(annotations),
+        false => Err(errors),
+    }
+}
+
+/// `uniforms` followed by the annotated params they leave out, and the
+/// annotations that apply. An annotation of a uniform gives it a range and
+/// keeps its value, an int value of a float is converted. The first of
+/// several annotations of a name applies, as the Common code repeats them.
+pub fn declare(
+    uniforms: &[Param],
+    annotations: impl IntoIterator<Item = Annotation>,
+) -> (Vec<Param>, Vec<Annotation>, Vec<String>) {
+    let mut params = uniforms.to_vec();
+    let mut applied: Vec<Annotation> = Vec::new();
+    let mut errors = Vec::new();
+    for annotation in annotations {
+        let name = &annotation.param.name;
+        if applied.iter().any(|a| a.param.name == *name) {
+            let first = applied.iter().find(|a| a.param.name == *name).unwrap();
+            if !first.param.value.same_type(annotation.param.value) {
+                errors.push(format!("@param `{}` is declared with two types", name));
+            }
+            continue;
+        }
+        match params.iter_mut().find(|p| p.name == *name) {
+            Some(param) => {
+                if let (ParamValue::Int(i), ParamValue::Float(_)) =
+                    (param.value, annotation.param.value)
+                {
+                    param.value = ParamValue::Float(i as f32);
+                }
+                if !param.value.same_type(annotation.param.value) {
+                    errors.push(format!(
+                        "@param `{}` has another type than --uniform {}",
+                        name, name
+                    ));
+                    continue;
+                }
+            }
+            None => params.push(annotation.param.clone()),
+        }
+        applied.push(annotation);
+    }
+    (params, applied, errors)
+}
+
+/// Whether `a` and `b` have the same names and types in the same order,
+/// and so the same layout.
+pub fn same_layout(a: &[Param], b: &[Param]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.name == b.name && a.value.same_type(b.value))
+}
+
+/// Names become GLSL macros, so they must be identifiers clear of the
+/// generated ones.
+fn check_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !identifier {
+        bail!("invalid uniform name `{}`, expected an identifier", name);
+    }
+    if name.starts_with("stoy_") || name.starts_with("gl_") {
+        bail!("uniform name `{}` is reserved", name);
+    }
+    Ok(())
+}
+
+/// Sets the `params` declared as in `declared` to the value in `old` of
+/// those `old_declared` declares the same.
+fn keep_values(params: &mut [Param], declared: &[Param], old: &[Param], old_declared: &[Param]) {
+    for (param, declared) in params.iter_mut().zip(declared) {
+        let same = |p: &&Param| p.name == param.name;
+        if old_declared.iter().find(same) == Some(declared) {
+            if let Some(old) = old.iter().find(same) {
+                param.value = old.value;
+            }
+        }
+    }
+}
+
+/// Layout of `Params` with the members in the order given, as the shaders
+/// see it.
+pub fn fields(params: &[Param]) -> Vec<Field> {
+    let mut offset = 0u32;
+    params
+        .iter()
+        .map(|param| {
+            let (size, align) = param.value.size_align();
+            offset = offset.next_multiple_of(align);
+            let field = Field {
+                name: param.name.clone(),
+                offset,
+                size,
+            };
+            offset += size;
+            field
+        })
+        .collect()
+}
+
+/// Contents of the uniform buffer, at least 16 bytes even without params.
+fn to_bytes(params: &[Param]) -> Vec<u8> {
+    let fields = fields(params);
+    let end = fields.last().map_or(0, |f| f.offset + f.size);
+    let mut bytes = vec![0; end.next_multiple_of(16).max(16) as usize];
+    for (param, field) in params.iter().zip(&fields) {
+        let start = field.offset as usize;
+        bytes[start..start + field.size as usize].copy_from_slice(&param.value.to_bytes());
+    }
+    bytes
+}
+
+/// Checks the struct a WGSL shader binds at `PARAMS_BINDING` against
+/// `params`.
+pub fn check_layout(module: &naga::Module, params: &[Param]) -> Result<(), String> {
+    uniform::check_struct(
+        module,
+        PARAMS_GROUP,
+        PARAMS_BINDING,
+        "Params",
+        &fields(params),
+    )
+    .map_err(|e| format!("{}\ndeclare custom uniforms with --uniform or // @param", e))
+}
+
+/// The uniform block of `params` in front of GLSL shaders, each member
+/// reachable by its name. Empty without params, GLSL has no empty blocks.
+pub fn glsl_declarations(params: &[Param]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let mut src = format!(
+        "layout(set = {}, binding = {}) uniform Params {{\n",
+        PARAMS_GROUP, PARAMS_BINDING
+    );
+    for param in params {
+        let _ = writeln!(src, "    {} {};", param.value.glsl_type(), param.name);
+    }
+    src.push_str("} stoy_params;\n\n");
+    for param in params {
+        let name = &param.name;
+        let _ = match param.value {
+            ParamValue::Bool(_) => writeln!(src, "#define {name} (stoy_params.{name} != 0u)"),
+            _ => writeln!(src, "#define {name} stoy_params.{name}"),
+        };
+    }
+    src.push('\n');
+    src
+}
+
+/// Layout entry of the custom uniforms, in the group of every pass and of
+/// the compute shader.
+pub fn layout_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: PARAMS_BINDING,
+        ..uniform::layout_entry()
+    }
+}
+
+pub struct Params {
+    /// Current values, which the panel changes.
+    pub params: Vec<Param>,
+    /// Values as declared.
+    declared: Vec<Param>,
+    /// Ranges of the params shown in the panel.
+    pub annotations: Vec<Annotation>,
+    pub buffer: wgpu::Buffer,
+    /// `PARAMS_GROUP` of the render passes, which holds nothing else.
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Params {
+    pub fn new(device: &wgpu::Device, params: Vec<Param>, annotations: Vec<Annotation>) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &to_bytes(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("params_bind_group_layout"),
+            entries: &[layout_entry()],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("params_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: PARAMS_BINDING,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            declared: params.clone(),
+            params,
+            annotations,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Uploads the values after the panel changed them.
+    pub fn write(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.buffer, 0, &to_bytes(&self.params));
+    }
+
+    /// Takes the values of the params of `old` declared the same, which
+    /// survive a rebuild. Those declared differently start over.
+    pub fn keep_values(&mut self, queue: &wgpu::Queue, old: &Params) {
+        keep_values(&mut self.params, &self.declared, &old.params, &old.declared);
+        self.write(queue);
+    }
+
+    /// Applies declarations of the same layout after a reload: values
+    /// declared differently start over, the others are kept.
+    pub fn redeclare(
+        &mut self,
+        queue: &wgpu::Queue,
+        params: Vec<Param>,
+        annotations: Vec<Annotation>,
+    ) {
+        let mut changed = false;
+        for ((param, declared), new) in self.params.iter_mut().zip(&self.declared).zip(&params) {
+            if new != declared {
+                param.value = new.value;
+                changed = true;
+            }
+        }
+        self.declared = params;
+        self.annotations = annotations;
+        if changed {
+            self.write(queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, value: ParamValue) -> Param {
+        Param::new(name, value).unwrap()
+    }
+
+    fn annotation(line: &str) -> Result<Annotation> {
+        Annotation::parse_line(line).unwrap()
+    }
+
+    #[test]
+    fn vec3_aligns_to_16_bytes() {
+        let params = [
+            param("a", ParamValue::Float(0.0)),
+            param("b", ParamValue::Vec3([0.0; 3])),
+            param("c", ParamValue::Float(0.0)),
+            param("d", ParamValue::Vec2([0.0; 2])),
+            param("e", ParamValue::Vec3([0.0; 3])),
+        ];
+        let layout: Vec<_> = fields(&params)
+            .into_iter()
+            .map(|f| (f.name, f.offset, f.size))
+            .collect();
+        let expected = [
+            ("a", 0, 4),
+            ("b", 16, 12),
+            // a scalar fills the end of a vec3
+            ("c", 28, 4),
+            ("d", 32, 8),
+            ("e", 48, 12),
+        ];
+        assert_eq!(layout, expected.map(|(n, o, s)| (n.to_string(), o, s)));
+        assert_eq!(to_bytes(&params).len(), 64);
+    }
+
+    #[test]
+    fn ranges() {
+        let err = |line| annotation(line).unwrap_err().to_string();
+        assert_eq!(err("// @param x float 2..1"), "empty range `2..1` of `x`");
+        assert_eq!(err("// @param x float 1..1"), "empty range `1..1` of `x`");
+        assert_eq!(
+            err("// @param x float NaN..1"),
+            "empty range `NaN..1` of `x`"
+        );
+        assert_eq!(
+            err("// @param x float 0..y"),
+            "invalid @param `x float 0..y`, expected <name> <type> [<min>..<max>] [= <value>]"
+        );
+        let range = |line| annotation(line).unwrap().range;
+        assert_eq!(range("// @param x float"), (0.0, 1.0));
+        assert_eq!(range("// @param x int"), (0.0, 10.0));
+        // without a value, as close to zero as the range goes
+        let x = annotation("// @param x vec2 0.5..2").unwrap();
+        assert_eq!(x.param.value, ParamValue::Vec2([0.5, 0.5]));
+        assert!(Annotation::parse_line("// @parameter x float").is_none());
+    }
+
+    #[test]
+    fn declare_converts_an_int_uniform() {
+        let uniforms = [
+            param("speed", ParamValue::Int(2)),
+            param("on", ParamValue::Bool(true)),
+        ];
+        let annotations = [
+            "// @param speed float 0..10 = 1.5",
+            "// @param on float",
+            "// @param tint color",
+        ]
+        .map(|line| annotation(line).unwrap());
+        let (params, applied, errors) = declare(&uniforms, annotations);
+        assert_eq!(
+            params,
+            [
+                param("speed", ParamValue::Float(2.0)),
+                param("on", ParamValue::Bool(true)),
+                param("tint", ParamValue::Vec3([1.0; 3])),
+            ]
+        );
+        let names: Vec<_> = applied.iter().map(|a| a.param.name.as_str()).collect();
+        assert_eq!(names, ["speed", "tint"]);
+        assert_eq!(errors, ["@param `on` has another type than --uniform on"]);
+    }
+
+    #[test]
+    fn keep_matching_values() {
+        let old_declared = [
+            param("same", ParamValue::Float(1.0)),
+            param("changed", ParamValue::Float(1.0)),
+            param("retyped", ParamValue::Float(1.0)),
+        ];
+        let old = [
+            param("same", ParamValue::Float(5.0)),
+            param("changed", ParamValue::Float(6.0)),
+            param("retyped", ParamValue::Float(7.0)),
+        ];
+        let declared = [
+            param("retyped", ParamValue::Int(1)),
+            param("added", ParamValue::Float(3.0)),
+            param("same", ParamValue::Float(1.0)),
+            param("changed", ParamValue::Float(2.0)),
+        ];
+        let mut params = declared.clone();
+        keep_values(&mut params, &declared, &old, &old_declared);
+        assert_eq!(
+            params,
+            [
+                param("retyped", ParamValue::Int(1)),
+                param("added", ParamValue::Float(3.0)),
+                param("same", ParamValue::Float(5.0)),
+                param("changed", ParamValue::Float(2.0)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    channel::{parse_buffer_name, ChannelInput, ChannelSource, CHANNEL_COUNT},
+    cli, glsl,
+    params::Param,
+    source::ShaderSource,
+    stoy::ShaderLang,
+    texture::Texture,
+    uniform::Uniform,
+    uniforms::uniforms::MainUniforms,
+};
+
+/// Number of offscreen buffers (Buffer A–D).
+pub const BUFFER_COUNT: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PassId {
+    /// Offscreen buffer, 0 = Buffer A.
+    Buffer(usize),
+    /// Final pass drawn to the surface.
+    Image,
+    /// Every entry point of the compute shader, which runs before the
+    /// buffers. Names their errors and timings, it is not a `Pass`.
+    Compute,
+}
+
+impl PassId {
+    /// A render pass, `compute` is not accepted.
+    pub fn parse(s: &str) -> Result<Self> {
+        if s == "image" {
+            return Ok(Self::Image);
+        }
+        parse_buffer_name(s)
+            .map(Self::Buffer)
+            .ok_or_else(|| anyhow!("unknown pass `{}`", s))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Buffer(i) => format!("buffer-{}", (b'a' + *i as u8) as char),
+            Self::Image => "image".to_string(),
+            Self::Compute => "compute".to_string(),
+        }
+    }
+}
+
+/// `--channel <pass>:<n>=<input>` on the command line.
+#[derive(Debug, Clone)]
+pub struct ChannelBinding {
+    pub pass: PassId,
+    pub channel: usize,
+    pub input: ChannelInput,
+}
+
+impl ChannelBinding {
+    pub fn parse(s: &str) -> Result<Self> {
+        let err = || anyhow!("invalid channel `{}`, expected <pass>:<n>=<source>", s);
+        let (target, source) = s.split_once('=').ok_or_else(err)?;
+        let (pass, channel) = target.split_once(':').ok_or_else(err)?;
+        let channel: usize = channel.parse().map_err(|_| err())?;
+        if channel >= CHANNEL_COUNT {
+            bail!("channel {} out of range, passes have {}", channel, CHANNEL_COUNT);
+        }
+        Ok(Self {
+            pass: PassId::parse(pass)?,
+            channel,
+            input: ChannelInput::parse(source)?,
+        })
+    }
+}
+
+/// Format of the target a pass renders into, `--format <pass>=<format>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TargetFormat {
+    /// `BUFFER_FORMAT` for buffers and the surface format for the image.
+    #[default]
+    Rgba8,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl TargetFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "rgba8" => Ok(Self::Rgba8),
+            "rgba16f" => Ok(Self::Rgba16Float),
+            "rgba32f" => Ok(Self::Rgba32Float),
+            _ => Err(anyhow!("invalid format `{}`, expected rgba8, rgba16f or rgba32f", s)),
+        }
+    }
+
+    /// The floating point texture format, `None` for 8 bits.
+    pub fn float(self) -> Option<wgpu::TextureFormat> {
+        match self {
+            Self::Rgba8 => None,
+            Self::Rgba16Float => Some(wgpu::TextureFormat::Rgba16Float),
+            Self::Rgba32Float => Some(wgpu::TextureFormat::Rgba32Float),
+        }
+    }
+}
+
+/// Size of a buffer's targets, `--buffer-size <buffer>=<WxH | scale>`.
+/// Buffers without one render at the render size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PassSize {
+    Fixed((u32, u32)),
+    /// Fraction of the render size.
+    Scale(f32),
+}
+
+impl PassSize {
+    pub fn parse(s: &str) -> Result<Self> {
+        if s.contains(['x', 'X']) {
+            return cli::parse_size(s).map(Self::Fixed);
+        }
+        match s.parse::<f32>() {
+            Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(Self::Scale(scale)),
+            _ => Err(anyhow!("invalid buffer size `{}`, expected <W>x<H> or a scale", s)),
+        }
+    }
+
+    /// Size of the targets at `render_size`, at least a pixel and at most
+    /// `max` on each side.
+    pub fn apply(self, render_size: (u32, u32), max: u32) -> (u32, u32) {
+        let (w, h) = match self {
+            Self::Fixed(size) => size,
+            Self::Scale(scale) => (
+                (render_size.0 as f32 * scale).round() as u32,
+                (render_size.1 as f32 * scale).round() as u32,
+            ),
+        };
+        (w.clamp(1, max), h.clamp(1, max))
+    }
+}
+
+pub struct Pass {
+    pub id: PassId,
+    pub shader_path: Option<PathBuf>,
+    pub lang: ShaderLang,
+    /// Source of the current pipeline, used to skip unchanged files on reload.
+    pub source: ShaderSource,
+    /// `None` for a buffer whose shader has not built yet, which only clears
+    /// its target.
+    pub pipeline: Option<wgpu::RenderPipeline>,
+    /// Whether `pipeline` is the built-in shader standing in for a shader
+    /// that failed to build, which `lang` and `shader_path` still describe.
+    pub fallback: bool,
+    pub format: wgpu::TextureFormat,
+    pub channels: [Option<Channel>; CHANNEL_COUNT],
+    /// Ping-pong targets of a buffer: frame `f` writes `targets[f % 2]` while
+    /// the other one still holds frame `f - 1`. `None` for the image pass.
+    pub targets: Option<[Texture; 2]>,
+    /// Size of the targets unless it is the render size.
+    pub size: Option<PassSize>,
+    /// `MainUniforms` at `size`, `None` for passes at the render size.
+    pub uniforms: Option<Uniform<MainUniforms>>,
+    /// Channel bind groups for even and odd frames.
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    /// `iChannelResolution` bound in `bind_groups`.
+    pub channel_uniforms: Option<wgpu::Buffer>,
+}
+
+pub struct Channel {
+    pub input: ChannelInput,
+    /// Texture loaded for this channel, `None` for buffers and the built-in
+    /// texture which are owned elsewhere.
+    pub texture: Option<Texture>,
+    pub sampler: wgpu::Sampler,
+}
+
+impl Channel {
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        input: ChannelInput,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        let texture = input.load_texture(device, queue, seed)?;
+        let sampler = input.sampler.create_sampler(device);
+        Ok(Self {
+            input,
+            texture,
+            sampler,
+        })
+    }
+}
+
+impl Pass {
+    /// Buffers read through a channel, excluding the pass itself.
+    pub fn buffer_inputs(&self) -> impl Iterator<Item = usize> + '_ {
+        let own = match self.id {
+            PassId::Buffer(i) => Some(i),
+            PassId::Image | PassId::Compute => None,
+        };
+        self.channels.iter().flatten().filter_map(move |channel| match channel.input.source {
+            ChannelSource::Buffer(b) if Some(b) != own => Some(b),
+            _ => None,
+        })
+    }
+
+    pub fn glsl_interface(&self, params: &[Param]) -> glsl::Interface {
+        interface(self.id, &self.channels, params)
+    }
+}
+
+/// GLSL declarations matching a pass, its channels and the custom uniforms.
+pub fn interface(
+    id: PassId,
+    channels: &[Option<Channel>; CHANNEL_COUNT],
+    params: &[Param],
+) -> glsl::Interface {
+    glsl::Interface {
+        cube_channels: std::array::from_fn(|i| {
+            channels[i]
+                .as_ref()
+                .is_some_and(|c| c.input.source.is_cube())
+        }),
+        flip_y: id == PassId::Image,
+        params: params.to_vec(),
+    }
+}
+
+/// Orders buffers so each one runs after the buffers it reads, given each
+/// pass with the buffers it reads. A buffer reading itself gets its previous
+/// frame wherever it runs. Buffers
+/// caught in a cycle, which `check_cycles` rejects up front, keep A–D order
+/// and read the previous frame of the ones that run after them.
+pub fn render_order(passes: &[(PassId, Vec<usize>)]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(passes.len());
+    let mut placed = vec![false; passes.len()];
+    let buffer_pos = |b: &usize| passes.iter().position(|(id, _)| *id == PassId::Buffer(*b));
+
+    loop {
+        let next = (0..passes.len()).find(|&i| {
+            !placed[i]
+                && passes[i].0 != PassId::Image
+                && passes[i]
+                    .1
+                    .iter()
+                    .filter_map(buffer_pos)
+                    .all(|dep| dep == i || placed[dep])
+        });
+        match next {
+            Some(i) => {
+                placed[i] = true;
+                order.push(i);
+            }
+            None => break,
+        }
+    }
+
+    // cycles and the image pass, in declaration order
+    order.extend((0..passes.len()).filter(|&i| !placed[i] && passes[i].0 != PassId::Image));
+    order.extend((0..passes.len()).filter(|&i| passes[i].0 == PassId::Image));
+    order
+}
+
+/// Fails on buffers reading each other in a cycle, `reads[b]` being the
+/// buffers buffer `b` reads. A buffer reading itself gets its previous
+/// frame, any other read gets the current one, which a cycle has no order
+/// for.
+pub fn check_cycles(reads: &[Vec<usize>; BUFFER_COUNT]) -> Result<()> {
+    fn visit(
+        b: usize,
+        reads: &[Vec<usize>; BUFFER_COUNT],
+        path: &mut Vec<usize>,
+        done: &mut [bool; BUFFER_COUNT],
+    ) -> Option<Vec<usize>> {
+        if let Some(at) = path.iter().position(|&p| p == b) {
+            let mut cycle = path[at..].to_vec();
+            cycle.push(b);
+            return Some(cycle);
+        }
+        if done[b] {
+            return None;
+        }
+        path.push(b);
+        for &next in reads[b].iter().filter(|&&next| next != b) {
+            if let Some(cycle) = visit(next, reads, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done[b] = true;
+        None
+    }
+
+    let mut done = [false; BUFFER_COUNT];
+    for b in 0..BUFFER_COUNT {
+        if let Some(cycle) = visit(b, reads, &mut Vec::new(), &mut done) {
+            let names: Vec<String> = cycle
+                .into_iter()
+                .map(|b| PassId::Buffer(b).name())
+                .collect();
+            bail!(
+                "buffers read each other in a cycle: {}; only a buffer reading itself gets its previous frame",
+                names.join(" -> ")
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(b: usize, reads: &[usize]) -> (PassId, Vec<usize>) {
+        (PassId::Buffer(b), reads.to_vec())
+    }
+
+    #[test]
+    fn buffers_run_after_what_they_read() {
+        // the image comes first in `Stoy::new` and last in the order
+        let passes = [
+            (PassId::Image, vec![0, 2]),
+            buffer(0, &[1]),
+            buffer(1, &[2]),
+            buffer(2, &[]),
+        ];
+        assert_eq!(render_order(&passes), [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn ping_pong_is_not_a_dependency() {
+        let passes = [
+            buffer(0, &[0, 1]),
+            buffer(1, &[1]),
+            (PassId::Image, vec![0]),
+        ];
+        assert_eq!(render_order(&passes), [1, 0, 2]);
+    }
+
+    #[test]
+    fn cycles_keep_declaration_order() {
+        let passes = [
+            buffer(0, &[1]),
+            buffer(1, &[0]),
+            buffer(2, &[]),
+            (PassId::Image, vec![0]),
+        ];
+        assert_eq!(render_order(&passes), [2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut reads: [Vec<usize>; BUFFER_COUNT] = Default::default();
+        reads[0] = vec![0];
+        reads[1] = vec![0, 3];
+        assert!(check_cycles(&reads).is_ok());
+        reads[3] = vec![2];
+        reads[2] = vec![1];
+        assert_eq!(
+            check_cycles(&reads).unwrap_err().to_string(),
+            "buffers read each other in a cycle: buffer-b -> buffer-d -> buffer-c -> buffer-b; \
+             only a buffer reading itself gets its previous frame"
+        );
+    }
+
+    #[test]
+    fn pass_sizes() {
+        assert_eq!(PassSize::parse("64x32").unwrap(), PassSize::Fixed((64, 32)));
+        assert_eq!(PassSize::parse("0.5").unwrap(), PassSize::Scale(0.5));
+        for (s, err) in [
+            ("0x32", "size `0x32` must be non-zero"),
+            ("64x", "invalid height in `64x`"),
+            ("0", "invalid buffer size `0`, expected <W>x<H> or a scale"),
+            (
+                "-1",
+                "invalid buffer size `-1`, expected <W>x<H> or a scale",
+            ),
+            (
+                "inf",
+                "invalid buffer size `inf`, expected <W>x<H> or a scale",
+            ),
+            (
+                "half",
+                "invalid buffer size `half`, expected <W>x<H> or a scale",
+            ),
+        ] {
+            assert_eq!(PassSize::parse(s).unwrap_err().to_string(), err);
+        }
+    }
+
+    #[test]
+    fn pass_size_clamps() {
+        assert_eq!(PassSize::Scale(0.5).apply((101, 50), 4096), (51, 25));
+        assert_eq!(PassSize::Scale(0.001).apply((100, 50), 4096), (1, 1));
+        assert_eq!(
+            PassSize::Fixed((8192, 16)).apply((100, 50), 4096),
+            (4096, 16)
+        );
+    }
+}
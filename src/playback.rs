@@ -0,0 +1,279 @@
+use winit::keyboard::{Key, NamedKey};
+
+/// Time added or removed by a single frame step.
+pub const STEP: f32 = 1.0 / 60.0;
+/// Time skipped by the seek keys.
+const SEEK: f32 = 5.0;
+/// Speed change of the speed keys.
+const SPEED_STEP: f32 = 0.25;
+
+/// Playback state behind `iTime` and `iFrame`.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    pub time: f32,
+    /// `iFrame` of the last frame, -1 before the first one.
+    pub frame: i32,
+    /// Multiplies the wall clock, negative values run backwards. Ignored
+    /// with a fixed frame rate.
+    pub speed: f32,
+    /// Makes `time` a function of `frame`: `origin + frame / fps`.
+    fixed_fps: Option<f64>,
+    origin: f32,
+    pub paused: bool,
+    /// Single frames requested with `step`, negative ones go back.
+    steps: i32,
+    /// Set by `reset` until the buffers have been cleared.
+    clear: bool,
+}
+
+/// What a key press asks for, see `cli::USAGE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    TogglePause,
+    Step(i32),
+    Seek(f32),
+    SeekTo(f32),
+    Speed(f32),
+    SetSpeed(f32),
+    Reverse,
+    Reset,
+}
+
+impl Command {
+    pub fn from_key(key: &Key) -> Option<Self> {
+        match key {
+            Key::Named(NamedKey::Space) => Some(Self::TogglePause),
+            Key::Named(NamedKey::ArrowRight) => Some(Self::Step(1)),
+            Key::Named(NamedKey::ArrowLeft) => Some(Self::Step(-1)),
+            Key::Named(NamedKey::PageUp) => Some(Self::Seek(SEEK)),
+            Key::Named(NamedKey::PageDown) => Some(Self::Seek(-SEEK)),
+            Key::Named(NamedKey::Home) => Some(Self::SeekTo(0.0)),
+            Key::Named(NamedKey::ArrowUp) => Some(Self::Speed(SPEED_STEP)),
+            Key::Named(NamedKey::ArrowDown) => Some(Self::Speed(-SPEED_STEP)),
+            Key::Character(c) => match c.as_str() {
+                "1" => Some(Self::SetSpeed(1.0)),
+                "-" => Some(Self::Reverse),
+                "r" | "R" => Some(Self::Reset),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Playback {
+    pub fn new(start_time: f32, fixed_fps: Option<f64>) -> Self {
+        Self {
+            time: start_time,
+            frame: -1,
+            speed: 1.0,
+            fixed_fps,
+            origin: start_time,
+            paused: false,
+            steps: 0,
+            clear: false,
+        }
+    }
+
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::TogglePause if self.paused => self.resume(),
+            Command::TogglePause => self.pause(),
+            Command::Step(frames) => self.step(frames),
+            Command::Seek(offset) => self.seek(self.time + offset),
+            Command::SeekTo(time) => self.seek(time),
+            Command::Speed(delta) => self.set_speed(self.speed + delta),
+            Command::SetSpeed(speed) => self.set_speed(speed),
+            Command::Reverse => self.set_speed(-self.speed),
+            Command::Reset => self.reset(),
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps = 0;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Pauses and renders `frames` more frames, going back in time for
+    /// negative counts. Buffers cannot be rewound, stepping back runs them
+    /// again with the earlier time.
+    pub fn step(&mut self, frames: i32) {
+        self.paused = true;
+        self.steps += frames;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+        if let Some(fps) = self.fixed_fps {
+            // the next frame lands on `time`
+            self.frame = ((time - self.origin) as f64 * fps).round() as i32 - 1;
+        }
+    }
+
+    /// Sets time and frame back to zero and clears the buffers before the
+    /// next frame.
+    pub fn reset(&mut self) {
+        self.time = 0.0;
+        self.origin = 0.0;
+        self.frame = -1;
+        self.steps = 0;
+        self.clear = true;
+    }
+
+    /// Moves on by `dt` seconds of wall time, or one frame with a fixed
+    /// frame rate. Returns the `iTimeDelta` of the frame to render, or
+    /// `None` while paused without a pending step.
+    pub fn advance(&mut self, dt: f32) -> Option<f32> {
+        let step = if self.steps != 0 {
+            let dir = self.steps.signum();
+            self.steps -= dir;
+            dir
+        } else if self.paused {
+            return None;
+        } else {
+            1
+        };
+        self.frame = (self.frame + step).max(0);
+
+        let delta = match self.fixed_fps {
+            Some(fps) => {
+                self.time = self.origin + (self.frame as f64 / fps) as f32;
+                (step as f64 / fps) as f32
+            }
+            None => {
+                let delta = if self.paused { step as f32 * STEP } else { dt * self.speed };
+                self.time += delta;
+                delta
+            }
+        };
+        Some(delta)
+    }
+
+    /// Whether the buffers should be cleared, once per `reset`.
+    pub fn take_clear(&mut self) -> bool {
+        std::mem::take(&mut self.clear)
+    }
+
+    /// Shown in the window title.
+    pub fn status(&self) -> String {
+        format!(
+            "{:.2}s  frame {}  x{:.2}{}",
+            self.time,
+            self.frame.max(0),
+            self.speed,
+            if self.paused { "  paused" } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wall_time_scales_with_speed() {
+        let mut playback = Playback::new(1.0, None);
+        assert_eq!(playback.advance(0.5), Some(0.5));
+        assert_eq!((playback.time, playback.frame), (1.5, 0));
+        playback.apply(Command::Speed(SPEED_STEP));
+        assert_eq!(playback.advance(0.5), Some(0.625));
+        playback.apply(Command::Reverse);
+        assert_eq!(playback.speed, -1.25);
+        assert_eq!(playback.advance(0.5), Some(-0.625));
+        assert_eq!((playback.time, playback.frame), (1.5, 2));
+        playback.apply(Command::SetSpeed(1.0));
+        assert_eq!(playback.speed, 1.0);
+    }
+
+    #[test]
+    fn steps_while_paused() {
+        let mut playback = Playback::new(0.0, None);
+        playback.advance(0.1);
+        playback.apply(Command::TogglePause);
+        assert_eq!(playback.advance(0.1), None);
+        playback.apply(Command::Step(2));
+        // a step moves by `STEP`, whatever the wall time and speed
+        playback.set_speed(3.0);
+        assert_eq!(playback.advance(0.1), Some(STEP));
+        assert_eq!(playback.advance(0.1), Some(STEP));
+        assert_eq!(playback.advance(0.1), None);
+        assert_eq!(playback.frame, 2);
+        playback.apply(Command::Step(-1));
+        assert_eq!(playback.advance(0.1), Some(-STEP));
+        assert_eq!(playback.frame, 1);
+        assert!((playback.time - (0.1 + STEP)).abs() < 1e-6);
+        // resuming drops the steps still pending
+        playback.step(-5);
+        playback.apply(Command::TogglePause);
+        assert!(!playback.paused);
+        assert_eq!(playback.advance(0.1), Some(0.3));
+        assert_eq!(playback.frame, 2);
+    }
+
+    #[test]
+    fn steps_back_stop_at_frame_0() {
+        let mut playback = Playback::new(0.0, Some(10.0));
+        playback.advance(0.0);
+        playback.step(-3);
+        playback.advance(0.0);
+        assert_eq!((playback.frame, playback.time), (0, 0.0));
+    }
+
+    #[test]
+    fn fixed_frame_rate() {
+        let mut playback = Playback::new(2.0, Some(4.0));
+        // wall time and speed make no difference
+        playback.set_speed(-2.0);
+        assert_eq!(playback.advance(1.0), Some(0.25));
+        assert_eq!((playback.frame, playback.time), (0, 2.0));
+        assert_eq!(playback.advance(1.0), Some(0.25));
+        assert_eq!((playback.frame, playback.time), (1, 2.25));
+        playback.step(-1);
+        assert_eq!(playback.advance(1.0), Some(-0.25));
+        assert_eq!((playback.frame, playback.time), (0, 2.0));
+    }
+
+    #[test]
+    fn seeks() {
+        let mut playback = Playback::new(0.0, None);
+        playback.advance(1.0);
+        playback.apply(Command::Seek(SEEK));
+        assert_eq!(playback.time, 6.0);
+        playback.apply(Command::SeekTo(0.0));
+        assert_eq!(playback.time, 0.0);
+
+        // with a fixed frame rate the next frame lands on the time
+        let mut playback = Playback::new(1.0, Some(10.0));
+        playback.seek(3.0);
+        playback.advance(0.0);
+        assert_eq!(playback.frame, 20);
+        assert!((playback.time - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reset() {
+        let mut playback = Playback::new(5.0, Some(10.0));
+        playback.advance(0.0);
+        playback.advance(0.0);
+        playback.step(3);
+        assert!(!playback.take_clear());
+        playback.apply(Command::Reset);
+        assert_eq!((playback.time, playback.frame), (0.0, -1));
+        // once, for the frame after the reset
+        assert!(playback.take_clear());
+        assert!(!playback.take_clear());
+        // still paused, without the pending steps or the start time
+        assert_eq!(playback.advance(0.0), None);
+        playback.step(1);
+        assert_eq!(playback.advance(0.0), Some(0.1));
+        assert_eq!((playback.time, playback.frame), (0.0, 0));
+    }
+}
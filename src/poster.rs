@@ -0,0 +1,92 @@
+//! Images larger than a texture, rendered in tiles and streamed to disk.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context, Result};
+use tiff::encoder::{colortype::RGB8, TiffEncoder, TiffKind};
+
+use crate::{cli::Options, headless::Headless};
+
+#[derive(Debug, Clone)]
+pub struct PosterOptions {
+    /// `.png`, or `.tif`/`.tiff`.
+    pub out: PathBuf,
+    /// Largest tile side, clamped to the maximum texture size.
+    pub tile: u32,
+}
+
+impl Default for PosterOptions {
+    fn default() -> Self {
+        Self {
+            out: PathBuf::from("poster.png"),
+            tile: 2048,
+        }
+    }
+}
+
+/// `shader_toy poster`: renders the canvas of `--size` tile by tile.
+pub fn run(options: &Options, poster: &PosterOptions) -> Result<()> {
+    let tiff = match poster.out.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("png") => false,
+        Some(ext) if ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff") => true,
+        _ => bail!("cannot write `{}`, expected .png, .tif or .tiff", poster.out.display()),
+    };
+    let mut headless = Headless::new()?;
+    let file = File::create(&poster.out)
+        .with_context(|| format!("failed to create `{}`", poster.out.display()))?;
+    let writer = BufWriter::new(file);
+    let (width, height) = (options.width, options.height);
+    let tile = poster.tile.min(headless.max_texture_size());
+
+    let result = if tiff {
+        // classic TIFF offsets are 32 bits
+        let bytes = width as u64 * height as u64 * 3;
+        if bytes > u32::MAX as u64 / 2 {
+            write_tiff(TiffEncoder::new_big(writer)?, &mut headless, options, tile)
+        } else {
+            write_tiff(TiffEncoder::new(writer)?, &mut headless, options, tile)
+        }
+    } else {
+        write_png(writer, &mut headless, options, tile)
+    };
+    if result.is_err() {
+        // don't leave a truncated image behind
+        let _ = std::fs::remove_file(&poster.out);
+    }
+    result?;
+    println!("Wrote {}x{} to {}", width, height, poster.out.display());
+    Ok(())
+}
+
+fn write_png(
+    writer: impl Write + 'static,
+    headless: &mut Headless,
+    options: &Options,
+    tile: u32,
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, options.width, options.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut stream = encoder.write_header()?.into_stream_writer()?;
+    headless.render_tiles(options, tile, |strip| Ok(stream.write_all(strip)?))?;
+    stream.finish()?;
+    Ok(())
+}
+
+fn write_tiff<W: Write + Seek, K: TiffKind>(
+    mut encoder: TiffEncoder<W, K>,
+    headless: &mut Headless,
+    options: &Options,
+    tile: u32,
+) -> Result<()> {
+    let mut image = encoder.new_image::<RGB8>(options.width, options.height)?;
+    // one strip per row of tiles
+    image.rows_per_strip(tile)?;
+    headless.render_tiles(options, tile, |strip| Ok(image.write_strip(strip)?))?;
+    image.finish()?;
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! Frame and per-pass timings for the HUD and `--profile`.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+
+/// Frames kept for the HUD.
+pub const HISTORY: usize = 120;
+
+#[derive(Debug, Clone)]
+pub struct FrameTimes {
+    /// `iFrame` and `iTime` of the frame.
+    pub frame: i32,
+    pub time: f32,
+    /// Seconds since the previous frame started.
+    pub interval: f32,
+    /// Seconds spent recording the frame before submitting it.
+    pub cpu: f32,
+    /// Seconds of each pass on the GPU in render order, from the latest
+    /// measurement.
+    pub passes: Option<Vec<f32>>,
+}
+
+/// How the time of the passes is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Timestamps,
+    /// The CPU waits for each pass to finish, which stalls the pipeline but
+    /// works on any device.
+    CpuWait,
+}
+
+pub struct Profiler {
+    /// Pass names in render order, the CSV columns.
+    names: Vec<String>,
+    pub source: Source,
+    history: VecDeque<FrameTimes>,
+    csv: Option<(PathBuf, BufWriter<File>)>,
+    /// Frames written to the CSV file and their summed times.
+    total: (u64, FrameTimes),
+    frame_start: Option<Instant>,
+    last_frame_start: Option<Instant>,
+}
+
+impl Profiler {
+    pub fn new(names: Vec<String>, source: Source) -> Self {
+        let total = FrameTimes {
+            frame: 0,
+            time: 0.0,
+            interval: 0.0,
+            cpu: 0.0,
+            passes: Some(vec![0.0; names.len()]),
+        };
+        Self {
+            names,
+            source,
+            history: VecDeque::with_capacity(HISTORY),
+            csv: None,
+            total: (0, total),
+            frame_start: None,
+            last_frame_start: None,
+        }
+    }
+
+    /// Writes a row per frame to the CSV file at `path` from now on.
+    pub fn write_csv(&mut self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create `{}`", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        write!(writer, "frame,time,frame_ms,cpu_ms")?;
+        for name in &self.names {
+            write!(writer, ",{}_ms", name)?;
+        }
+        writeln!(writer)?;
+        self.csv = Some((path.to_path_buf(), writer));
+        Ok(())
+    }
+
+    /// Forgets the history, whose frames may be long ago.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether every frame is measured for the CSV file, the GPU included.
+    pub fn writes(&self) -> bool {
+        self.csv.is_some()
+    }
+
+    /// Marks the start of a frame's CPU work.
+    pub fn begin_frame(&mut self) {
+        self.last_frame_start = self.frame_start.replace(Instant::now());
+    }
+
+    /// Seconds since `begin_frame`.
+    pub fn cpu_time(&self) -> f32 {
+        self.frame_start
+            .map_or(0.0, |start| start.elapsed().as_secs_f32())
+    }
+
+    /// Seconds between the starts of the last two frames.
+    pub fn interval(&self) -> f32 {
+        match (self.last_frame_start, self.frame_start) {
+            (Some(last), Some(now)) => (now - last).as_secs_f32(),
+            _ => 0.0,
+        }
+    }
+
+    /// Adds a finished frame.
+    pub fn push(&mut self, times: FrameTimes) -> Result<()> {
+        if let Some((path, writer)) = &mut self.csv {
+            let ms = |s: f32| format!("{:.4}", s * 1000.0);
+            write!(
+                writer,
+                "{},{},{},{}",
+                times.frame,
+                times.time,
+                ms(times.interval),
+                ms(times.cpu)
+            )
+            .and_then(|_| {
+                for i in 0..self.names.len() {
+                    let pass = times.passes.as_ref().map_or(String::new(), |p| ms(p[i]));
+                    write!(writer, ",{}", pass)?;
+                }
+                writeln!(writer)
+            })
+            .with_context(|| format!("failed to write `{}`", path.display()))?;
+
+            let (count, total) = &mut self.total;
+            *count += 1;
+            total.interval += times.interval;
+            total.cpu += times.cpu;
+            if let (Some(sum), Some(passes)) = (&mut total.passes, &times.passes) {
+                for (sum, pass) in sum.iter_mut().zip(passes) {
+                    *sum += pass;
+                }
+            }
+        }
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(times);
+        Ok(())
+    }
+
+    /// The last `HISTORY` frames, oldest first.
+    pub fn history(&self) -> &VecDeque<FrameTimes> {
+        &self.history
+    }
+
+    /// Flushes the CSV file and prints the mean times written to it.
+    pub fn finish(&mut self) -> Result<()> {
+        let Some((path, mut writer)) = self.csv.take() else {
+            return Ok(());
+        };
+        writer
+            .flush()
+            .with_context(|| format!("failed to write `{}`", path.display()))?;
+        let (count, total) = &self.total;
+        let mean = |s: f32| s * 1000.0 / (*count).max(1) as f32;
+        println!(
+            "Profiled {} frames to {}: frame {:.3} ms, cpu {:.3} ms",
+            count,
+            path.display(),
+            mean(total.interval),
+            mean(total.cpu)
+        );
+        for (name, sum) in self.names.iter().zip(total.passes.iter().flatten()) {
+            println!("  {:<10} {:.3} ms", name, mean(*sum));
+        }
+        Ok(())
+    }
+}
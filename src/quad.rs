@@ -33,6 +33,31 @@ pub const VERTICES: &[Quad] = &[
 ];
 
 impl Quad {
+    /// `VERTICES` with the texture coordinates of the part of a `canvas`
+    /// covered by the tile at `offset`, in pixels from the top-left corner.
+    /// A fractional offset shifts the samples within the pixels.
+    pub fn tile(canvas: (u32, u32), offset: (f32, f32), size: (u32, u32)) -> Vec<Quad> {
+        let u = [
+            offset.0 / canvas.0 as f32,
+            (offset.0 + size.0 as f32) / canvas.0 as f32,
+        ];
+        // texture coordinates grow upwards
+        let v = [
+            1.0 - (offset.1 + size.1 as f32) / canvas.1 as f32,
+            1.0 - offset.1 / canvas.1 as f32,
+        ];
+        VERTICES
+            .iter()
+            .map(|q| Quad {
+                position: q.position,
+                tex_coords: [
+                    u[q.tex_coords[0] as usize],
+                    v[q.tex_coords[1] as usize],
+                ],
+            })
+            .collect()
+    }
+
     const ATTRIBS: [wgpu::VertexAttribute; 2] =
         wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2];
 
@@ -0,0 +1,196 @@
+//! Video recording through an `ffmpeg` child process fed raw RGBA frames
+//! over stdin.
+
+use std::{
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Debug, Clone, Default)]
+pub struct RecordOptions {
+    /// Output file, its extension picks the container.
+    pub path: PathBuf,
+    /// ffmpeg encoder, the default of the container if `None`.
+    pub codec: Option<String>,
+    /// Constant rate factor, lower is better.
+    pub crf: Option<u32>,
+    /// ffmpeg pixel format of the output, e.g. `yuv420p` or `yuv444p`.
+    pub pixel_format: Option<String>,
+}
+
+/// Encoder settings that depend on the container.
+struct Container {
+    codec: &'static str,
+    crf: u32,
+    pixel_format: &'static str,
+    /// Extra arguments placed before the output file.
+    args: &'static [&'static str],
+}
+
+impl RecordOptions {
+    fn container(&self) -> Result<Option<Container>> {
+        let extension = self
+            .path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        Ok(match extension.as_deref() {
+            Some("mp4" | "mov" | "mkv") => Some(Container {
+                codec: "libx264",
+                crf: 18,
+                pixel_format: "yuv420p",
+                args: &[],
+            }),
+            Some("webm") => Some(Container {
+                codec: "libvpx-vp9",
+                crf: 32,
+                pixel_format: "yuv420p",
+                // constant quality mode of libvpx
+                args: &["-b:v", "0"],
+            }),
+            Some("gif") => {
+                if self.codec.is_some() || self.crf.is_some() || self.pixel_format.is_some() {
+                    bail!("--codec, --crf and --pix-fmt do not apply to GIF");
+                }
+                None
+            }
+            _ => bail!(
+                "cannot record `{}`, expected .mp4, .mov, .mkv, .webm or .gif",
+                self.path.display()
+            ),
+        })
+    }
+
+    /// Arguments of `ffmpeg` reading `size` frames at `fps` from stdin.
+    fn ffmpeg_args(&self, size: (u32, u32), fps: f64) -> Result<Vec<String>> {
+        let mut args: Vec<String> = [
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-s",
+            &format!("{}x{}", size.0, size.1),
+            "-r",
+            &fps.to_string(),
+            "-i",
+            "-",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        match self.container()? {
+            Some(container) => {
+                let pixel_format = self.pixel_format.as_deref().unwrap_or(container.pixel_format);
+                // chroma subsampled formats need even dimensions
+                if pixel_format.starts_with("yuv420") || pixel_format.starts_with("yuv422") {
+                    args.extend(["-vf".into(), "pad=ceil(iw/2)*2:ceil(ih/2)*2".into()]);
+                }
+                args.extend([
+                    "-c:v".into(),
+                    self.codec.as_deref().unwrap_or(container.codec).into(),
+                    "-crf".into(),
+                    self.crf.unwrap_or(container.crf).to_string(),
+                    "-pix_fmt".into(),
+                    pixel_format.into(),
+                ]);
+                args.extend(container.args.iter().map(|arg| arg.to_string()));
+            }
+            // a palette made from the whole clip
+            None => args.extend([
+                "-vf".into(),
+                "split[a][b];[a]palettegen[p];[b][p]paletteuse".into(),
+            ]),
+        }
+        args.push(self.path.display().to_string());
+        Ok(args)
+    }
+}
+
+/// Running `ffmpeg` encoding the frames written to it.
+pub struct Recorder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    size: (u32, u32),
+    path: PathBuf,
+    frames: u64,
+}
+
+impl Recorder {
+    /// Starts `ffmpeg` for frames of `size` played back at `fps`.
+    pub fn start(options: &RecordOptions, size: (u32, u32), fps: f64) -> Result<Self> {
+        let args = options.ffmpeg_args(size, fps)?;
+        let mut child = match Command::new("ffmpeg")
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => bail!(
+                "recording needs ffmpeg, which was not found on PATH; install it or use \
+                 `shader_toy render` to write PNG frames"
+            ),
+            Err(err) => return Err(err).context("failed to start ffmpeg"),
+        };
+        let stdin = child.stdin.take();
+        Ok(Self {
+            child,
+            stdin,
+            size,
+            path: options.path.clone(),
+            frames: 0,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn write(&mut self, frame: &image::RgbaImage) -> Result<()> {
+        if frame.dimensions() != self.size {
+            bail!(
+                "frame is {}x{}, the recording {}x{}",
+                frame.width(),
+                frame.height(),
+                self.size.0,
+                self.size.1
+            );
+        }
+        let stdin = self.stdin.as_mut().expect("stdin is open until finish");
+        if let Err(err) = stdin.write_all(frame.as_raw()) {
+            // ffmpeg quit early, its exit status says more than the pipe
+            self.stdin = None;
+            let status = self.child.wait()?;
+            bail!("ffmpeg exited with {} ({})", status, err);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Closes the input and waits for `ffmpeg` to write the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.stdin = None;
+        let status = self.child.wait().context("failed to wait for ffmpeg")?;
+        if !status.success() {
+            bail!("ffmpeg exited with {}", status);
+        }
+        println!("Recorded {} frames to {}", self.frames, self.path.display());
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // let ffmpeg finish the file even if the recording was not finished
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
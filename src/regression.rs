@@ -0,0 +1,249 @@
+//! Golden-image tests: every shader in a folder is rendered headless at
+//! fixed times and compared with reference PNGs.
+
+use std::{fmt, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{cli::Options, deterministic::Deterministic, headless::Headless};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Folder of `.wgsl` and `.glsl` image shaders.
+    pub shaders: PathBuf,
+    /// Reference images, `<shader>_t<time>.png`.
+    pub references: PathBuf,
+    /// Where the actual and diff images of failed comparisons go.
+    pub failures: PathBuf,
+    /// Times rendered, rounded to whole frames.
+    pub times: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    /// Largest difference allowed in any channel of any pixel.
+    pub tolerance: u8,
+    /// Largest mean CIE76 color difference allowed over the image.
+    pub max_delta_e: f32,
+    /// Overwrites the references instead of comparing with them.
+    pub bless: bool,
+}
+
+impl Config {
+    /// Defaults for `shaders`: references and failures in subfolders.
+    pub fn new(shaders: impl Into<PathBuf>) -> Self {
+        let shaders = shaders.into();
+        Self {
+            references: shaders.join("references"),
+            failures: shaders.join("failures"),
+            shaders,
+            times: vec![0.0, 1.0],
+            width: 128,
+            height: 128,
+            fps: 60.0,
+            tolerance: 2,
+            max_delta_e: 1.0,
+            bless: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Images compared or blessed.
+    pub checked: usize,
+    pub failures: Vec<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} images, {} failed", self.checked, self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders every shader, falling back to a software adapter without a GPU.
+/// A shader that fails to load counts as a failure.
+pub fn run(config: &Config) -> Result<Report> {
+    let mut shaders: Vec<PathBuf> = std::fs::read_dir(&config.shaders)
+        .with_context(|| format!("failed to read `{}`", config.shaders.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    shaders.retain(|path| {
+        path.is_file()
+            && matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("wgsl" | "glsl" | "frag" | "fs")
+            )
+    });
+    shaders.sort();
+    if shaders.is_empty() {
+        bail!("no shaders in `{}`", config.shaders.display());
+    }
+
+    let frames: Vec<u64> = config
+        .times
+        .iter()
+        .map(|t| (*t as f64 * config.fps).round().max(0.0) as u64)
+        .collect();
+    let end = frames.iter().max().map_or(1, |last| last + 1);
+
+    let mut headless = Headless::new()?;
+    let mut report = Report::default();
+    for shader in &shaders {
+        let stem = shader.file_stem().unwrap_or_default().to_string_lossy();
+        let options = Options {
+            shader: Some(shader.clone()),
+            width: config.width,
+            height: config.height,
+            watch: false,
+            deterministic: Some(Deterministic {
+                fps: config.fps,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = headless.render(&options, 0..end, |frame, image| {
+            for (time, _) in config.times.iter().zip(&frames).filter(|(_, f)| **f == frame) {
+                let name = format!("{}_t{}", stem, time);
+                report.checked += 1;
+                if let Err(err) = check(config, &name, &image) {
+                    report.failures.push(format!("{}: {:#}", name, err));
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            report
+                .failures
+                .push(format!("{}: {:#}", shader.display(), err));
+        }
+    }
+    Ok(report)
+}
+
+/// Compares `actual` with its reference, or replaces the reference when
+/// blessing.
+fn check(config: &Config, name: &str, actual: &image::RgbaImage) -> Result<()> {
+    let reference_path = config.references.join(format!("{}.png", name));
+    if config.bless {
+        std::fs::create_dir_all(&config.references)?;
+        return actual
+            .save(&reference_path)
+            .with_context(|| format!("failed to write `{}`", reference_path.display()));
+    }
+
+    let reference = image::open(&reference_path)
+        .with_context(|| format!("no reference `{}`, run with --bless", reference_path.display()))?
+        .to_rgba8();
+    let failed = |reason: String, diff: Option<image::RgbaImage>| -> Result<()> {
+        std::fs::create_dir_all(&config.failures)?;
+        let actual_path = config.failures.join(format!("{}.actual.png", name));
+        actual.save(&actual_path)?;
+        let mut reason = format!("{}, wrote {}", reason, actual_path.display());
+        if let Some(diff) = diff {
+            let diff_path = config.failures.join(format!("{}.diff.png", name));
+            diff.save(&diff_path)?;
+            reason += &format!(" and {}", diff_path.display());
+        }
+        bail!(reason)
+    };
+
+    if reference.dimensions() != actual.dimensions() {
+        let (w, h) = reference.dimensions();
+        return failed(format!("reference is {}x{}", w, h), None);
+    }
+    let comparison = compare(actual, &reference, config.tolerance);
+    if comparison.over_tolerance > 0 {
+        return failed(
+            format!(
+                "{} pixels differ by more than {} (max {})",
+                comparison.over_tolerance, config.tolerance, comparison.max_channel_diff
+            ),
+            Some(comparison.diff),
+        );
+    }
+    if comparison.mean_delta_e > config.max_delta_e {
+        return failed(
+            format!(
+                "mean color difference {:.3} exceeds {}",
+                comparison.mean_delta_e, config.max_delta_e
+            ),
+            Some(comparison.diff),
+        );
+    }
+    Ok(())
+}
+
+struct Comparison {
+    max_channel_diff: u8,
+    /// Pixels with a channel differing by more than the tolerance.
+    over_tolerance: usize,
+    mean_delta_e: f32,
+    /// Red where pixels exceed the tolerance, brighter for larger color
+    /// differences, over a dimmed copy of the reference.
+    diff: image::RgbaImage,
+}
+
+fn compare(actual: &image::RgbaImage, reference: &image::RgbaImage, tolerance: u8) -> Comparison {
+    let mut max_channel_diff = 0;
+    let mut over_tolerance = 0;
+    let mut delta_e_sum = 0.0;
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+    for ((a, r), d) in actual.pixels().zip(reference.pixels()).zip(diff.pixels_mut()) {
+        let channel_diff = (0..4).map(|i| a[i].abs_diff(r[i])).max().unwrap_or(0);
+        max_channel_diff = max_channel_diff.max(channel_diff);
+        let delta_e = delta_e(a.0, r.0);
+        delta_e_sum += delta_e as f64;
+        *d = if channel_diff > tolerance {
+            over_tolerance += 1;
+            image::Rgba([(128.0 + delta_e * 4.0).min(255.0) as u8, 0, 0, 255])
+        } else {
+            let luma = 0.2126 * r[0] as f32 + 0.7152 * r[1] as f32 + 0.0722 * r[2] as f32;
+            let dimmed = (luma / 4.0) as u8;
+            image::Rgba([dimmed, dimmed, dimmed, 255])
+        };
+    }
+    let pixels = (actual.width() * actual.height()).max(1) as f64;
+    Comparison {
+        max_channel_diff,
+        over_tolerance,
+        mean_delta_e: (delta_e_sum / pixels) as f32,
+        diff,
+    }
+}
+
+/// CIE76 difference of two sRGB colors, about 2.3 is just noticeable.
+/// Alpha is left to the channel tolerance.
+fn delta_e(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let (a, b) = (lab(a), lab(b));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// CIE L*a*b* under D65.
+fn lab(rgba: [u8; 4]) -> [f32; 3] {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(rgba[0]), linear(rgba[1]), linear(rgba[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
@@ -0,0 +1,252 @@
+//! Resolution of the passes apart from the output: higher for anti-aliasing
+//! edges computed in the shader, which multisampling can't reach since every
+//! pass is a single quad, or lower for speed.
+
+use anyhow::{anyhow, bail, Result};
+
+/// Samples of `--accumulate` when only `--motion-blur` is given.
+pub const MOTION_BLUR_SAMPLES: u32 = 16;
+
+/// Filter of an image rendered smaller than the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Upscale {
+    #[default]
+    Bilinear,
+    Nearest,
+}
+
+impl Upscale {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "bilinear" => Ok(Self::Bilinear),
+            "nearest" => Ok(Self::Nearest),
+            _ => Err(anyhow!("invalid upscale filter `{}`, expected bilinear or nearest", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    /// Resolution of every pass relative to the output, filtered to it.
+    pub scale: f32,
+    /// Renders of the image pass averaged into each frame, each shifted by
+    /// a different sub-pixel offset.
+    pub samples: u32,
+    /// Part of the frame interval the samples are spread over, 0 for none.
+    pub shutter: f32,
+    pub upscale: Upscale,
+    /// Seconds per frame the image pass is scaled down to stay within, see
+    /// `DynamicScale`.
+    pub frame_time: Option<f32>,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            samples: 1,
+            shutter: 0.0,
+            upscale: Upscale::Bilinear,
+            frame_time: None,
+        }
+    }
+}
+
+impl Sampling {
+    pub fn parse_scale(s: &str) -> Result<f32> {
+        let scale: f32 = s
+            .trim_end_matches(['x', 'X'])
+            .parse()
+            .map_err(|_| anyhow!("invalid render scale `{}`", s))?;
+        if !(0.1..=8.0).contains(&scale) {
+            bail!("render scale must be between 0.1 and 8");
+        }
+        Ok(scale)
+    }
+
+    /// Whether the image pass needs a target of its own to be resolved from.
+    pub fn resolves(&self) -> bool {
+        self.scale != 1.0 || self.samples > 1 || self.frame_time.is_some()
+    }
+
+    /// Size the passes render at for an output of `size`, scaled down as
+    /// needed to fit in `max` pixels.
+    pub fn size(&self, size: (u32, u32), max: u32) -> (u32, u32) {
+        let scale = self
+            .scale
+            .min(max as f32 / size.0 as f32)
+            .min(max as f32 / size.1 as f32);
+        scaled(size, scale)
+    }
+
+    /// Offset of sample `i` in pixels of the render size, spread over the
+    /// pixel by the Halton sequence. The only sample is not moved.
+    pub fn jitter(&self, i: u32) -> [f32; 2] {
+        if self.samples == 1 {
+            return [0.0; 2];
+        }
+        [halton(i + 1, 2) - 0.5, halton(i + 1, 3) - 0.5]
+    }
+
+    /// Time of sample `i` after the frame, as a part of the frame interval.
+    pub fn time(&self, i: u32) -> f32 {
+        self.shutter * i as f32 / self.samples as f32
+    }
+}
+
+/// `size` times `scale`, at least one pixel.
+pub fn scaled(size: (u32, u32), scale: f32) -> (u32, u32) {
+    let scaled = |v: u32| ((v as f32 * scale).round() as u32).max(1);
+    (scaled(size.0), scaled(size.1))
+}
+
+/// Fraction of the render size the image pass runs at, lowered in steps
+/// while its time per frame is over the target and raised again once there
+/// is room for the next step.
+pub struct DynamicScale {
+    /// Seconds.
+    target: f32,
+    fraction: f32,
+    /// Exponential average of the times since the last change.
+    average: Option<f32>,
+    /// Measurements left to skip after a change, still of the old size.
+    settle: u32,
+}
+
+impl DynamicScale {
+    const MIN: f32 = 0.25;
+    const STEP: f32 = 1.0 / 16.0;
+    const SMOOTHING: f32 = 0.2;
+    const SETTLE: u32 = 8;
+    /// Part of the target a frame may go over without lowering the fraction,
+    /// so a frame interval held at the refresh rate by vsync does not.
+    const TOLERANCE: f32 = 0.05;
+
+    pub fn new(target: f32) -> Self {
+        Self {
+            target,
+            fraction: 1.0,
+            average: None,
+            settle: 0,
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    /// Feeds the time of a frame, in seconds.
+    pub fn measure(&mut self, time: f32) {
+        if self.settle > 0 {
+            self.settle -= 1;
+            return;
+        }
+        let average = match self.average {
+            Some(average) => average + (time - average) * Self::SMOOTHING,
+            None => time,
+        };
+        self.average = Some(average);
+        // the time grows with the pixels, the square of the fraction
+        let ideal = self.fraction * (self.target / average).sqrt();
+        let fraction = if average > self.target * (1.0 + Self::TOLERANCE) {
+            ((ideal / Self::STEP).floor() * Self::STEP).min(self.fraction - Self::STEP)
+        } else if ideal >= self.fraction + Self::STEP {
+            self.fraction + Self::STEP
+        } else {
+            return;
+        };
+        let fraction = fraction.clamp(Self::MIN, 1.0);
+        if fraction != self.fraction {
+            self.fraction = fraction;
+            self.average = None;
+            self.settle = Self::SETTLE;
+        }
+    }
+}
+
+/// Element `i` of the van der Corput sequence in `base`, in `0..1`.
+fn halton(mut i: u32, base: u32) -> f32 {
+    let (mut result, mut fraction) = (0.0, 1.0);
+    while i > 0 {
+        fraction /= base as f32;
+        result += fraction * (i % base) as f32;
+        i /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(scale: f32) -> Sampling {
+        Sampling {
+            scale,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn size_fits_in_max() {
+        assert_eq!(scale(2.0).size((1000, 500), 4096), (2000, 1000));
+        // uniformly, keeping the aspect ratio
+        assert_eq!(scale(2.0).size((3000, 1000), 4096), (4096, 1365));
+        assert_eq!(scale(1.0).size((5000, 100), 4096), (4096, 82));
+        assert_eq!(scale(0.1).size((4, 40), 4096), (1, 4));
+    }
+
+    /// Feeds `time` until the fraction changes or `n` frames went by.
+    fn run(scale: &mut DynamicScale, time: f32, n: u32) -> f32 {
+        let before = scale.fraction();
+        for _ in 0..n {
+            scale.measure(time);
+            if scale.fraction() != before {
+                break;
+            }
+        }
+        scale.fraction()
+    }
+
+    #[test]
+    fn dynamic_scale_lowers_to_min() {
+        let target = 1.0 / 60.0;
+        let mut scale = DynamicScale::new(target);
+        // four times the time, half the pixels on each side
+        assert_eq!(run(&mut scale, 4.0 * target, 1), 0.5);
+        // frames of the old size are skipped
+        for _ in 0..DynamicScale::SETTLE {
+            scale.measure(100.0 * target);
+        }
+        assert_eq!(scale.fraction(), 0.5);
+        assert_eq!(run(&mut scale, 100.0 * target, 1), DynamicScale::MIN);
+        assert_eq!(run(&mut scale, 100.0 * target, 100), DynamicScale::MIN);
+    }
+
+    #[test]
+    fn dynamic_scale_raises_in_steps() {
+        let target = 1.0 / 60.0;
+        let mut scale = DynamicScale::new(target);
+        run(&mut scale, 100.0 * target, 1);
+        let mut fractions = Vec::new();
+        for _ in 0..20 {
+            fractions.push(run(&mut scale, target / 100.0, 100));
+        }
+        assert_eq!(fractions[0], DynamicScale::MIN + DynamicScale::STEP);
+        assert!(fractions
+            .windows(2)
+            .all(|w| w[1] - w[0] <= DynamicScale::STEP));
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn dynamic_scale_holds_near_the_target() {
+        let target = 1.0 / 60.0;
+        let mut scale = DynamicScale::new(target);
+        // vsync holding the frame a little over the target
+        assert_eq!(run(&mut scale, target * 1.04, 100), 1.0);
+        let mut scale = DynamicScale::new(target);
+        run(&mut scale, 4.0 * target, 1);
+        // the next step up would not fit
+        assert_eq!(run(&mut scale, target, 100), 0.5);
+    }
+}
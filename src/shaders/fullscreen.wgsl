@@ -0,0 +1,18 @@
+// Vertex stage used for shaders that only provide a fragment stage.
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position * 2.0, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    return out;
+}
@@ -0,0 +1,25 @@
+// Draws the error overlay texture over the viewport set by `Overlay::draw`.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // one triangle covering the viewport, (0, 0) at its top-left corner
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_overlay: texture_2d<f32>;
+@group(0) @binding(1)
+var s_overlay: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_overlay, s_overlay, in.tex_coords);
+}
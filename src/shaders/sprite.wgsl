@@ -14,11 +14,19 @@ struct Camera {
 @group(0) @binding(0) 
 var<uniform> camera: Camera;
 
+// Mirrors `MainUniforms` in src/uniforms/uniforms.rs
 struct Uniforms {
     time: f32,
+    time_delta: f32,
     resolution: vec2<f32>,
     mouse_position: vec2<f32>,
     zoom: vec2<f32>,
+    frame: i32,
+    frame_rate: f32,
+    sample_rate: f32,
+    mouse: vec4<f32>,
+    date: vec4<f32>,
+    tile_offset: vec2<f32>,
 }
 @group(2) @binding(0)
 var<uniform> u: Uniforms;
@@ -0,0 +1,81 @@
+// Maps the image target to the display, see `Tonemap::draw`.
+struct Settings {
+    // 0: none, 1: Reinhard, 2: ACES
+    mode: u32,
+    // linear scale, 2^stops
+    exposure: f32,
+    // of this draw when accumulating, 1 otherwise
+    weight: f32,
+    // of a smaller source, 0: bilinear, 1: nearest
+    upscale: u32,
+    // source pixels per target pixel
+    scale: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var t_image: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: Settings;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // one triangle covering the target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The source under the target pixel at `position`, interpolated when the
+// source is smaller and through a tent filter as wide as two target pixels
+// when it is larger. Pixels are loaded rather than sampled, 32-bit float
+// textures may not filter.
+fn resolve(position: vec2<f32>) -> vec3<f32> {
+    let scale = settings.scale;
+    let center = position * scale;
+    let last = vec2<i32>(textureDimensions(t_image)) - 1;
+    if scale.x <= 1.0 && scale.y <= 1.0 {
+        if settings.upscale == 1u {
+            return textureLoad(t_image, vec2<i32>(center), 0).rgb;
+        }
+        let p = center - 0.5;
+        let base = floor(p);
+        let t = p - base;
+        let i = vec2<i32>(base);
+        let a = textureLoad(t_image, clamp(i, vec2<i32>(0), last), 0).rgb;
+        let b = textureLoad(t_image, clamp(i + vec2<i32>(1, 0), vec2<i32>(0), last), 0).rgb;
+        let c = textureLoad(t_image, clamp(i + vec2<i32>(0, 1), vec2<i32>(0), last), 0).rgb;
+        let d = textureLoad(t_image, clamp(i + vec2<i32>(1, 1), vec2<i32>(0), last), 0).rgb;
+        return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+    }
+    let lo = vec2<i32>(floor(center - scale));
+    let hi = vec2<i32>(ceil(center + scale));
+    var sum = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var y = lo.y; y < hi.y; y++) {
+        for (var x = lo.x; x < hi.x; x++) {
+            let d = abs(vec2<f32>(f32(x), f32(y)) + 0.5 - center) / scale;
+            let w = max(1.0 - d.x, 0.0) * max(1.0 - d.y, 0.0);
+            let texel = clamp(vec2<i32>(x, y), vec2<i32>(0), last);
+            sum += w * textureLoad(t_image, texel, 0).rgb;
+            total += w;
+        }
+    }
+    return sum / total;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = resolve(position.xy) * settings.exposure;
+    var color = hdr;
+    if settings.mode == 1u {
+        color = hdr / (1.0 + hdr);
+    } else if settings.mode == 2u {
+        color = aces(hdr);
+    }
+    // the target is sRGB, encoding happens on store
+    return vec4<f32>(max(color, vec3<f32>(0.0)), 1.0) * settings.weight;
+}
@@ -0,0 +1,847 @@
+//! Shadertoy's JSON format, as its API and its export write it. `import`
+//! turns such a file into a project directory with a `stoy.toml`,
+//! `export-json` turns the passes of the options back into one.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    channel::{ChannelInput, ChannelSource, Filter, Wrap, CHANNEL_COUNT},
+    cli::Options,
+    manifest::{self, Info, Manifest},
+    params::{Param, ParamValue},
+    pass::{check_cycles, PassId, BUFFER_COUNT},
+    source::{ShaderSource, SourceOptions},
+    stoy::ShaderLang,
+    tonemap::Operator,
+};
+
+/// Output id of the image pass.
+const IMAGE_ID: i64 = 37;
+/// Output id of Buffer A, B–D follow.
+const BUFFER_ID: i64 = 257;
+/// Output ids of Buffer A–D in newer files.
+const BUFFER_KEYS: [&str; BUFFER_COUNT] = ["4dXGR8", "XsXGR8", "4sXGR8", "XdfGR8"];
+/// File of the Common tab in an imported project.
+const COMMON_FILE: &str = "common.glsl";
+/// Where Shadertoy serves the textures and cubemaps channels read.
+const MEDIA_PATH: &str = "/media/a/";
+/// Cube face names of `ChannelSource::Cubemap`, in the order of
+/// Shadertoy's `<name>`, `<name>_1` … `<name>_5` files.
+const CUBE_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// A shader as Shadertoy's API and export write it, leaving out what has
+/// no counterpart here.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Shader {
+    ver: String,
+    info: ShaderInfo,
+    renderpass: Vec<RenderPass>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct ShaderInfo {
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct RenderPass {
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    code: Option<String>,
+    name: Option<String>,
+    description: String,
+    /// `image`, `buffer`, `common`, `cubemap` or `sound`.
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Input {
+    /// Output id of the buffer read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<OutputId>,
+    /// `filepath` in older files.
+    #[serde(alias = "filepath")]
+    src: String,
+    /// `type` in older files.
+    #[serde(alias = "type")]
+    ctype: String,
+    channel: Option<i64>,
+    sampler: Option<Sampler>,
+    published: i64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Sampler {
+    filter: Option<String>,
+    wrap: Option<String>,
+    vflip: Option<Flag>,
+    srgb: Option<Flag>,
+    internal: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Output {
+    id: Option<OutputId>,
+    channel: i64,
+}
+
+/// The file `export` writes, the way the API answers.
+#[derive(Serialize)]
+struct Export {
+    #[serde(rename = "Shader")]
+    shader: Shader,
+}
+
+/// Output id, a number in older files and a string in newer ones.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum OutputId {
+    Number(i64),
+    Key(String),
+}
+
+/// A boolean, which Shadertoy writes as `"true"` or `"false"`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Flag {
+    Bool(bool),
+    Text(String),
+}
+
+impl Flag {
+    fn get(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            Self::Text(s) => s.parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub json: PathBuf,
+    /// Project directory, the JSON file without its extension if `None`.
+    pub out: Option<PathBuf>,
+}
+
+/// Writes the GLSL of every pass and a `stoy.toml` into a new project
+/// directory. Textures and cubemaps are copied from next to the JSON file,
+/// where Shadertoy's media files have to be downloaded to; what has no
+/// counterpart here is left out with a warning.
+pub fn import(options: &ImportOptions) -> Result<()> {
+    let text = fs::read_to_string(&options.json)
+        .with_context(|| format!("failed to read `{}`", options.json.display()))?;
+    let shader = serde_json::from_str(&text)
+        .map_err(anyhow::Error::from)
+        .and_then(find_shader)
+        .map_err(|e| anyhow!("{}: {}", options.json.display(), e))?;
+    let passes = &shader.renderpass;
+    let media = options.json.parent().unwrap_or(Path::new(""));
+    let dir = options
+        .out
+        .clone()
+        .unwrap_or_else(|| options.json.with_extension(""));
+    let manifest_path = dir.join(manifest::FILE_NAME);
+    if manifest_path.exists() {
+        bail!("`{}` exists, pass another --out", manifest_path.display());
+    }
+
+    // everything is checked before anything is written
+    let mut imported = Vec::new();
+    for pass in passes {
+        let kind = pass.kind.as_str();
+        let name = pass.name.as_deref().unwrap_or(kind);
+        let id = match kind {
+            "image" => PassId::Image,
+            "buffer" => match output_buffer(pass) {
+                Some(i) => PassId::Buffer(i),
+                None => {
+                    eprintln!("{}: not one of Buffer A–D, leaving it out", name);
+                    continue;
+                }
+            },
+            "common" => continue,
+            _ => {
+                eprintln!(
+                    "{}: {} passes are not supported, leaving it out",
+                    name, kind
+                );
+                continue;
+            }
+        };
+        let code = pass
+            .code
+            .as_deref()
+            .ok_or_else(|| anyhow!("{}: {}: no code", options.json.display(), name))?;
+        imported.push((id, name, code, &pass.inputs));
+    }
+    if !imported.iter().any(|(id, ..)| *id == PassId::Image) {
+        bail!("{}: no image pass", options.json.display());
+    }
+    let buffers: Vec<usize> = imported
+        .iter()
+        .filter_map(|(id, ..)| match id {
+            PassId::Buffer(i) => Some(*i),
+            _ => None,
+        })
+        .collect();
+    let mut reads: [Vec<usize>; BUFFER_COUNT] = Default::default();
+    for (id, _, _, inputs) in &imported {
+        let PassId::Buffer(b) = *id else { continue };
+        let read = inputs.iter().filter(|input| input.ctype == "buffer");
+        reads[b].extend(read.filter_map(input_buffer));
+    }
+    check_cycles(&reads).map_err(|e| anyhow!("{}: {}", options.json.display(), e))?;
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create `{}`", dir.display()))?;
+
+    let common: Vec<&str> = passes
+        .iter()
+        .filter(|pass| pass.kind == "common")
+        .filter_map(|pass| pass.code.as_deref())
+        .collect();
+
+    let mut sections = Vec::new();
+    for &(id, name, code, inputs) in &imported {
+        let file = format!("{}.glsl", id.name());
+        fs::write(dir.join(&file), code)
+            .with_context(|| format!("failed to write `{}`", dir.join(&file).display()))?;
+
+        let mut section = toml::Table::new();
+        section.insert("shader".into(), file.into());
+        if id != PassId::Image {
+            section.insert("format".into(), "rgba32f".into());
+        }
+        let mut channels = toml::Table::new();
+        for input in inputs {
+            let channel = input.channel.unwrap_or(-1);
+            if !(0..CHANNEL_COUNT as i64).contains(&channel) {
+                eprintln!(
+                    "{}: channel {} does not exist, leaving it out",
+                    name, channel
+                );
+                continue;
+            }
+            let channel = channel as usize;
+            let at = format!("{}: iChannel{}", name, channel);
+            if let Some(entry) = import_input(input, &at, &buffers, media, &dir)? {
+                channels.insert(channel.to_string(), entry);
+            }
+        }
+        if !channels.is_empty() {
+            section.insert("channels".into(), channels.into());
+        }
+        sections.push((id, section));
+    }
+    // the image first, then the buffers in order
+    sections.sort_by_key(|(id, _)| match id {
+        PassId::Buffer(i) => *i + 1,
+        _ => 0,
+    });
+
+    let info = &shader.info;
+    let title = info.name.as_deref().unwrap_or("Untitled");
+    let mut manifest = toml::Table::new();
+    if !common.is_empty() {
+        let path = dir.join(COMMON_FILE);
+        fs::write(&path, common.join("\n"))
+            .with_context(|| format!("failed to write `{}`", path.display()))?;
+        manifest.insert("common".into(), COMMON_FILE.into());
+    }
+    let mut project = toml::Table::new();
+    project.insert("name".into(), title.into());
+    if let Some(author) = &info.username {
+        project.insert("author".into(), author.as_str().into());
+    }
+    if let Some(description) = &info.description {
+        project.insert("description".into(), description.as_str().into());
+    }
+    if !info.tags.is_empty() {
+        project.insert("tags".into(), info.tags.clone().into());
+    }
+    manifest.insert("info".into(), project.into());
+    let passes: toml::Table = sections
+        .into_iter()
+        .map(|(id, section)| (id.name(), section.into()))
+        .collect();
+    manifest.insert("passes".into(), passes.into());
+    let toml = toml::to_string(&manifest)?;
+    fs::write(&manifest_path, toml)
+        .with_context(|| format!("failed to write `{}`", manifest_path.display()))?;
+
+    println!("Imported `{}` into {}", title, dir.display());
+    Ok(())
+}
+
+/// The shader object of an API response, `{"Shader": {…}}`, of a list
+/// holding one, or the object itself.
+fn find_shader(json: Value) -> Result<Shader> {
+    let mut shader = match json {
+        Value::Array(mut shaders) if shaders.len() == 1 => shaders.remove(0),
+        Value::Array(shaders) => bail!("the file holds {} shaders, expected one", shaders.len()),
+        json => json,
+    };
+    if let Some(inner) = shader.get_mut("Shader") {
+        shader = inner.take();
+    }
+    if shader.get("renderpass").is_none() {
+        bail!("not a Shadertoy shader, it has no renderpass list");
+    }
+    Ok(serde_json::from_value(shader)?)
+}
+
+/// Buffer a buffer pass renders into, from its output id or its name.
+fn output_buffer(pass: &RenderPass) -> Option<usize> {
+    pass.outputs
+        .first()
+        .and_then(|output| output.id.as_ref())
+        .and_then(buffer_of_id)
+        .or_else(|| {
+            let name = pass.name.as_deref()?;
+            crate::channel::parse_buffer_name(name.strip_prefix("Buffer ")?)
+        })
+}
+
+fn buffer_of_id(id: &OutputId) -> Option<usize> {
+    match id {
+        OutputId::Number(n) => usize::try_from(n - BUFFER_ID)
+            .ok()
+            .filter(|i| *i < BUFFER_COUNT),
+        OutputId::Key(s) => BUFFER_KEYS.iter().position(|key| key == s),
+    }
+}
+
+/// The buffer a `buffer` input reads.
+fn input_buffer(input: &Input) -> Option<usize> {
+    input.id.as_ref().and_then(buffer_of_id).or_else(|| {
+        // /media/previz/buffer00.png
+        let file_name = input.src.rsplit('/').next().unwrap_or_default();
+        let digit = file_name.strip_prefix("buffer0")?.chars().next()?;
+        digit
+            .to_digit(10)
+            .map(|d| d as usize)
+            .filter(|d| *d < BUFFER_COUNT)
+    })
+}
+
+/// The channel entry of the manifest for an input, `None` when it has no
+/// counterpart here. `buffers` are those imported.
+fn import_input(
+    input: &Input,
+    at: &str,
+    buffers: &[usize],
+    media: &Path,
+    dir: &Path,
+) -> Result<Option<toml::Value>> {
+    let kind = input.ctype.as_str();
+    let src = input.src.as_str();
+    let file_name = src.rsplit('/').next().unwrap_or_default();
+    let missing = || {
+        eprintln!(
+            "{}: `{}` is not next to the JSON file, download it to `{}` and import again",
+            at,
+            src,
+            media.join(file_name).display()
+        );
+        Ok(None)
+    };
+    let source = match kind {
+        "buffer" => match input_buffer(input) {
+            Some(i) if buffers.contains(&i) => PassId::Buffer(i).name(),
+            Some(i) => {
+                let buffer = PassId::Buffer(i).name();
+                eprintln!(
+                    "{}: reads {}, which has no pass, leaving it out",
+                    at, buffer
+                );
+                return Ok(None);
+            }
+            None => {
+                eprintln!("{}: reads an unknown buffer, leaving it out", at);
+                return Ok(None);
+            }
+        },
+        "texture" if !src.starts_with(MEDIA_PATH) => {
+            eprintln!(
+                "{}: `{}` is not a Shadertoy texture, leaving it out",
+                at, src
+            );
+            return Ok(None);
+        }
+        "texture" => {
+            let from = media.join(file_name);
+            if !from.is_file() {
+                return missing();
+            }
+            copy(&from, &dir.join(file_name))?;
+            file_name.to_string()
+        }
+        "cubemap" if src.starts_with(MEDIA_PATH) => {
+            let (stem, ext) = file_name.rsplit_once('.').unwrap_or((file_name, "png"));
+            let faces: Vec<PathBuf> = (0..CUBE_FACES.len())
+                .map(|i| match i {
+                    0 => media.join(file_name),
+                    i => media.join(format!("{}_{}.{}", stem, i, ext)),
+                })
+                .collect();
+            if !faces.iter().all(|face| face.is_file()) {
+                return missing();
+            }
+            for (face, name) in faces.iter().zip(CUBE_FACES) {
+                copy(face, &dir.join(stem).join(format!("{}.{}", name, ext)))?;
+            }
+            format!("cubemap:{}", stem)
+        }
+        // keyboard, webcam, mic, music, musicstream, video, volume and Cube A
+        _ => {
+            eprintln!("{}: {} inputs are not supported, leaving it out", at, kind);
+            return Ok(None);
+        }
+    };
+
+    let default = ChannelInput::parse(&source)?.sampler;
+    let sampler = input.sampler.as_ref();
+    let mut settings = toml::Table::new();
+    let filter = match sampler.and_then(|s| s.filter.as_deref()) {
+        Some("nearest") => Some(Filter::Nearest),
+        Some("linear") => Some(Filter::Linear),
+        Some("mipmap") => Some(Filter::Mipmap),
+        _ => None,
+    };
+    if let Some(filter) = filter.filter(|f| *f != default.filter) {
+        settings.insert("filter".into(), filter_name(filter).into());
+    }
+    let wrap = match sampler.and_then(|s| s.wrap.as_deref()) {
+        Some("clamp") => Some(Wrap::Clamp),
+        Some("repeat") => Some(Wrap::Repeat),
+        _ => None,
+    };
+    if let Some(wrap) = wrap.filter(|w| *w != default.wrap) {
+        settings.insert("wrap".into(), wrap_name(wrap).into());
+    }
+    // buffers are never flipped, Shadertoy's files still say so
+    if let Some(vflip) = sampler
+        .and_then(|s| s.vflip.as_ref())
+        .and_then(Flag::get)
+        .filter(|v| kind != "buffer" && *v != default.vflip)
+    {
+        settings.insert("vflip".into(), vflip.into());
+    }
+    if settings.is_empty() {
+        return Ok(Some(source.into()));
+    }
+    let mut table = toml::Table::new();
+    table.insert("source".into(), source.into());
+    table.extend(settings);
+    Ok(Some(table.into()))
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create `{}`", parent.display()))?;
+    }
+    // importing into the directory of the JSON file
+    if from.canonicalize().ok() == to.canonicalize().ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)
+        .with_context(|| format!("failed to copy `{}` to `{}`", from.display(), to.display()))?;
+    Ok(())
+}
+
+fn filter_name(filter: Filter) -> &'static str {
+    match filter {
+        Filter::Nearest => "nearest",
+        Filter::Linear => "linear",
+        Filter::Mipmap => "mipmap",
+    }
+}
+
+fn wrap_name(wrap: Wrap) -> &'static str {
+    match wrap {
+        Wrap::Clamp => "clamp",
+        Wrap::Repeat => "repeat",
+        Wrap::Mirror => "mirror",
+    }
+}
+
+/// Writes the image and buffer passes of `options` as a Shadertoy JSON
+/// file, with the info of the manifest and the custom uniforms as constants
+/// in the Common tab, followed by the common file. Includes are expanded
+/// in place. Image files are referenced as Shadertoy media by
+/// their file name, Shadertoy only offers its own.
+pub fn export(options: &Options, out: &Path) -> Result<()> {
+    if options.compute.shader.is_some() {
+        bail!("Shadertoy has no compute shaders, remove --compute to export");
+    }
+    let image = options
+        .shader
+        .as_ref()
+        .ok_or_else(|| anyhow!("no shader given, the built-in shader cannot be exported"))?;
+    if options.tonemap != Operator::None || options.exposure != 0.0 {
+        eprintln!("Shadertoy shows the image pass as it is, leaving out the tonemapping");
+    }
+    let info = match &options.manifest {
+        Some(path) => Manifest::load(path)?.info,
+        None => Info::default(),
+    };
+
+    // the Common tab comes in front of the passes already
+    let sources = SourceOptions {
+        common: None,
+        ..options.sources.clone()
+    };
+    let mut common = String::new();
+    if !options.uniforms.is_empty() {
+        common += &common_code(&options.uniforms);
+    }
+    if let Some(path) = &options.sources.common {
+        if ShaderLang::from_path(path) != ShaderLang::Glsl {
+            bail!("`{}` is WGSL, Shadertoy runs GLSL", path.display());
+        }
+        if !common.is_empty() {
+            common.push('\n');
+        }
+        common += &ShaderSource::load(path, &sources)
+            .map_err(|e| anyhow!(e))?
+            .code;
+    }
+
+    let mut passes = Vec::new();
+    if !common.is_empty() {
+        passes.push(RenderPass {
+            code: Some(common),
+            name: Some("Common".to_string()),
+            kind: "common".to_string(),
+            ..Default::default()
+        });
+    }
+    let buffers = options
+        .buffers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, path)| Some((PassId::Buffer(i), path.as_ref()?)));
+    for (id, path) in [(PassId::Image, image)].into_iter().chain(buffers) {
+        passes.push(export_pass(options, &sources, id, path)?);
+    }
+
+    let name = info.name.clone().unwrap_or_else(|| {
+        options
+            .manifest
+            .as_ref()
+            .and_then(|m| {
+                m.canonicalize()
+                    .ok()?
+                    .parent()?
+                    .file_name()
+                    .map(|n| n.to_owned())
+            })
+            .or_else(|| image.file_stem().map(|s| s.to_owned()))
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let count = passes.len();
+    let shader = Shader {
+        ver: "0.1".to_string(),
+        info: ShaderInfo {
+            name: Some(name),
+            username: info.author,
+            description: Some(info.description.unwrap_or_default()),
+            tags: info.tags,
+        },
+        renderpass: passes,
+    };
+    let json = serde_json::to_string_pretty(&Export { shader })?;
+    fs::write(out, json + "\n").with_context(|| format!("failed to write `{}`", out.display()))?;
+    println!("Exported {} passes to {}", count, out.display());
+    Ok(())
+}
+
+/// The custom uniforms as GLSL constants of the same names.
+fn common_code(params: &[Param]) -> String {
+    let vector = |v: &[f32]| {
+        let items: Vec<String> = v.iter().map(|x| format!("{:?}", x)).collect();
+        format!("vec{}({})", v.len(), items.join(", "))
+    };
+    let mut code = String::from("// uniforms of the project\n");
+    for param in params {
+        let (ty, value) = match param.value {
+            ParamValue::Float(v) => ("float", format!("{:?}", v)),
+            ParamValue::Int(v) => ("int", v.to_string()),
+            ParamValue::Bool(v) => ("bool", v.to_string()),
+            ParamValue::Vec2(v) => ("vec2", vector(&v)),
+            ParamValue::Vec3(v) => ("vec3", vector(&v)),
+            ParamValue::Vec4(v) => ("vec4", vector(&v)),
+        };
+        code += &format!("const {} {} = {};\n", ty, param.name, value);
+    }
+    code
+}
+
+fn export_pass(
+    options: &Options,
+    sources: &SourceOptions,
+    id: PassId,
+    path: &Path,
+) -> Result<RenderPass> {
+    if ShaderLang::from_path(path) != ShaderLang::Glsl {
+        bail!("`{}` is WGSL, Shadertoy runs GLSL", path.display());
+    }
+    let code = ShaderSource::load(path, sources)
+        .map_err(|e| anyhow!(e))?
+        .code;
+    let (name, kind, output) = match id {
+        PassId::Buffer(i) => {
+            if options.buffer_sizes[i].is_some() {
+                eprintln!(
+                    "{}: Shadertoy buffers have the size of the canvas, leaving out its size",
+                    id.name()
+                );
+            }
+            let letter = (b'A' + i as u8) as char;
+            (format!("Buffer {}", letter), "buffer", BUFFER_ID + i as i64)
+        }
+        _ => ("Image".to_string(), "image", IMAGE_ID),
+    };
+
+    let mut inputs = Vec::new();
+    for channel in 0..CHANNEL_COUNT {
+        let Some(binding) = options
+            .channels
+            .iter()
+            .rev()
+            .find(|b| b.pass == id && b.channel == channel)
+        else {
+            continue;
+        };
+        let at = format!("{}: iChannel{}", id.name(), channel);
+        let input = &binding.input;
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let (kind, src, input_id) = match &input.source {
+            ChannelSource::Buffer(i) => (
+                "buffer",
+                format!("/media/previz/buffer0{}.png", i),
+                Some(BUFFER_ID + *i as i64),
+            ),
+            ChannelSource::File(path) => {
+                ("texture", MEDIA_PATH.to_string() + &file_name(path), None)
+            }
+            ChannelSource::Cubemap(dir) => {
+                // the directory names the set, as an import leaves it
+                let ext = fs::read_dir(dir)
+                    .ok()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .find(|face| face.file_stem().is_some_and(|s| s == CUBE_FACES[0]))
+                    .and_then(|face| Some(face.extension()?.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "png".to_string());
+                (
+                    "cubemap",
+                    format!("{}{}.{}", MEDIA_PATH, file_name(dir), ext),
+                    None,
+                )
+            }
+            ChannelSource::Storage(_) => {
+                bail!("{}: Shadertoy has no storage textures", at)
+            }
+            ChannelSource::Builtin | ChannelSource::Noise => {
+                eprintln!("{}: Shadertoy has no such texture, leaving it out", at);
+                continue;
+            }
+        };
+        if input.sampler.wrap == Wrap::Mirror {
+            eprintln!("{}: Shadertoy has no mirrored wrapping, using repeat", at);
+        }
+        let wrap = match input.sampler.wrap {
+            Wrap::Clamp => "clamp",
+            Wrap::Repeat | Wrap::Mirror => "repeat",
+        };
+        let internal = if kind == "buffer" { "float" } else { "byte" };
+        inputs.push(Input {
+            id: input_id.map(OutputId::Number),
+            src,
+            ctype: kind.to_string(),
+            channel: Some(channel as i64),
+            sampler: Some(Sampler {
+                filter: Some(filter_name(input.sampler.filter).to_string()),
+                wrap: Some(wrap.to_string()),
+                vflip: Some(Flag::Text(input.sampler.vflip.to_string())),
+                srgb: Some(Flag::Text("false".to_string())),
+                internal: Some(internal.to_string()),
+            }),
+            published: 1,
+        });
+    }
+
+    Ok(RenderPass {
+        inputs,
+        outputs: vec![Output {
+            id: Some(OutputId::Number(output)),
+            channel: 0,
+        }],
+        code: Some(code),
+        name: Some(name),
+        description: String::new(),
+        kind: kind.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = r#"{"Shader": {
+        "ver": "0.1",
+        "info": {
+            "id": "XsXXDn",
+            "name": "Round \"trip\"",
+            "username": "someone",
+            "description": "line one\nline two",
+            "tags": ["a", "b"]
+        },
+        "renderpass": [
+            {
+                "inputs": [],
+                "outputs": [],
+                "code": "float wave(float x) { return 0.5 + 0.5 * sin(x); }\n",
+                "name": "Common",
+                "description": "",
+                "type": "common"
+            },
+            {
+                "inputs": [
+                    {
+                        "id": 257,
+                        "src": "/media/previz/buffer00.png",
+                        "ctype": "buffer",
+                        "channel": 0,
+                        "sampler": {"filter": "nearest", "wrap": "clamp", "vflip": "true"},
+                        "published": 1
+                    },
+                    {
+                        "id": 5,
+                        "src": "/media/a/tex.png",
+                        "ctype": "texture",
+                        "channel": 1,
+                        "sampler": {"filter": "linear", "wrap": "repeat", "vflip": "false"},
+                        "published": 1
+                    }
+                ],
+                "outputs": [{"id": 37, "channel": 0}],
+                "code": "void mainImage(out vec4 c, in vec2 p) {\n    c = texture(iChannel0, p / iResolution.xy);\n}\n",
+                "name": "Image",
+                "description": "",
+                "type": "image"
+            },
+            {
+                "inputs": [
+                    {
+                        "id": "4dXGR8",
+                        "src": "/media/previz/buffer00.png",
+                        "ctype": "buffer",
+                        "channel": 0,
+                        "sampler": {"filter": "linear", "wrap": "clamp", "vflip": "true"},
+                        "published": 1
+                    }
+                ],
+                "outputs": [{"id": "4dXGR8", "channel": 0}],
+                "code": "void mainImage(out vec4 c, in vec2 p) {\n    c = vec4(wave(iTime));\n}\n",
+                "name": "Buffer A",
+                "description": "",
+                "type": "buffer"
+            }
+        ]
+    }}"#;
+
+    fn read(path: &Path) -> Shader {
+        let text = fs::read_to_string(path).unwrap();
+        find_shader(serde_json::from_str(&text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn import_then_export() {
+        let dir =
+            std::env::temp_dir().join(format!("shader_toy_round_trip_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("shader.json");
+        fs::write(&json, SHADER).unwrap();
+        image::RgbaImage::new(1, 1)
+            .save(dir.join("tex.png"))
+            .unwrap();
+        let project = dir.join("project");
+        import(&ImportOptions {
+            json: json.clone(),
+            out: Some(project.clone()),
+        })
+        .unwrap();
+
+        let out = dir.join("back.json");
+        let args = [
+            "export-json",
+            project.to_str().unwrap(),
+            "--out",
+            out.to_str().unwrap(),
+        ];
+        let options = Options::parse(args.map(String::from)).unwrap().unwrap();
+        export(&options, &out).unwrap();
+        let (before, after) = (read(&json), read(&out));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(after.info.name, before.info.name);
+        assert_eq!(after.info.username, before.info.username);
+        assert_eq!(after.info.description, before.info.description);
+        assert_eq!(after.info.tags, before.info.tags);
+        assert_eq!(after.renderpass.len(), before.renderpass.len());
+        for (before, after) in before.renderpass.iter().zip(&after.renderpass) {
+            assert_eq!(after.kind, before.kind);
+            assert_eq!(after.name, before.name);
+            assert_eq!(after.code, before.code);
+            assert_eq!(output_buffer(after), output_buffer(before));
+            assert_eq!(after.inputs.len(), before.inputs.len());
+            for (before, after) in before.inputs.iter().zip(&after.inputs) {
+                assert_eq!(after.ctype, before.ctype);
+                assert_eq!(after.src, before.src);
+                assert_eq!(after.channel, before.channel);
+                assert_eq!(
+                    after.id.as_ref().and_then(buffer_of_id),
+                    before.id.as_ref().and_then(buffer_of_id)
+                );
+                let sampler = |input: &Input| {
+                    let sampler = input.sampler.as_ref().unwrap();
+                    // buffers are never flipped
+                    let vflip = sampler.vflip.as_ref().and_then(Flag::get);
+                    (
+                        sampler.filter.clone(),
+                        sampler.wrap.clone(),
+                        vflip.filter(|_| input.ctype != "buffer"),
+                    )
+                };
+                assert_eq!(sampler(after), sampler(before));
+            }
+        }
+    }
+}
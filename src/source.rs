@@ -0,0 +1,378 @@
+//! Shader sources as naga sees them: the Common code in front, every
+//! `#include "file"` line replaced by that file, and a map from the result
+//! back to the files, so diagnostics point at the line that was written.
+
+use std::{
+    collections::HashSet,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use codespan_reporting::{
+    diagnostic::Label,
+    files::SimpleFiles,
+    term::{self, termcolor::NoColor},
+};
+use wgpu::naga;
+
+use crate::stoy::ShaderLang;
+
+/// Where includes are looked for and the code in front of every shader,
+/// `--include` and `--common`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceOptions {
+    /// Searched in order after the directory of the including file.
+    pub include_dirs: Vec<PathBuf>,
+    /// Prepended to every shader in its language, like Shadertoy's Common
+    /// tab.
+    pub common: Option<PathBuf>,
+}
+
+/// A run of `ShaderSource::code` copied from one file.
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    start: usize,
+    len: usize,
+    file: usize,
+    file_start: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShaderSource {
+    /// The expanded code.
+    pub code: String,
+    /// Every file read, with its text.
+    files: Vec<(PathBuf, String)>,
+    /// Runs of `code` in order.
+    pieces: Vec<Piece>,
+}
+
+/// An error with spans in `ShaderSource::code`, as naga reports them.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostic {
+    pub message: String,
+    pub labels: Vec<(naga::Span, String)>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn from_wgsl(err: &naga::front::wgsl::ParseError, code: &str) -> Self {
+        // naga keeps the notes to itself, they are read back from its output
+        let notes = err
+            .emit_to_string(code)
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("= "))
+            .map(str::to_string)
+            .collect();
+        Self {
+            message: err.message().to_string(),
+            labels: err
+                .labels()
+                .map(|(span, label)| (span, label.to_string()))
+                .collect(),
+            notes,
+        }
+    }
+
+    pub fn from_validation<E: std::error::Error>(err: &naga::WithSpan<E>) -> Self {
+        let mut notes = Vec::new();
+        let mut source: &dyn std::error::Error = err.as_inner();
+        while let Some(next) = source.source() {
+            notes.push(next.to_string());
+            source = next;
+        }
+        Self {
+            message: err.as_inner().to_string(),
+            labels: err.spans().cloned().collect(),
+            notes,
+        }
+    }
+}
+
+impl ShaderSource {
+    /// Reads the shader at `path` with everything it includes. Each file
+    /// is included once, later `#include`s of it are skipped.
+    pub fn load(path: &Path, options: &SourceOptions) -> Result<Self, String> {
+        let mut source = Self::default();
+        let mut included = HashSet::new();
+        let lang = ShaderLang::from_path(path);
+        let common = options
+            .common
+            .as_ref()
+            .filter(|common| ShaderLang::from_path(common) == lang);
+        for file in common.into_iter().chain([&path.to_path_buf()]) {
+            source.expand(file, None, &mut Vec::new(), &mut included, options)?;
+            if !source.code.ends_with('\n') {
+                source.code.push('\n');
+            }
+        }
+        Ok(source)
+    }
+
+    /// A shader that is not read from a file, reported as `name`.
+    pub fn from_code(name: &str, code: &str) -> Self {
+        let mut source = Self::default();
+        source.files.push((PathBuf::from(name), code.to_string()));
+        source.push(0, 0, code);
+        source
+    }
+
+    /// Every file the code came from.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// Appends `path`, expanding its includes. `stack` holds the files
+    /// including it, to report cycles.
+    fn expand(
+        &mut self,
+        path: &Path,
+        at: Option<&str>,
+        stack: &mut Vec<PathBuf>,
+        included: &mut HashSet<PathBuf>,
+        options: &SourceOptions,
+    ) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            let err = format!("failed to read `{}`: {}", path.display(), e);
+            at.map_or(err.clone(), |at| format!("{}: {}", at, err))
+        })?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(start) = stack.iter().position(|p| *p == canonical) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|p| {
+                    p.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            return Err(format!(
+                "{}: includes form a cycle: {}",
+                at.unwrap_or_default(),
+                cycle.join(" -> ")
+            ));
+        }
+        if !included.insert(canonical.clone()) {
+            return Ok(());
+        }
+        stack.push(canonical);
+        let file = self.files.len();
+        self.files.push((path.to_path_buf(), text.clone()));
+
+        let mut offset = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let at = || format!("{}:{}", path.display(), i + 1);
+            match include_target(line).map_err(|e| format!("{}: {}", at(), e))? {
+                Some(target) => {
+                    let dir = match path.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                    let found = resolve(target, dir, &options.include_dirs).ok_or_else(|| {
+                        let mut dirs = vec![dir.display().to_string()];
+                        dirs.extend(options.include_dirs.iter().map(|d| d.display().to_string()));
+                        format!("{}: `{}` is not in {}", at(), target, dirs.join(", "))
+                    })?;
+                    self.expand(&found, Some(&at()), stack, included, options)?;
+                    // the line after the include starts a line of its own
+                    if !self.code.is_empty() && !self.code.ends_with('\n') {
+                        self.code.push('\n');
+                    }
+                }
+                None => self.push(file, offset, line),
+            }
+            offset += line.len();
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    fn push(&mut self, file: usize, file_start: usize, text: &str) {
+        let start = self.code.len();
+        match self.pieces.last_mut() {
+            Some(last)
+                if last.file == file
+                    && last.start + last.len == start
+                    && last.file_start + last.len == file_start =>
+            {
+                last.len += text.len()
+            }
+            _ => self.pieces.push(Piece {
+                start,
+                len: text.len(),
+                file,
+                file_start,
+            }),
+        }
+        self.code.push_str(text);
+    }
+
+    /// The file and range a span of `code` was copied from, cut off at the
+    /// end of the run it starts in. Spans between runs, like the end of the
+    /// code, stay at the end of the run before.
+    fn locate(&self, span: naga::Span) -> Option<(usize, Range<usize>)> {
+        let range = span.to_range()?;
+        let piece = self.pieces.iter().rfind(|p| p.start <= range.start)?;
+        let end = piece.start + piece.len;
+        let offset = |i: usize| piece.file_start + i.min(end) - piece.start;
+        Some((
+            piece.file,
+            offset(range.start)..offset(range.end.max(range.start)),
+        ))
+    }
+
+    /// Renders `diagnostic` against the files its spans came from. Spans
+    /// outside them, in generated code, are left out.
+    pub fn emit(&self, diagnostic: &Diagnostic) -> String {
+        let mut files = SimpleFiles::new();
+        for (path, text) in &self.files {
+            files.add(path.display().to_string(), text.as_str());
+        }
+        let labels = diagnostic
+            .labels
+            .iter()
+            .filter_map(|(span, label)| {
+                let (file, range) = self.locate(*span)?;
+                Some(Label::primary(file, range).with_message(label))
+            })
+            .collect();
+        let diagnostic = codespan_reporting::diagnostic::Diagnostic::error()
+            .with_message(&diagnostic.message)
+            .with_labels(labels)
+            .with_notes(diagnostic.notes.clone());
+        let mut writer = NoColor::new(Vec::new());
+        term::emit(&mut writer, &term::Config::default(), &files, &diagnostic)
+            .expect("cannot write error");
+        String::from_utf8_lossy(&writer.into_inner()).into_owned()
+    }
+}
+
+/// The file of an `#include "file"` line.
+fn include_target(line: &str) -> Result<Option<&str>, String> {
+    let Some(rest) = line.trim_start().strip_prefix("#include") else {
+        return Ok(None);
+    };
+    let rest = rest.trim();
+    rest.strip_prefix('"')
+        .and_then(|rest| rest.split_once('"'))
+        .filter(|(_, after)| after.trim().is_empty() || after.trim().starts_with("//"))
+        .map(|(target, _)| Some(target))
+        .ok_or_else(|| format!("expected #include \"<file>\", got `#include {}`", rest))
+}
+
+/// `target` next to the including file, or in the first include
+/// directory holding it.
+fn resolve(target: &str, dir: &Path, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+    [dir]
+        .into_iter()
+        .chain(include_dirs.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(target))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory holding `files`, removed before it is written.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shader_toy_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, text) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn nested_includes() {
+        let dir = project(
+            "nested",
+            &[
+                ("main.glsl", "#include \"a.glsl\"\nmain\n"),
+                ("a.glsl", "a1\n#include \"b.glsl\" // from lib\na2\n"),
+                ("lib/b.glsl", "b\n"),
+            ],
+        );
+        let options = SourceOptions {
+            include_dirs: vec![dir.join("lib")],
+            common: None,
+        };
+        let source = ShaderSource::load(&dir.join("main.glsl"), &options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(source.code, "a1\nb\na2\nmain\n");
+        let names: Vec<_> = source.files().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(names, ["main.glsl", "a.glsl", "b.glsl"]);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = project(
+            "cycle",
+            &[
+                ("main.glsl", "#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "\n#include \"a.glsl\"\n"),
+            ],
+        );
+        let err = ShaderSource::load(&dir.join("main.glsl"), &SourceOptions::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let expected = format!(
+            "{}:2: includes form a cycle: a.glsl -> b.glsl -> a.glsl",
+            dir.join("b.glsl").display()
+        );
+        assert_eq!(err.unwrap_err(), expected);
+    }
+
+    #[test]
+    fn diamond_includes_once() {
+        let dir = project(
+            "diamond",
+            &[
+                (
+                    "main.glsl",
+                    "#include \"a.glsl\"\n#include \"b.glsl\"\nmain\n",
+                ),
+                ("a.glsl", "#include \"c.glsl\"\na\n"),
+                ("b.glsl", "#include \"c.glsl\"\nb\n"),
+                ("c.glsl", "c\n"),
+            ],
+        );
+        let source = ShaderSource::load(&dir.join("main.glsl"), &SourceOptions::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(source.unwrap().code, "c\na\nb\nmain\n");
+    }
+
+    #[test]
+    fn spans_point_into_the_included_file() {
+        let dir = project(
+            "spans",
+            &[
+                ("main.glsl", "one\n#include \"lib.glsl\"\nthree\n"),
+                ("lib.glsl", "first\n  second\n"),
+            ],
+        );
+        let source = ShaderSource::load(&dir.join("main.glsl"), &SourceOptions::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let source = source.unwrap();
+        let span = |word: &str| {
+            let start = source.code.find(word).unwrap();
+            naga::Span::new(start as u32, (start + word.len()) as u32)
+        };
+        assert_eq!(source.locate(span("second")), Some((1, 8..14)));
+        assert_eq!(source.locate(span("three")), Some((0, 24..29)));
+
+        let diagnostic = Diagnostic {
+            message: "unknown identifier".to_string(),
+            labels: vec![(span("second"), "here".to_string())],
+            notes: Vec::new(),
+        };
+        let emitted = source.emit(&diagnostic);
+        let at = format!("{}:2:3", dir.join("lib.glsl").display());
+        assert!(emitted.contains(&at), "{}", emitted);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    quad::{Quad, VERTICES},
+    quad::VERTICES,
     texture::Texture,
 };
 use wgpu::util::DeviceExt;
@@ -19,7 +19,7 @@ impl Sprite {
         layout: &wgpu::BindGroupLayout,
         bytes: &[u8],
     ) -> Self {
-        let texture = Texture::from_bytes(&device, &queue, bytes, am, "spaceship").unwrap();
+        let texture = Texture::from_bytes(device, queue, bytes, am, "spaceship").unwrap();
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout,
@@ -48,6 +48,7 @@ impl Sprite {
         }
     }
 
+    #[allow(dead_code)]
     pub fn from_empty(
         device: &wgpu::Device,
         dimensions: (u32, u32),
@@ -55,7 +56,14 @@ impl Sprite {
         layout: &wgpu::BindGroupLayout,
         label: &str,
     ) -> Self {
-        let texture = Texture::empty(&device, dimensions, Some(am), Some(label)).unwrap();
+        let texture = Texture::empty(
+            device,
+            dimensions,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+            Some(am),
+            Some(label),
+        )
+        .unwrap();
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout,
@@ -84,6 +92,7 @@ impl Sprite {
         }
     }
 
+    #[allow(dead_code)]
     pub fn bind<'a, 'b>(&self, rpass: &'b mut wgpu::RenderPass<'a>) {
         rpass.set_vertex_buffer(0, self.buffer.slice(..));
         rpass.set_bind_group(1, &self.bind_group, &[]);
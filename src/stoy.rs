@@ -1,8 +1,39 @@
 use notify::Watcher;
-use std::{path::Path, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use wgpu::naga;
 
-use crate::{input_manager::{InputEvent, InputManager}, uniforms::uniforms::MainUniforms};
+use wgpu::util::DeviceExt;
+use winit::{
+    event::{ElementState, MouseButton, WindowEvent},
+    keyboard::{Key, NamedKey},
+};
+
+use crate::{
+    capture,
+    channel::{self, ChannelInput, ChannelSource, ChannelUniforms, ChannelViews, CHANNEL_COUNT},
+    cli::Options,
+    compute::Compute,
+    deterministic::Deterministic,
+    glsl, hud,
+    input_manager::{InputEvent, InputManager},
+    layout,
+    overlay::Overlay,
+    panel::Panel,
+    params::{self, Annotation, Param, Params},
+    pass::{self, render_order, Channel, Pass, PassId, PassSize, BUFFER_COUNT},
+    playback::{Command, Playback},
+    profiler::{FrameTimes, Profiler, Source},
+    record::Recorder,
+    sampling::{self, DynamicScale, Sampling},
+    source::{Diagnostic, ShaderSource, SourceOptions},
+    texture::Texture,
+    timer::GpuTimer,
+    tonemap::Tonemap,
+    uniforms::uniforms::MainUniforms,
+};
 use std::sync::mpsc;
 
 use crate::{
@@ -11,25 +42,111 @@ use crate::{
     uniform::Uniform,
 };
 
+/// Shader used when no file is given on the command line.
+const BUILTIN_SHADER: &str = include_str!("./shaders/sprite.wgsl");
 
+/// Format of the average of the image samples, blendable everywhere.
+const RESOLVE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Overlay entry of manifest errors.
+const MANIFEST: &str = "manifest";
+/// Overlay entry of `// @param` errors.
+const PARAMS: &str = "params";
+
+/// New source for the pass at the given index.
+type Reload = (usize, ShaderSource);
 
 pub struct Stoy {
     test_sprite: Sprite,
-    pipeline: wgpu::RenderPipeline,
+    /// Bound to channels without an input, and to the unused half of the
+    /// 2D/cube pair of every channel.
+    black: Texture,
+    black_cube: Texture,
+    /// Buffers in dependency order, followed by the image pass.
+    passes: Vec<Pass>,
+    /// Runs before the passes, `None` without `--compute`.
+    compute: Option<Compute>,
     camera: Camera2D,
     uniforms: Uniform<MainUniforms>,
-    channel: (mpsc::Sender<String>, mpsc::Receiver<String>),
+    /// `uniforms` at the size of the image pass, smaller than the buffers
+    /// with a dynamic scale.
+    image_uniforms: Uniform<MainUniforms>,
+    /// Custom uniforms of every pass and the compute shader.
+    params: Params,
+    /// The custom uniforms of the options, annotations add to them.
+    given_params: Vec<Param>,
+    /// Shown in the window, not drawn by `render_to`.
+    panel: Panel,
+    channel: (mpsc::Sender<Reload>, mpsc::Receiver<Reload>),
+    channel_layout: wgpu::BindGroupLayout,
     pipeline_layout: wgpu::PipelineLayout,
+    /// Format of the surface, or whatever `render_to` draws into.
+    format: wgpu::TextureFormat,
+    /// Output size, the passes render at `sampling.size` of it.
+    size: (u32, u32),
+    sampling: Sampling,
+    /// Largest texture side of the device.
+    max_size: u32,
+    /// Lowers the resolution of the image pass to meet a frame time.
+    dynamic: Option<DynamicScale>,
+    /// Measures each pass, in render order. `dynamic` takes the frame
+    /// interval and the profiler waits for each pass if `None`.
+    timer: Option<GpuTimer>,
+    /// Frame timings, measured while the HUD is shown or a profile is
+    /// written.
+    profiler: Profiler,
+    hud: bool,
+    /// Floating point or supersampled target of the image pass, tonemapped
+    /// into the surface. `None` draws the image pass straight into the
+    /// surface.
+    image_target: Option<(Texture, Tonemap)>,
+    /// Average of the samples of `image_target` at the output size, which
+    /// the tonemap reads instead.
+    resolve: Option<(Texture, Tonemap)>,
+    /// Uniforms and jittered vertices of each sample of the image pass,
+    /// empty with a single sample.
+    samples: Vec<(Uniform<MainUniforms>, wgpu::Buffer)>,
+    /// Frames rendered so far, picks the ping-pong targets.
+    frame: u64,
+    playback: Playback,
+    /// Whether the last `update` started a new frame. A paused frame only
+    /// redraws the image pass from the current buffer contents.
+    advanced: bool,
+    /// Replaces the cursor and the clock for reproducible frames.
+    deterministic: Option<Deterministic>,
+    /// Set by the screenshot key, handled after the next frame.
+    screenshot_requested: bool,
+    screenshot_dir: PathBuf,
+    /// Resolution of screenshots, the window size if `None`.
+    screenshot_size: Option<(u32, u32)>,
+    /// Receives every new frame, rendered like a screenshot at its size.
+    recorder: Option<Recorder>,
     read_lock: std::sync::Arc<std::sync::Mutex<Option<std::time::Instant>>>,
     input: InputManager,
-    // unused - avoid dropping the watcher
-    _watcher: notify::RecommendedWatcher,
+    /// Shader errors shown in the window until the pass reloads.
+    overlay: Overlay,
+    /// The manifest the options came from and its contents, rebuilt from
+    /// `args` when they change.
+    manifest: Option<(PathBuf, String)>,
+    args: Vec<String>,
+    /// Include path and Common code of every shader.
+    sources: SourceOptions,
+    watcher: Option<notify::RecommendedWatcher>,
+    /// Directories `watcher` covers, canonical.
+    watched: Vec<PathBuf>,
 }
 
 impl Stoy {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: &wgpu::TextureFormat) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: &wgpu::TextureFormat,
+        size: (u32, u32),
+        options: &Options,
+    ) -> Self {
         //uniforms
         let uniforms = Uniform::<MainUniforms>::new(device);
+        let image_uniforms = Uniform::<MainUniforms>::new(device);
         let camera_uniform = Uniform::<Camera2DUniform>::new(device);
         //gruops
         let camera = Camera2D::new(camera_uniform);
@@ -42,206 +159,1551 @@ impl Stoy {
             &sprite_layout,
             bytes,
         );
+        let black = Texture::empty(device, (1, 1), BUFFER_FORMAT, None, Some("black")).unwrap();
+        let black_cube = Texture::cube_from_images(
+            device,
+            queue,
+            &vec![image::DynamicImage::new_rgba8(1, 1); 6],
+            Some("black_cube"),
+        )
+        .unwrap();
 
+        // read once more by the passes, the params of all of them come first
+        let sources: Vec<ShaderSource> = options
+            .shaders()
+            .filter_map(|path| ShaderSource::load(path, &options.sources).ok())
+            .collect();
+        let (declared, annotations, params_error) = declare_params(&options.uniforms, &sources);
+        let params = Params::new(device, declared, annotations);
+        let channel_layout = channel::create_bind_group_layout(device);
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Main_pipeline_layout"),
             bind_group_layouts: &[
                 &camera.uniform.bind_group_layout,
-                &sprite_layout,
+                &channel_layout,
                 &uniforms.bind_group_layout,
+                &params.bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/sprite.wgsl"));
-        let pipeline = create_render_pipeline(device, &shader, *format, &pipeline_layout);
+        let mut overlay = Overlay::new(device, *format);
+        if let Some(err) = params_error {
+            eprintln!("{}", err);
+            overlay.set(PARAMS, err);
+        }
+        let sampling = options.sampling;
+        let max_size = device.limits().max_texture_dimension_2d;
+        let render_size = sampling.size(size, max_size);
+        let dynamic = sampling.frame_time.map(DynamicScale::new);
 
-        let (tx, rx) = std::sync::mpsc::channel::<String>();
+        let compute = options.compute.shader.as_ref().and_then(|path| {
+            let layout = &uniforms.bind_group_layout;
+            let mut compute = Compute::new(device, &options.compute, layout, &params, render_size)
+                .map_err(|err| {
+                    let err = format!("{:#}\nSkipping compute.", err);
+                    eprintln!("{}", err);
+                    overlay.set(&PassId::Compute.name(), err);
+                })
+                .ok()?;
+            // like a failed reload, the storage stays for the next one
+            let source = ShaderSource::load(path, &options.sources);
+            if let Err(err) = source.and_then(|source| compute.build(device, source)) {
+                eprintln!("{}", err);
+                overlay.set(&PassId::Compute.name(), err);
+            }
+            Some(compute)
+        });
 
-        let read_lock = std::sync::Arc::new(std::sync::Mutex::new(None));
-        let read_lock_clone = read_lock.clone();
-
-        let mut watcher = notify::RecommendedWatcher::new(
-            move |res: notify::Result<notify::Event>| match res {
-                Ok(evt) => {
-                    println!("evt: {:?}", evt);
-                    if evt.kind.is_modify() || evt.kind.is_create() {
-                        let mut t = read_lock_clone.lock().unwrap();
-                        *t = Some(instant::Instant::now());
-                        /*println!("Reading sprite.wgsl...");
-                        }*/
-                    }
+        let seed = options.deterministic.as_ref().map(|d| d.seed);
+        let load_channels = |id: PassId| -> [Option<Channel>; CHANNEL_COUNT] {
+            let mut inputs: [Option<ChannelInput>; CHANNEL_COUNT] = Default::default();
+            if id == PassId::Image {
+                inputs[0] = Some(ChannelInput::new(ChannelSource::Builtin));
+            }
+            for binding in options.channels.iter().filter(|b| b.pass == id) {
+                inputs[binding.channel] = Some(binding.input.clone());
+            }
+            inputs.map(|input| {
+                Channel::load(device, queue, input?, seed)
+                    .map_err(|err| eprintln!("{}: {:#}", id.name(), err))
+                    .ok()
+            })
+        };
+
+        let mut passes = Vec::new();
+        for (i, path) in options.buffers.iter().enumerate() {
+            let Some(path) = path else { continue };
+            let id = PassId::Buffer(i);
+            let channels = load_channels(id);
+            let interface = pass::interface(id, &channels, &params.params);
+            let buffer_format = options
+                .format(id)
+                .float()
+                .map_or(BUFFER_FORMAT, |float| supported_float_format(device, id, float));
+            let size = options.buffer_sizes[i];
+            let target_size = target_size(size, render_size, max_size);
+            let loaded = load_pass(
+                device,
+                path,
+                &options.sources,
+                &interface,
+                &pipeline_layout,
+                buffer_format,
+                None,
+            );
+            // a buffer has no sensible fallback, its readers get black until
+            // the shader builds, `source` is empty so a reload retries it
+            let (source, pipeline) = match loaded {
+                Ok((_, source, pipeline)) => (source, Some(pipeline)),
+                Err(err) => {
+                    eprintln!("{}\nSkipping {}.", err, id.name());
+                    overlay.set(&id.name(), format!("{}\nSkipping {}.", err, id.name()));
+                    (ShaderSource::default(), None)
                 }
-                Err(e) => eprintln!("watch error: {:?}", e),
+            };
+            passes.push(Pass {
+                id,
+                shader_path: Some(path.clone()),
+                lang: ShaderLang::from_path(path),
+                source,
+                pipeline,
+                fallback: false,
+                format: buffer_format,
+                channels,
+                targets: Some(create_buffer_targets(device, target_size, i, buffer_format)),
+                size,
+                uniforms: size.map(|_| Uniform::new(device)),
+                bind_groups: Vec::new(),
+                channel_uniforms: None,
+            });
+        }
+
+        let channels = load_channels(PassId::Image);
+        let interface = pass::interface(PassId::Image, &channels, &params.params);
+        let image_hdr = options
+            .format(PassId::Image)
+            .float()
+            .map(|float| supported_float_format(device, PassId::Image, float));
+        let image_format = image_hdr.unwrap_or(*format);
+        let image_target = (image_hdr.is_some() || sampling.resolves()).then(|| {
+            let target =
+                Texture::empty(device, render_size, image_format, None, Some("image")).unwrap();
+            let tonemap = Tonemap::new(
+                device,
+                *format,
+                options.tonemap,
+                options.exposure,
+                sampling.upscale,
+            );
+            (target, tonemap)
+        });
+        let resolve = sampling.resolves().then(|| {
+            let average =
+                Texture::empty(device, size, RESOLVE_FORMAT, None, Some("resolve")).unwrap();
+            (average, Tonemap::accumulator(device, RESOLVE_FORMAT, sampling.upscale))
+        });
+        let samples = create_samples(device, &sampling, render_size);
+        let image_blend = image_blend(image_format);
+        let user_pipeline = options.shader.as_ref().and_then(|path| {
+            load_pass(
+                device,
+                path,
+                &options.sources,
+                &interface,
+                &pipeline_layout,
+                image_format,
+                image_blend,
+            )
+            .map_err(|err| {
+                let err = format!("{}\nFalling back to the built-in shader.", err);
+                eprintln!("{}", err);
+                overlay.set(&PassId::Image.name(), err);
+            })
+            .ok()
+        });
+        // a failed shader keeps its language, reloads parse it again
+        let image_lang = options
+            .shader
+            .as_deref()
+            .map_or(ShaderLang::Wgsl, ShaderLang::from_path);
+        let fallback = user_pipeline.is_none();
+        let (source, pipeline) = user_pipeline.map_or_else(
+            || {
+                let pipeline = try_rebuild_pipeline(
+                    device,
+                    ShaderLang::Wgsl,
+                    BUILTIN_SHADER,
+                    &interface,
+                    &pipeline_layout,
+                    image_format,
+                    image_blend,
+                )
+                .expect("built-in shader is valid");
+                (ShaderSource::from_code("builtin", BUILTIN_SHADER), pipeline)
             },
-            notify::Config::default(),
-        )
-        .unwrap();
+            |(_, source, pipeline)| (source, pipeline),
+        );
+        passes.push(Pass {
+            id: PassId::Image,
+            shader_path: options.shader.clone(),
+            lang: image_lang,
+            source,
+            pipeline: Some(pipeline),
+            fallback,
+            format: image_format,
+            channels,
+            targets: None,
+            size: None,
+            uniforms: None,
+            bind_groups: Vec::new(),
+            channel_uniforms: None,
+        });
 
-        watcher
-            .watch(Path::new("./src/shaders/"), RecursiveMode::Recursive)
-            .expect("Watcher throws!");
+        for binding in &options.channels {
+            if !passes.iter().any(|p| p.id == binding.pass) {
+                eprintln!(
+                    "{} has no shader, ignoring its channel {}",
+                    binding.pass.name(),
+                    binding.channel
+                );
+            }
+        }
+        let reads: Vec<_> = passes
+            .iter()
+            .map(|p| (p.id, p.buffer_inputs().collect()))
+            .collect();
+        let order = render_order(&reads);
+        let mut slots: Vec<_> = passes.into_iter().map(Some).collect();
+        let passes: Vec<Pass> = order.into_iter().map(|i| slots[i].take().unwrap()).collect();
 
-        Self {
+        // the compute span comes first, see `render_to`
+        let spans = compute.iter().map(|_| PassId::Compute).chain(passes.iter().map(|p| p.id));
+        let names: Vec<_> = spans.map(|id| id.name()).collect();
+        let timer = GpuTimer::new(device, queue, names.len() as u32);
+        if timer.is_none() && dynamic.is_some() {
+            eprintln!("no GPU timestamps on this device, scaling to the frame interval instead");
+        }
+        let source = match timer {
+            Some(_) => Source::Timestamps,
+            None => Source::CpuWait,
+        };
+        let profiler = Profiler::new(names, source);
+
+        let (tx, rx) = std::sync::mpsc::channel::<Reload>();
+
+        let read_lock = std::sync::Arc::new(std::sync::Mutex::new(None));
+
+        let manifest = options.manifest.as_ref().map(|path| {
+            let text = std::fs::read_to_string(path).unwrap_or_default();
+            (path.clone(), text)
+        });
+        let watch_dirs = options.watch_dirs();
+        let watcher =
+            (!watch_dirs.is_empty()).then(|| create_watcher(&watch_dirs, read_lock.clone()));
+        let watched = watch_dirs.iter().filter_map(|dir| dir.canonicalize().ok()).collect();
+
+        let mut stoy = Self {
             test_sprite,
-            pipeline,
+            black,
+            black_cube,
+            passes,
+            compute,
             uniforms,
+            image_uniforms,
+            params,
+            given_params: options.uniforms.clone(),
+            panel: Panel::new(true),
             camera,
             channel: (tx, rx),
+            channel_layout,
             pipeline_layout,
+            format: *format,
+            size,
+            sampling,
+            max_size,
+            dynamic,
+            timer,
+            profiler,
+            hud: options.hud,
+            image_target,
+            resolve,
+            samples,
+            frame: 0,
+            playback: Playback::new(
+                options.start_time,
+                options.deterministic.as_ref().map(|d| d.fps),
+            ),
+            deterministic: options.deterministic.clone(),
+            advanced: false,
+            screenshot_requested: false,
+            screenshot_dir: options.screenshot_dir.clone(),
+            screenshot_size: options.screenshot_size,
+            recorder: None,
             input: InputManager::default(),
+            overlay,
+            manifest,
+            args: options.args.clone(),
             read_lock,
-            _watcher: watcher,
-        }
+            sources: options.sources.clone(),
+            watcher,
+            watched,
+        };
+        stoy.rebuild_bind_groups(device);
+        // also those of shaders that failed, which hold no pass
+        let files: Vec<PathBuf> = sources
+            .iter()
+            .flat_map(|source| source.files().map(Path::to_path_buf))
+            .collect();
+        stoy.watch(&files);
+        stoy
     }
 
     pub fn input(&mut self, event: InputEvent) {
-    
+        match &event {
+            InputEvent::Window(WindowEvent::KeyboardInput { event: key, .. })
+                if key.state.is_pressed() =>
+            {
+                if key.logical_key == Key::Named(NamedKey::F12) {
+                    self.screenshot_requested = true;
+                } else if key.logical_key == Key::Named(NamedKey::F3) {
+                    self.hud = !self.hud;
+                    self.profiler.clear();
+                } else if key.logical_key == Key::Named(NamedKey::F2) {
+                    self.panel.shown = !self.panel.shown;
+                } else if let Some(command) = Command::from_key(&key.logical_key) {
+                    self.playback.apply(command);
+                }
+            }
+            InputEvent::Window(WindowEvent::CursorMoved { position, .. }) => {
+                self.panel.move_cursor(position.x, position.y);
+            }
+            // clicks on the panel are not clicks on the shader
+            InputEvent::Window(WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            }) => {
+                let taken = match state {
+                    ElementState::Pressed => self.panel.press(),
+                    ElementState::Released => self.panel.release(),
+                };
+                if taken {
+                    return;
+                }
+            }
+            _ => (),
+        }
         self.input.process_events(event);
+    }
+
+    /// Time controls, the same ones the keyboard drives.
+    #[allow(dead_code)]
+    pub fn playback(&mut self) -> &mut Playback {
+        &mut self.playback
+    }
+
+    /// Playback state for the window title.
+    pub fn status(&self) -> String {
+        self.playback.status()
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        self.size = size;
+        let render_size = self.render_size();
+        for pass in &mut self.passes {
+            let PassId::Buffer(i) = pass.id else { continue };
+            let size = target_size(pass.size, render_size, self.max_size);
+            // fixed sizes keep their contents
+            let current = pass.targets.as_ref().map(|t| t[0].texture.size());
+            if current.is_none_or(|c| (c.width, c.height) != size) {
+                pass.targets = Some(create_buffer_targets(device, size, i, pass.format));
+            }
+        }
+        if let Some(compute) = &mut self.compute {
+            compute.resize(device, render_size, &self.params);
+        }
+        if let Some((average, _)) = &mut self.resolve {
+            *average = Texture::empty(device, size, RESOLVE_FORMAT, None, Some("resolve")).unwrap();
+        }
+        self.resize_image(device);
+        self.rebuild_bind_groups(device);
+    }
+
+    /// Recreates the target of the image pass at `image_size`.
+    fn resize_image(&mut self, device: &wgpu::Device) {
+        let image_size = self.image_size();
+        if let Some((target, _)) = &mut self.image_target {
+            let format = target.texture.format();
+            *target = Texture::empty(device, image_size, format, None, Some("image")).unwrap();
+        }
+        self.samples = create_samples(device, &self.sampling, image_size);
+    }
+
+    /// Size the passes render at.
+    fn render_size(&self) -> (u32, u32) {
+        self.sampling.size(self.size, self.max_size)
+    }
+
+    /// Size the image pass renders at, the render size unless scaled down
+    /// dynamically.
+    fn image_size(&self) -> (u32, u32) {
+        let fraction = self.dynamic.as_ref().map_or(1.0, DynamicScale::fraction);
+        sampling::scaled(self.render_size(), fraction)
+    }
+
+    /// Recreates the channel bind groups of every pass, for both frame
+    /// parities. Needed whenever a buffer target is recreated.
+    fn rebuild_bind_groups(&mut self, device: &wgpu::Device) {
+        let mut all = Vec::with_capacity(self.passes.len());
+        for (pos, pass) in self.passes.iter().enumerate() {
+            let resolution = ChannelUniforms {
+                resolution: std::array::from_fn(|c| {
+                    let Some(channel) = &pass.channels[c] else {
+                        return [0.0; 4];
+                    };
+                    let size = self.channel_texture(channel, pos, 0).texture.size();
+                    [size.width as f32, size.height as f32, 1.0, 0.0]
+                }),
+            };
+            let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Channel Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[resolution]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let groups: Vec<_> = (0..2)
+                .map(|parity| {
+                    let channels = std::array::from_fn(|c| match &pass.channels[c] {
+                        Some(channel) => {
+                            let texture = self.channel_texture(channel, pos, parity);
+                            let (view_2d, view_cube) = if channel.input.source.is_cube() {
+                                (&self.black.view, &texture.view)
+                            } else {
+                                (&texture.view, &self.black_cube.view)
+                            };
+                            ChannelViews {
+                                view_2d,
+                                view_cube,
+                                sampler: &channel.sampler,
+                            }
+                        }
+                        None => ChannelViews {
+                            view_2d: &self.black.view,
+                            view_cube: &self.black_cube.view,
+                            sampler: &self.black.sampler,
+                        },
+                    });
+                    channel::create_bind_group(
+                        device,
+                        &self.channel_layout,
+                        &channels,
+                        &uniforms,
+                        &format!("{}_channels_{}", pass.id.name(), parity),
+                    )
+                })
+                .collect();
+            all.push((groups, uniforms));
+        }
+        for (pass, (groups, uniforms)) in self.passes.iter_mut().zip(all) {
+            pass.bind_groups = groups;
+            pass.channel_uniforms = Some(uniforms);
+        }
+    }
+
+    fn channel_texture<'a>(&'a self, channel: &'a Channel, reader: usize, parity: usize) -> &'a Texture {
+        match (&channel.input.source, &channel.texture) {
+            (_, Some(texture)) => texture,
+            (ChannelSource::Buffer(b), None) => {
+                self.buffer_texture(*b, reader, parity).unwrap_or(&self.black)
+            }
+            (ChannelSource::Storage(n), None) => self
+                .compute
+                .as_ref()
+                .and_then(|compute| compute.texture(*n, parity))
+                .unwrap_or(&self.black),
+            (ChannelSource::Builtin, None) => &self.test_sprite.texture,
+            _ => &self.black,
+        }
+    }
 
+    /// Texture of `buffer` as seen by the pass at `reader` on a frame of the
+    /// given parity: this frame's output if the buffer already ran,
+    /// otherwise the previous frame.
+    fn buffer_texture(&self, buffer: usize, reader: usize, parity: usize) -> Option<&Texture> {
+        let pos = self
+            .passes
+            .iter()
+            .position(|p| p.id == PassId::Buffer(buffer))?;
+        let targets = self.passes[pos].targets.as_ref()?;
+        if pos < reader {
+            Some(&targets[parity])
+        } else {
+            Some(&targets[1 - parity])
+        }
     }
 
-    pub fn update(&mut self, queue: &mut wgpu::Queue, size: (u32, u32)) {
-        self.uniforms.data.time += 0.01;
-        self.uniforms.data.resulotion = [size.0 as f32, size.1 as f32];
-        self.uniforms.data.mouse_position = [self.input.x as f32, self.input.y as f32];
-        self.uniforms.data.zoom = [self.input.wx.abs(), self.input.wy.abs()];
+    pub fn update(&mut self, queue: &mut wgpu::Queue, size: (u32, u32), dt: Duration) {
+        let dt = dt.as_secs_f32();
+        self.profiler.begin_frame();
+        if let (Some(dynamic), None) = (&mut self.dynamic, &self.timer) {
+            dynamic.measure(dt);
+        }
+        let tick = self.playback.advance(dt);
+        self.advanced = tick.is_some();
+        let data = &mut self.uniforms.data;
+        data.time = self.playback.time;
+        if let Some(time_delta) = tick {
+            data.time_delta = time_delta;
+        }
+        data.frame = self.playback.frame.max(0);
+        let render_size = self.sampling.size(size, self.max_size);
+        data.resolution = [render_size.0 as f32, render_size.1 as f32];
+        match &self.deterministic {
+            Some(deterministic) => {
+                let frame = self.playback.frame.max(0);
+                let (mouse, [x, y]) = match &deterministic.mouse {
+                    Some(script) => (script.mouse(frame), script.position(frame)),
+                    None => ([0.0; 4], [0.0; 2]),
+                };
+                data.mouse = mouse;
+                data.mouse_position = [x, size.1 as f32 - y];
+                data.zoom = [0.0, 0.0];
+                data.date = date_uniform(&deterministic.date);
+                // the rate the frames play back at, not the one they render at
+                data.frame_rate = deterministic.fps as f32;
+            }
+            None => {
+                if dt > 0.0 {
+                    data.frame_rate = 1.0 / dt;
+                }
+                data.mouse_position = [self.input.x as f32, self.input.y as f32];
+                data.zoom = [self.input.wx.abs(), self.input.wy.abs()];
+                data.mouse = shadertoy_mouse(&mut self.input, size.1 as f32);
+                data.date = date_uniform(&chrono::Local::now());
+            }
+        }
+        scale_mouse(
+            data,
+            [
+                render_size.0 as f32 / size.0 as f32,
+                render_size.1 as f32 / size.1 as f32,
+            ],
+        );
 
         self.camera.uniform.write(queue);
         self.uniforms.write(queue);
+
+        // the size `render_to` resizes the image target to
+        let image_size = sampling::scaled(
+            render_size,
+            self.dynamic.as_ref().map_or(1.0, DynamicScale::fraction),
+        );
+        let image = &mut self.image_uniforms.data;
+        *image = self.uniforms.data;
+        image.resolution = [image_size.0 as f32, image_size.1 as f32];
+        scale_mouse(
+            image,
+            [
+                image_size.0 as f32 / render_size.0 as f32,
+                image_size.1 as f32 / render_size.1 as f32,
+            ],
+        );
+        self.image_uniforms.write(queue);
+        for pass in &mut self.passes {
+            let (Some(uniforms), Some(targets)) = (&mut pass.uniforms, &pass.targets) else {
+                continue;
+            };
+            let size = targets[0].texture.size();
+            uniforms.data = self.uniforms.data;
+            uniforms.data.resolution = [size.width as f32, size.height as f32];
+            scale_mouse(
+                &mut uniforms.data,
+                [
+                    size.width as f32 / render_size.0 as f32,
+                    size.height as f32 / render_size.1 as f32,
+                ],
+            );
+            uniforms.write(queue);
+        }
+        for (i, (uniforms, _)) in self.samples.iter_mut().enumerate() {
+            let i = i as u32;
+            uniforms.data = self.image_uniforms.data;
+            uniforms.data.tile_offset = self.sampling.jitter(i);
+            uniforms.data.time += self.sampling.time(i) * self.uniforms.data.time_delta;
+            uniforms.write(queue);
+        }
     }
+    /// Renders a frame into the surface and presents it.
     pub fn render(
         &mut self,
         surface: &wgpu::Surface,
         device: &wgpu::Device,
         queue: &mut wgpu::Queue,
-        format: &wgpu::TextureFormat,
+    ) {
+        let frame = surface
+            .get_current_texture()
+            .expect("Failed to acquire next swap chain texture");
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let panel = self
+            .panel
+            .frame(&mut self.params.params, &self.params.annotations, self.size);
+        if panel.as_ref().is_some_and(|panel| panel.changed) {
+            self.params.write(queue);
+        }
+        self.render_to(device, queue, &view);
+        if let Some(panel) = panel {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            self.overlay.draw_image(
+                device,
+                queue,
+                &mut encoder,
+                &view,
+                self.size,
+                &panel.pixels,
+                panel.size,
+                panel.origin,
+            );
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        frame.present();
+    }
+
+    /// Renders a frame into `view`, which must have the format `Stoy` was
+    /// created with and the size last passed to `update`.
+    pub fn render_to(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &mut wgpu::Queue,
+        context_view: &wgpu::TextureView,
     ) {
         let mut g = self.read_lock.lock().unwrap();
+        let mut manifest_changed = false;
+        // the sources the params are declared in, `None` without a reload
+        let mut declared: Option<Vec<(PassId, ShaderSource)>> = None;
+        // a reload may include files in directories not watched yet
+        let mut files = Vec::new();
         if let Some(last) = *g {
             if last.elapsed() > Duration::from_millis(200) {
-                println!("Last: {}", last.elapsed().as_secs_f32());
-                    if let Ok(src) = std::fs::read_to_string("./src/shaders/sprite.wgsl") {
-                    let _ = self.channel.0.send(src);
+                if let Some((path, text)) = &mut self.manifest {
+                    match std::fs::read_to_string(&*path) {
+                        Ok(new) if new != *text => {
+                            *text = new;
+                            manifest_changed = true;
+                        }
+                        Ok(_) => (),
+                        Err(err) => {
+                            let err = format!("failed to read `{}`: {}", path.display(), err);
+                            eprintln!("Hot-reload: {}", err);
+                            self.overlay.set(MANIFEST, err);
+                        }
+                    }
                 }
-                *g = None;
-            }
-        }
-
-        if let Ok(new_src) = self.channel.1.try_recv() {
-
-            println!("Waiting... {} <<<", new_src);
-            match naga::front::wgsl::parse_str(&new_src) {
-                Ok(module) => {
-                    let mut validator = naga::valid::Validator::new(
-                        naga::valid::ValidationFlags::all(),
-                        naga::valid::Capabilities::default(),
-                    );
-                    if let Err(validation_err) = validator.validate(&module) {
-                        eprintln!("Hot-reload: WGSL validation error: {:?}", validation_err);
-                        return;
+                let sources = declared.insert(Vec::new());
+                for (i, pass) in self.passes.iter().enumerate() {
+                    let Some(path) = &pass.shader_path else { continue };
+                    match ShaderSource::load(path, &self.sources) {
+                        Ok(src) if src.code != pass.source.code => {
+                            files.extend(src.files().map(Path::to_path_buf));
+                            sources.push((pass.id, src.clone()));
+                            let _ = self.channel.0.send((i, src));
+                        }
+                        Ok(src) => sources.push((pass.id, src)),
+                        Err(err) => {
+                            sources.push((pass.id, pass.source.clone()));
+                            eprintln!("Hot-reload: {}", err);
+                            self.overlay.set(&pass.id.name(), err);
+                        }
                     }
-                    match try_rebuild_pipeline(&device, &new_src, &self.pipeline_layout, *format) {
-                        Ok(new_pipeline) => {
-                            self.pipeline = new_pipeline;
-                            eprintln!("Shader reloaded successfully!");
+                }
+                if let Some(compute) = &mut self.compute {
+                    match ShaderSource::load(&compute.shader_path, &self.sources) {
+                        Ok(src) if src.code != compute.source.code => {
+                            files.extend(src.files().map(Path::to_path_buf));
+                            sources.push((PassId::Compute, src.clone()));
+                            match compute.build(device, src) {
+                                Ok(()) => {
+                                    self.overlay.clear(&PassId::Compute.name());
+                                    eprintln!("Shader reloaded successfully!");
+                                }
+                                Err(err) => {
+                                    eprintln!("Shader reload failed:\n{}", err);
+                                    self.overlay.set(&PassId::Compute.name(), err);
+                                }
+                            }
                         }
+                        Ok(src) => sources.push((PassId::Compute, src)),
                         Err(err) => {
-                            eprintln!("Shader reload failed:\n{}", err);
+                            sources.push((PassId::Compute, compute.source.clone()));
+                            eprintln!("Hot-reload: {}", err);
+                            self.overlay.set(&PassId::Compute.name(), err);
                         }
                     }
                 }
-                Err(parse_err) => {
-                    eprintln!("Hot-reload: failed to parse WGSL: {:?}", parse_err);
-                    return;
+                *g = None;
+            }
+        }
+        drop(g);
+        self.watch(&files);
+        let mut params_changed = false;
+        if let Some(mut sources) = declared {
+            // in the order of `Options::shaders`, which `new` declares them in
+            sources.sort_by_key(|(id, _)| match id {
+                PassId::Image => 0,
+                PassId::Buffer(i) => 1 + i,
+                PassId::Compute => 1 + BUFFER_COUNT,
+            });
+            let sources: Vec<ShaderSource> = sources.into_iter().map(|(_, src)| src).collect();
+            let (declared, annotations, err) = declare_params(&self.given_params, &sources);
+            match err {
+                Some(err) => {
+                    eprintln!("Hot-reload: {}", err);
+                    self.overlay.set(PARAMS, err);
                 }
+                None => self.overlay.clear(PARAMS),
+            }
+            if params::same_layout(&declared, &self.params.params) {
+                self.params.redeclare(queue, declared, annotations);
+            } else {
+                params_changed = true;
+            }
+        }
+        if manifest_changed || params_changed {
+            let what = if manifest_changed { "Manifest" } else { "Params" };
+            if self.rebuild(device, queue) {
+                eprintln!("{} reloaded successfully!", what);
+            }
+        }
+
+        while let Ok((i, new_src)) = self.channel.1.try_recv() {
+            let pass = &mut self.passes[i];
+            let name = pass
+                .shader_path
+                .as_ref()
+                .map_or_else(|| "builtin".to_string(), |p| p.display().to_string());
+            let blend = if pass.id == PassId::Image {
+                image_blend(pass.format)
+            } else {
+                None
+            };
+            let interface = pass.glsl_interface(&self.params.params);
+            let rebuilt = parse_shader(pass.lang, &new_src, &name, &interface).and_then(|_| {
+                try_rebuild_pipeline(
+                    device,
+                    pass.lang,
+                    &new_src.code,
+                    &interface,
+                    &self.pipeline_layout,
+                    pass.format,
+                    blend,
+                )
+            });
+            match rebuilt {
+                Ok(new_pipeline) => {
+                    pass.pipeline = Some(new_pipeline);
+                    pass.fallback = false;
+                    pass.source = new_src;
+                    self.overlay.clear(&pass.id.name());
+                    eprintln!("Shader reloaded successfully!");
+                }
+                Err(err) => {
+                    let err = if pass.fallback {
+                        format!("{}\nFalling back to the built-in shader.", err)
+                    } else {
+                        err
+                    };
+                    eprintln!("Shader reload failed:\n{}", err);
+                    self.overlay.set(&pass.id.name(), err);
+                }
+            }
+        }
+        if let Some((target, _)) = &self.image_target {
+            let size = target.texture.size();
+            if (size.width, size.height) != self.image_size() {
+                self.resize_image(device);
             }
         }
-        let frame = surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
 
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        let context_view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let profiling = self.hud || self.profiler.writes();
+        let cpu_wait = profiling && self.timer.is_none();
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &context_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            rpass.set_pipeline(&self.pipeline);
-            rpass.set_bind_group(0, &self.camera.uniform.bind_group, &[]);
-            self.test_sprite.bind(&mut rpass);
-            rpass.set_bind_group(2, &self.uniforms.bind_group, &[]);
+        if self.playback.take_clear() {
+            for pass in &self.passes {
+                for target in pass.targets.iter().flatten() {
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("clear_buffer"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &target.view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                }
+            }
+            if let Some(compute) = &mut self.compute {
+                compute.reset(device, &mut encoder, &self.params);
+                self.rebuild_bind_groups(device);
+            }
+        }
 
-            rpass.draw(0..6, 0..1);
+        // while paused the image reads what the buffers wrote last frame
+        let parity = if self.advanced {
+            (self.frame % 2) as usize
+        } else {
+            ((self.frame + 1) % 2) as usize
+        };
+        // spans of the timer and the profiler: the compute shader, if any,
+        // then the passes
+        let offset = self.compute.is_some() as usize;
+        let mut waited = vec![0.0; offset + self.passes.len()];
+        if cpu_wait {
+            // each span alone on the GPU from here
+            submit_and_wait(device, queue, &mut encoder);
+        }
+        if let Some(compute) = &mut self.compute {
+            if let Some(timer) = &self.timer {
+                timer.begin(&mut encoder, 0);
+            }
+            if self.advanced {
+                compute.dispatch(&mut encoder, parity, &self.uniforms.bind_group);
+            }
+            if let Some(timer) = &self.timer {
+                timer.end(&mut encoder, 0);
+            }
+            if cpu_wait {
+                waited[0] = submit_and_wait(device, queue, &mut encoder);
+            }
+        }
+        let vertices = &self.test_sprite.buffer;
+        for (i, pass) in self.passes.iter().enumerate() {
+            let span = (offset + i) as u32;
+            if let Some(timer) = &self.timer {
+                timer.begin(&mut encoder, span);
+            }
+            if !self.advanced && pass.id != PassId::Image {
+                if let Some(timer) = &self.timer {
+                    timer.end(&mut encoder, span);
+                }
+                continue;
+            }
+            match (&pass.targets, &self.image_target, &self.resolve) {
+                (Some(targets), ..) => {
+                    let view = &targets[parity].view;
+                    let uniforms = pass.uniforms.as_ref().unwrap_or(&self.uniforms);
+                    self.draw_pass(&mut encoder, pass, view, parity, uniforms, vertices);
+                }
+                (None, Some((target, _)), Some((average, accumulator))) => {
+                    let samples: Vec<_> = if self.samples.is_empty() {
+                        vec![(&self.image_uniforms, vertices)]
+                    } else {
+                        self.samples.iter().map(|(u, v)| (u, v)).collect()
+                    };
+                    let weight = 1.0 / samples.len() as f32;
+                    for (i, (uniforms, vertices)) in samples.into_iter().enumerate() {
+                        self.draw_pass(&mut encoder, pass, &target.view, parity, uniforms, vertices);
+                        let view = &average.view;
+                        accumulator.accumulate(
+                            device,
+                            &mut encoder,
+                            target,
+                            view,
+                            self.size,
+                            weight,
+                            i == 0,
+                        );
+                    }
+                }
+                (None, Some((target, _)), None) => {
+                    let (view, uniforms) = (&target.view, &self.image_uniforms);
+                    self.draw_pass(&mut encoder, pass, view, parity, uniforms, vertices);
+                }
+                (None, None, _) => {
+                    let (view, uniforms) = (context_view, &self.image_uniforms);
+                    self.draw_pass(&mut encoder, pass, view, parity, uniforms, vertices);
+                }
+            }
+            if let Some(timer) = &self.timer {
+                timer.end(&mut encoder, span);
+            }
+            if cpu_wait {
+                waited[span as usize] = submit_and_wait(device, queue, &mut encoder);
+            }
+        }
+        if let Some(timer) = &mut self.timer {
+            timer.resolve(&mut encoder);
+        }
+        if let Some((target, tonemap)) = &self.image_target {
+            let source = self.resolve.as_ref().map_or(target, |(average, _)| average);
+            tonemap.draw(device, &mut encoder, source, context_view, self.size);
+        }
+        self.overlay.draw(device, queue, &mut encoder, context_view, self.size);
+        if self.hud {
+            let (pixels, size) = hud::rasterize(&self.profiler);
+            let origin = (self.size.0.saturating_sub(size.0), 0);
+            self.overlay
+                .draw_image(device, queue, &mut encoder, context_view, self.size, &pixels, size, origin);
         }
 
         queue.submit(std::iter::once(encoder.finish()));
+        let cpu = self.profiler.cpu_time() - waited.iter().sum::<f32>();
+        let gpu = match &mut self.timer {
+            Some(timer) => {
+                timer.submitted(device, self.profiler.writes());
+                timer.read()
+            }
+            None => cpu_wait.then_some(waited),
+        };
+        if let (Some(dynamic), Some(_), Some(gpu)) = (&mut self.dynamic, &self.timer, &gpu) {
+            let image = self.passes.iter().position(|p| p.id == PassId::Image);
+            dynamic.measure(gpu[offset + image.expect("image pass is always present")]);
+        }
+        if profiling {
+            let times = FrameTimes {
+                frame: self.uniforms.data.frame,
+                time: self.uniforms.data.time,
+                interval: self.profiler.interval(),
+                cpu,
+                passes: gpu,
+            };
+            if let Err(err) = self.profiler.push(times) {
+                eprintln!("Profiling stopped: {:#}", err);
+                let _ = self.profiler.finish();
+            }
+        }
+        if self.advanced {
+            self.frame += 1;
+        }
 
-        frame.present();
+        if let Some(mut recorder) = self.recorder.take().filter(|_| self.advanced) {
+            match self
+                .screenshot(device, queue, recorder.size())
+                .and_then(|image| recorder.write(&image))
+            {
+                Ok(()) => self.recorder = Some(recorder),
+                Err(err) => eprintln!("Recording stopped: {:#}", err),
+            }
+        }
+
+        if std::mem::take(&mut self.screenshot_requested) {
+            let size = self.screenshot_size.unwrap_or(self.size);
+            match self.save_screenshot(device, queue, size) {
+                Ok(path) => println!("Saved screenshot to {}", path.display()),
+                Err(err) => eprintln!("Screenshot failed: {:#}", err),
+            }
+        }
+    }
+
+    /// Watches the directories of `files` the watcher does not cover yet,
+    /// so editing an included file reloads the shaders using it.
+    fn watch(&mut self, files: &[PathBuf]) {
+        let Some(watcher) = &mut self.watcher else { return };
+        for file in files {
+            let dir = match file.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let Ok(dir) = dir.canonicalize() else { continue };
+            if self.watched.iter().any(|watched| dir.starts_with(watched)) {
+                continue;
+            }
+            match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => self.watched.push(dir),
+                Err(err) => eprintln!("failed to watch `{}`: {}", dir.display(), err),
+            }
+        }
+    }
+
+    /// Rebuilds everything from the command line after the manifest or the
+    /// layout of the params changed, keeping the clock, the input, the HUD,
+    /// the panel, the values of params declared the same, the recording and
+    /// the profile if the passes are the same. On error the current passes
+    /// keep running.
+    fn rebuild(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let options = match Options::parse(self.args.clone()) {
+            Ok(Some(options)) => options,
+            Ok(None) => return false,
+            Err(err) => {
+                eprintln!("Manifest reload failed: {}", err);
+                self.overlay.set(MANIFEST, err.to_string());
+                return false;
+            }
+        };
+        let mut stoy = Stoy::new(device, queue, &self.format, self.size, &options);
+        std::mem::swap(&mut stoy.playback, &mut self.playback);
+        std::mem::swap(&mut stoy.input, &mut self.input);
+        stoy.hud = self.hud;
+        std::mem::swap(&mut stoy.panel, &mut self.panel);
+        stoy.params.keep_values(queue, &self.params);
+        stoy.recorder = self.recorder.take();
+        if stoy.profiler.names() == self.profiler.names() {
+            std::mem::swap(&mut stoy.profiler, &mut self.profiler);
+        } else if let Err(err) = self.profiler.finish() {
+            eprintln!("Profiling stopped: {:#}", err);
+        }
+        *self = stoy;
+        true
+    }
+
+    /// Writes the timings of every frame to a CSV file at `path`, see
+    /// `finish_profile`.
+    pub fn profile(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.profiler.write_csv(path)
+    }
+
+    /// Flushes the profile and prints a summary, if there is one.
+    pub fn finish_profile(&mut self) -> anyhow::Result<()> {
+        self.profiler.finish()
+    }
+
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Waits for the recording to be written, if there is one.
+    pub fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(err) = recorder.finish() {
+                eprintln!("Recording failed: {:#}", err);
+            }
+        }
+    }
+
+    /// Errors of shaders that failed to load or reload, as shown in the
+    /// overlay.
+    pub fn errors(&self) -> Vec<&str> {
+        self.overlay.messages().collect()
+    }
+
+    fn draw_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pass: &Pass,
+        view: &wgpu::TextureView,
+        parity: usize,
+        uniforms: &Uniform<MainUniforms>,
+        vertices: &wgpu::Buffer,
+    ) {
+        // `IMAGE_BLEND` keeps the cleared alpha, so the image comes out opaque
+        let clear = match pass.id {
+            PassId::Image => wgpu::Color::BLACK,
+            PassId::Buffer(_) | PassId::Compute => wgpu::Color::TRANSPARENT,
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&pass.id.name()),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let Some(pipeline) = &pass.pipeline else {
+            return;
+        };
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, &self.camera.uniform.bind_group, &[]);
+        rpass.set_vertex_buffer(0, vertices.slice(..));
+        rpass.set_bind_group(1, &pass.bind_groups[parity], &[]);
+        rpass.set_bind_group(2, &uniforms.bind_group, &[]);
+        rpass.set_bind_group(params::PARAMS_GROUP, &self.params.bind_group, &[]);
+
+        rpass.draw(0..6, 0..1);
     }
+
+    /// Renders the image pass of the last frame at `size`, independent of
+    /// the window. Buffers keep the window size and are sampled as they are.
+    pub fn screenshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: (u32, u32),
+    ) -> anyhow::Result<image::RgbaImage> {
+        let mut uniforms = Uniform::<MainUniforms>::new(device);
+        uniforms.data = self.uniforms.data;
+        let (from, to) = (self.render_size(), self.sampling.size(size, self.max_size));
+        let data = &mut uniforms.data;
+        data.resolution = [to.0 as f32, to.1 as f32];
+        scale_mouse(
+            data,
+            [to.0 as f32 / from.0 as f32, to.1 as f32 / from.1 as f32],
+        );
+        uniforms.write(queue);
+
+        self.render_image(device, queue, &uniforms, &self.test_sprite.buffer, size)
+    }
+
+    /// Renders the tile of `size` at `offset`, in pixels from the top-left
+    /// corner, of `canvas`, the size last passed to `update`, which may be
+    /// larger than the `Stoy` and its targets. GLSL shaders
+    /// see the canvas coordinates in `fragCoord` and WGSL shaders in their
+    /// texture coordinates, or by adding `tile_offset` to the position.
+    /// Only the image pass is drawn, buffers are sampled as they are.
+    pub fn render_tile(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        canvas: (u32, u32),
+        offset: (u32, u32),
+        size: (u32, u32),
+    ) -> anyhow::Result<image::RgbaImage> {
+        let mut uniforms = Uniform::<MainUniforms>::new(device);
+        uniforms.data = self.uniforms.data;
+        let data = &mut uniforms.data;
+        // `update` holds the render size to a texture, the canvas is not one
+        let from = data.resolution;
+        data.resolution = [canvas.0 as f32, canvas.1 as f32];
+        scale_mouse(data, [canvas.0 as f32 / from[0], canvas.1 as f32 / from[1]]);
+        data.tile_offset = [offset.0 as f32, offset.1 as f32];
+        uniforms.write(queue);
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tile_vertices"),
+            contents: bytemuck::cast_slice(&Quad::tile(
+                canvas,
+                (offset.0 as f32, offset.1 as f32),
+                size,
+            )),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        self.render_image(device, queue, &uniforms, &vertices, size)
+    }
+
+    /// Draws the image pass into a new texture of `size`, supersampled like
+    /// the window, and reads it back.
+    fn render_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniforms: &Uniform<MainUniforms>,
+        vertices: &wgpu::Buffer,
+        size: (u32, u32),
+    ) -> anyhow::Result<image::RgbaImage> {
+        let image = self
+            .passes
+            .iter()
+            .find(|pass| pass.id == PassId::Image)
+            .expect("image pass is always present");
+        let max = device.limits().max_texture_dimension_2d;
+        if size.0 > max || size.1 > max {
+            anyhow::bail!("{}x{} exceeds the maximum texture size {}", size.0, size.1, max);
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("screenshot"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // the buffers were last written with the parity before `self.frame`
+        let parity = ((self.frame + 1) % 2) as usize;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("screenshot"),
+        });
+        match &self.image_target {
+            Some((_, tonemap)) => {
+                let render_size = self.sampling.size(size, max);
+                let source =
+                    Texture::empty(device, render_size, image.format, None, Some("screenshot_image"))?;
+                self.draw_pass(&mut encoder, image, &source.view, parity, uniforms, vertices);
+                tonemap.draw(device, &mut encoder, &source, &view, size);
+            }
+            None => self.draw_pass(&mut encoder, image, &view, parity, uniforms, vertices),
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        capture::read_texture(device, queue, &texture)
+    }
+
+    /// The floating point image of the last frame, before tonemapping.
+    pub fn read_hdr(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<image::Rgba32FImage> {
+        let Some((target, _)) = &self.image_target else {
+            anyhow::bail!("the image pass does not render in floating point");
+        };
+        let source = self.resolve.as_ref().map_or(target, |(average, _)| average);
+        capture::read_texture_hdr(device, queue, &source.texture)
+    }
+
+    /// Takes a screenshot and writes it to the screenshot directory.
+    pub fn save_screenshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: (u32, u32),
+    ) -> anyhow::Result<PathBuf> {
+        let image = self.screenshot(device, queue, size)?;
+        capture::save_timestamped(&image, &self.screenshot_dir)
+    }
+}
+
+/// `iMouse` from the cursor state, flipped to a bottom-left origin.
+fn shadertoy_mouse(input: &mut InputManager, height: f32) -> [f32; 4] {
+    let flip = |y: f64| height - y as f32;
+    let clicked = input.take_click();
+    let (cx, cy) = (input.click_x as f32, flip(input.click_y));
+    [
+        input.drag_x as f32,
+        flip(input.drag_y),
+        if input.pressed { cx } else { -cx },
+        if clicked { cy } else { -cy },
+    ]
+}
+
+/// Scales the mouse uniforms by `scale`, in x and y.
+fn scale_mouse(data: &mut MainUniforms, scale: [f32; 2]) {
+    for (i, v) in data.mouse_position.iter_mut().enumerate() {
+        *v *= scale[i];
+    }
+    for (i, v) in data.mouse.iter_mut().enumerate() {
+        *v *= scale[i % 2];
+    }
+}
+
+/// `iDate`: year, month from 0, day and seconds since midnight.
+fn date_uniform(date: &(impl chrono::Datelike + chrono::Timelike)) -> [f32; 4] {
+    let seconds = date.num_seconds_from_midnight() as f32 + date.nanosecond() as f32 * 1e-9;
+    [
+        date.year() as f32,
+        date.month0() as f32,
+        date.day() as f32,
+        seconds,
+    ]
 }
+
+/// Size of a buffer's targets at `render_size`.
+fn target_size(size: Option<PassSize>, render_size: (u32, u32), max: u32) -> (u32, u32) {
+    size.map_or(render_size, |size| size.apply(render_size, max))
+}
+
+fn create_buffer_targets(
+    device: &wgpu::Device,
+    size: (u32, u32),
+    index: usize,
+    format: wgpu::TextureFormat,
+) -> [Texture; 2] {
+    let label = PassId::Buffer(index).name();
+    std::array::from_fn(|_| Texture::empty(device, size, format, None, Some(&label)).unwrap())
+}
+
+/// Uniforms and vertices of the image pass for each of the samples, shifted
+/// by their jitter. Empty with a single sample, which uses the main ones.
+fn create_samples(
+    device: &wgpu::Device,
+    sampling: &Sampling,
+    size: (u32, u32),
+) -> Vec<(Uniform<MainUniforms>, wgpu::Buffer)> {
+    if sampling.samples == 1 {
+        return Vec::new();
+    }
+    (0..sampling.samples)
+        .map(|i| {
+            let [x, y] = sampling.jitter(i);
+            let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sample_vertices"),
+                contents: bytemuck::cast_slice(&Quad::tile(size, (x, y), size)),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            (Uniform::new(device), vertices)
+        })
+        .collect()
+}
+
+/// Submits `encoder` and waits for the GPU to finish it, replacing it with
+/// a new one. Returns the seconds waited.
+fn submit_and_wait(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+) -> f32 {
+    let start = std::time::Instant::now();
+    let done = std::mem::replace(encoder, device.create_command_encoder(&Default::default()));
+    queue.submit(std::iter::once(done.finish()));
+    device.poll(wgpu::Maintain::Wait);
+    start.elapsed().as_secs_f32()
+}
+
+/// Source language of a shader file, picked from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderLang {
+    Wgsl,
+    /// Shadertoy-style GLSL providing `mainImage`.
+    Glsl,
+}
+
+impl ShaderLang {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("glsl" | "frag" | "fs") => Self::Glsl,
+            _ => Self::Wgsl,
+        }
+    }
+}
+
+/// Reads, validates and builds the pipeline of a pass.
+fn load_pass(
+    device: &wgpu::Device,
+    path: &Path,
+    sources: &SourceOptions,
+    interface: &glsl::Interface,
+    pipeline_layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> Result<(ShaderLang, ShaderSource, wgpu::RenderPipeline), String> {
+    let lang = ShaderLang::from_path(path);
+    let src = ShaderSource::load(path, sources)?;
+    parse_shader(lang, &src, &path.display().to_string(), interface)?;
+    let pipeline =
+        try_rebuild_pipeline(device, lang, &src.code, interface, pipeline_layout, format, blend)?;
+    Ok((lang, src, pipeline))
+}
+
+/// Parses and validates a shader before it reaches wgpu, which would
+/// otherwise abort on the first error, and checks it against the pipeline
+/// layout.
+fn parse_shader(
+    lang: ShaderLang,
+    src: &ShaderSource,
+    path: &str,
+    interface: &glsl::Interface,
+) -> Result<naga::Module, String> {
+    let module = match lang {
+        ShaderLang::Wgsl => naga::front::wgsl::parse_str(&src.code)
+            .map_err(|e| src.emit(&Diagnostic::from_wgsl(&e, &src.code)))?,
+        ShaderLang::Glsl => glsl::parse(src, interface)?,
+    };
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|e| match lang {
+        ShaderLang::Wgsl => src.emit(&Diagnostic::from_validation(&e)),
+        ShaderLang::Glsl => glsl::emit_validation_error(&e, src, interface),
+    })?;
+    let entry_points: &[_] = match lang {
+        ShaderLang::Wgsl => &[
+            (naga::ShaderStage::Vertex, "vs_main"),
+            (naga::ShaderStage::Fragment, "fs_main"),
+        ],
+        ShaderLang::Glsl => &[(naga::ShaderStage::Fragment, glsl::ENTRY_POINT)],
+    };
+    layout::check_module(&module, &info, entry_points, &layout::pass_layout_entries())
+        .map_err(|e| format!("{}: {}", path, e))?;
+    Uniform::<MainUniforms>::check_layout(&module, 2, 0)?;
+    params::check_layout(&module, &interface.params)?;
+    Ok(module)
+}
+
+/// The params of `given` and those annotated in `sources`, with the errors
+/// of the annotations for the overlay.
+fn declare_params(
+    given: &[Param],
+    sources: &[ShaderSource],
+) -> (Vec<Param>, Vec<Annotation>, Option<String>) {
+    let mut annotations = Vec::new();
+    let mut errors = Vec::new();
+    for source in sources {
+        match params::annotations(source) {
+            Ok(found) => annotations.extend(found),
+            Err(err) => errors.push(err.trim_end().to_string()),
+        }
+    }
+    let (declared, annotations, conflicts) = params::declare(given, annotations);
+    errors.extend(conflicts);
+    let err = (!errors.is_empty()).then(|| errors.join("\n"));
+    (declared, annotations, err)
+}
+
+fn create_watcher(
+    dirs: &[PathBuf],
+    read_lock: std::sync::Arc<std::sync::Mutex<Option<std::time::Instant>>>,
+) -> notify::RecommendedWatcher {
+    let mut watcher = notify::RecommendedWatcher::new(
+        move |res: notify::Result<notify::Event>| match res {
+            Ok(evt) => {
+                if evt.kind.is_modify() || evt.kind.is_create() {
+                    let mut t = read_lock.lock().unwrap();
+                    *t = Some(instant::Instant::now());
+                }
+            }
+            Err(e) => eprintln!("watch error: {:?}", e),
+        },
+        notify::Config::default(),
+    )
+    .unwrap();
+
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .expect("Watcher throws!");
+    }
+    watcher
+}
+
+/// Blending of the image pass onto the surface.
+pub const IMAGE_BLEND: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+/// `format`, or `Rgba16Float` if the device cannot render into 32-bit float
+/// textures or, for buffers that are read through filtering samplers,
+/// filter them.
+fn supported_float_format(
+    device: &wgpu::Device,
+    pass: PassId,
+    format: wgpu::TextureFormat,
+) -> wgpu::TextureFormat {
+    if format != wgpu::TextureFormat::Rgba32Float {
+        return format;
+    }
+    let filterable =
+        pass == PassId::Image || device.features().contains(wgpu::Features::FLOAT32_FILTERABLE);
+    // downlevel backends like GL may not render into them at all
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let _ = Texture::empty(device, (1, 1), format, None, Some("format_probe"));
+    let renderable = pollster::block_on(device.pop_error_scope()).is_none();
+    if filterable && renderable {
+        return format;
+    }
+    eprintln!(
+        "{}: rgba32f targets are not supported on this device, using rgba16f",
+        pass.name()
+    );
+    wgpu::TextureFormat::Rgba16Float
+}
+
+/// Blending of the image pass into a target of `format`. 32-bit float
+/// targets cannot blend, their colors are written as they are instead of
+/// scaled by alpha.
+fn image_blend(format: wgpu::TextureFormat) -> Option<wgpu::BlendState> {
+    (format != wgpu::TextureFormat::Rgba32Float).then_some(IMAGE_BLEND)
+}
+
+/// Format of the buffer targets unless `--format` picks a floating point
+/// one.
+pub const BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+/// Builds the pipeline of a pass, returning wgpu validation errors instead
+/// of raising them.
 fn try_rebuild_pipeline(
     device: &wgpu::Device,
-    wgsl_source: &str,
+    lang: ShaderLang,
+    source: &str,
+    interface: &glsl::Interface,
     pipeline_layout: &wgpu::PipelineLayout,
     format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
 ) -> Result<wgpu::RenderPipeline, String> {
-    // Create shader module
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("hot_shader"),
-        source: wgpu::ShaderSource::Wgsl(wgsl_source.into()),
-    });
-    let pipeline = create_render_pipeline(device, &shader, format, pipeline_layout);
-    Ok(pipeline)
+    // anything `parse_shader` missed ends up here instead of in wgpu's
+    // uncaptured error handler, which panics
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let pipeline = match lang {
+        ShaderLang::Wgsl => {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("hot_shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            create_render_pipeline(
+                device,
+                &shader,
+                &shader,
+                "fs_main",
+                format,
+                blend,
+                pipeline_layout,
+            )
+        }
+        ShaderLang::Glsl => {
+            let vertex =
+                device.create_shader_module(wgpu::include_wgsl!("./shaders/fullscreen.wgsl"));
+            let fragment = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("hot_shader"),
+                source: wgpu::ShaderSource::Glsl {
+                    shader: glsl::wrap(source, interface).into(),
+                    stage: naga::ShaderStage::Fragment,
+                    defines: Default::default(),
+                },
+            });
+            create_render_pipeline(
+                device,
+                &vertex,
+                &fragment,
+                glsl::ENTRY_POINT,
+                format,
+                blend,
+                pipeline_layout,
+            )
+        }
+    };
+    match pollster::block_on(device.pop_error_scope()) {
+        None => Ok(pipeline),
+        Some(wgpu::Error::Validation { description, .. }) => {
+            Err(format!("failed to create the pipeline: {}", description))
+        }
+        Some(err) => Err(format!("failed to create the pipeline: {}", err)),
+    }
 }
 pub fn create_render_pipeline(
     device: &wgpu::Device,
-    shader: &wgpu::ShaderModule,
+    vertex: &wgpu::ShaderModule,
+    fragment: &wgpu::ShaderModule,
+    fs_entry: &str,
     format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
     pipeline_layout: &wgpu::PipelineLayout,
 ) -> wgpu::RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: None,
-        layout: Some(&pipeline_layout),
+        layout: Some(pipeline_layout),
         vertex: wgpu::VertexState {
-            module: &shader,
+            module: vertex,
             entry_point: Some("vs_main"),
             compilation_options: Default::default(),
             buffers: &[Quad::desc()],
         },
         fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
+            module: fragment,
+            entry_point: Some(fs_entry),
             compilation_options: Default::default(),
             targets: &[Some(wgpu::ColorTargetState {
                 format,
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::One,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::Zero,
-                        dst_factor: wgpu::BlendFactor::One,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                }),
+                blend,
                 write_mask: wgpu::ColorWrites::all(),
             })],
         }),
@@ -266,14 +1728,18 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+#[allow(dead_code)]
 const SAFE_FRAC_PI_2: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+#[allow(dead_code)]
 const SAFE_MIN_RADIUS: f32 = 1.0;
+#[allow(dead_code)]
 pub struct Camera2D {
     pub position: Vector3<f32>,
     pub scale: Vector2<f32>,
     pub uniform: Uniform<Camera2DUniform>,
 }
 
+#[allow(dead_code)]
 impl Camera2D {
     pub fn new(uniform: Uniform<Camera2DUniform>) -> Self {
         Self {
@@ -293,6 +1759,7 @@ impl Camera2D {
 pub struct Camera2DUniform {
     pub proj: [[f32; 4]; 4],
 }
+#[allow(dead_code)]
 impl Camera2DUniform {
     fn update(&mut self, position: Vector3<f32>) {
         let view = Matrix4::from_translation(-position);
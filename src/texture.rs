@@ -9,9 +9,11 @@ pub struct Texture {
 }
 
 impl Texture {
+    /// Render target that can be sampled and read back.
     pub fn empty(
         device: &wgpu::Device,
         dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
         am: Option<wgpu::AddressMode>,
         label: Option<&str>,
     ) -> Result<Self> {
@@ -32,10 +34,11 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::COPY_DST,
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
             label,
             view_formats: &[],
         });
@@ -43,9 +46,9 @@ impl Texture {
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
+            address_mode_u: am,
+            address_mode_v: am,
+            address_mode_w: am,
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Nearest,
             mipmap_filter: wgpu::FilterMode::Nearest,
@@ -59,6 +62,40 @@ impl Texture {
         })
     }
 
+    /// Target of compute shaders that can also be sampled and copied, see
+    /// `compute::Storage`.
+    pub fn storage(
+        device: &wgpu::Device,
+        dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: dimensions.0,
+                height: dimensions.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -77,7 +114,30 @@ impl Texture {
         am: wgpu::AddressMode,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
+        Self::upload(device, queue, img, am, 1, label)
+    }
+
+    /// Like `from_image`, with a full mip chain generated on the CPU.
+    pub fn from_image_mipmapped(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        am: wgpu::AddressMode,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let (w, h) = img.dimensions();
+        let levels = 32 - w.max(h).max(1).leading_zeros();
+        Self::upload(device, queue, img, am, levels, label)
+    }
+
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        am: wgpu::AddressMode,
+        mip_level_count: u32,
+        label: Option<&str>,
+    ) -> Result<Self> {
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -88,7 +148,7 @@ impl Texture {
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -98,28 +158,37 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        for level in 0..mip_level_count {
+            let size = texture.size().mip_level_size(level, wgpu::TextureDimension::D2);
+            let rgba = if level == 0 {
+                img.to_rgba8()
+            } else {
+                img.resize_exact(size.width, size.height, image::imageops::FilterType::Triangle)
+                    .to_rgba8()
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * size.width),
+                    rows_per_image: Some(size.height),
+                },
+                size,
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
+            address_mode_u: am,
+            address_mode_v: am,
+            address_mode_w: am,
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Nearest,
             mipmap_filter: wgpu::FilterMode::Nearest,
@@ -132,4 +201,78 @@ impl Texture {
             sampler,
         })
     }
+
+    /// Cube texture from six equally sized faces in +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            bail!("a cubemap needs 6 faces, got {}", faces.len());
+        }
+        let dimensions = faces[0].dimensions();
+        if faces.iter().any(|f| f.dimensions() != dimensions) {
+            bail!("cubemap faces differ in size");
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: dimensions.0,
+                height: dimensions.1,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label,
+            view_formats: &[],
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &face.to_rgba8(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * dimensions.0),
+                    rows_per_image: Some(dimensions.1),
+                },
+                wgpu::Extent3d {
+                    width: dimensions.0,
+                    height: dimensions.1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
 }
@@ -0,0 +1,151 @@
+//! GPU time of spans of commands, like the passes of a frame, read back a
+//! frame or more later unless asked to wait.
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+/// Values of `GpuTimer::mapped`.
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Resolved into the readback buffer by the encoder being recorded.
+    Copied,
+    /// Waiting for the readback buffer to map.
+    Mapping,
+}
+
+pub struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    spans: u32,
+    /// Nanoseconds per tick.
+    period: f32,
+    state: State,
+    /// Set by the mapping of the readback buffer once it is done.
+    mapped: Arc<AtomicU8>,
+}
+
+impl GpuTimer {
+    /// Timer of `spans` spans, `None` if the device has no timestamp
+    /// queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, spans: u32) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let queries = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("timer_queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2 * spans,
+        });
+        let size = 2 * spans as u64 * std::mem::size_of::<u64>() as u64;
+        let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timer_resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timer_readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            queries,
+            resolve,
+            readback,
+            spans,
+            period: queue.get_timestamp_period(),
+            state: State::Idle,
+            mapped: Arc::new(AtomicU8::new(PENDING)),
+        })
+    }
+
+    /// Starts `span`, before the commands it measures. Every span has to
+    /// be written each frame, an empty one by an `end` right after.
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, span: u32) {
+        self.mark(encoder, 2 * span);
+    }
+
+    /// Ends `span`, after the commands it measures.
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, span: u32) {
+        self.mark(encoder, 2 * span + 1);
+    }
+
+    /// An empty compute pass writing timestamp `index`, which needs no
+    /// target unlike a render pass.
+    fn mark(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("timer"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.queries,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+    }
+
+    /// Copies the spans out for reading, after the last `end`. Skipped
+    /// while the last ones are still being read.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.state != State::Idle {
+            return;
+        }
+        encoder.resolve_query_set(&self.queries, 0..2 * self.spans, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, self.resolve.size());
+        self.state = State::Copied;
+    }
+
+    /// Starts reading the spans back, after the encoder was submitted. With
+    /// `wait` they are ready for `read` on return.
+    pub fn submitted(&mut self, device: &wgpu::Device, wait: bool) {
+        if self.state == State::Copied {
+            let mapped = self.mapped.clone();
+            self.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let status = if result.is_ok() { MAPPED } else { FAILED };
+                    mapped.store(status, Ordering::Release);
+                });
+            self.state = State::Mapping;
+        }
+        device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+    }
+
+    /// Seconds of each span read back since the last call, if any. A
+    /// failed read is dropped and the next frame's spans are read instead.
+    pub fn read(&mut self) -> Option<Vec<f32>> {
+        match self.mapped.swap(PENDING, Ordering::Acquire) {
+            MAPPED => (),
+            FAILED => {
+                // a failed mapping leaves the buffer unmapped, unmapping it
+                // again would be a validation error
+                eprintln!("failed to read the GPU timestamps back");
+                self.state = State::Idle;
+                return None;
+            }
+            _ => return None,
+        }
+        let ticks: Vec<u64> = {
+            let data = self.readback.slice(..).get_mapped_range();
+            data.chunks_exact(8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                .collect()
+        };
+        self.readback.unmap();
+        self.state = State::Idle;
+        let seconds = |t: &[u64]| t[1].saturating_sub(t[0]) as f32 * self.period * 1e-9;
+        Some(ticks.chunks_exact(2).map(seconds).collect())
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::{anyhow, Result};
+use wgpu::util::DeviceExt;
+
+use crate::{sampling::Upscale, texture::Texture};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Operator {
+    /// Clamps to the display range.
+    #[default]
+    None,
+    Reinhard,
+    /// Filmic curve of the Academy Color Encoding System.
+    Aces,
+}
+
+impl Operator {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "reinhard" => Ok(Self::Reinhard),
+            "aces" => Ok(Self::Aces),
+            _ => Err(anyhow!("invalid tonemap `{}`, expected none, reinhard or aces", s)),
+        }
+    }
+}
+
+/// Mirrors `Settings` in shaders/tonemap.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Settings {
+    mode: u32,
+    exposure: f32,
+    weight: f32,
+    /// `Upscale`, 0: bilinear, 1: nearest
+    upscale: u32,
+    /// Source pixels per target pixel.
+    scale: [f32; 2],
+}
+
+/// Draws the image target into the output, scaled by the exposure,
+/// compressed by the operator and filtered to the output size.
+pub struct Tonemap {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    operator: Operator,
+    /// Linear scale.
+    exposure: f32,
+    upscale: Upscale,
+}
+
+impl Tonemap {
+    /// `exposure` is in stops, each doubling the brightness.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        operator: Operator,
+        exposure: f32,
+        upscale: Upscale,
+    ) -> Self {
+        let mut tonemap = Self::with_blend(device, format, upscale, None);
+        tonemap.operator = operator;
+        tonemap.exposure = exposure.exp2();
+        tonemap
+    }
+
+    /// Adds weighted samples of a source to a target in `accumulate`,
+    /// without tonemapping. The target must be blendable.
+    pub fn accumulator(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        upscale: Upscale,
+    ) -> Self {
+        let add = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let blend = wgpu::BlendState {
+            color: add,
+            alpha: add,
+        };
+        Self::with_blend(device, format, upscale, Some(blend))
+    }
+
+    fn with_blend(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        upscale: Upscale,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tonemap_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/tonemap.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        Self {
+            pipeline,
+            layout,
+            operator: Operator::None,
+            exposure: 1.0,
+            upscale,
+        }
+    }
+
+    /// Draws `source` into `target` of `size`.
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &Texture,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        self.run(device, encoder, source, target, size, 1.0, true);
+    }
+
+    /// Adds `weight` times `source` to `target` of `size`, clearing it
+    /// first if `clear` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn accumulate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &Texture,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+        weight: f32,
+        clear: bool,
+    ) {
+        self.run(device, encoder, source, target, size, weight, clear);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &Texture,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+        weight: f32,
+        clear: bool,
+    ) {
+        let source_size = source.texture.size();
+        let settings = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tonemap_settings"),
+            contents: bytemuck::bytes_of(&Settings {
+                mode: self.operator as u32,
+                exposure: self.exposure,
+                weight,
+                upscale: self.upscale as u32,
+                scale: [
+                    source_size.width as f32 / size.0 as f32,
+                    source_size.height as f32 / size.1 as f32,
+                ],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: settings.as_entire_binding(),
+                },
+            ],
+        });
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tonemap"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
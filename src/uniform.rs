@@ -1,4 +1,4 @@
-use wgpu::util::DeviceExt;
+use wgpu::{naga, util::DeviceExt};
 
 pub struct Uniform<T> {
     pub data: T,
@@ -22,16 +22,7 @@ where
         });
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
+            entries: &[layout_entry()],
             label: Some("Uniform Bind Group Layout"),
         });
 
@@ -56,3 +47,235 @@ where
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
     }
 }
+
+/// The single entry of a `Uniform`'s bind group layout.
+pub fn layout_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX
+            | wgpu::ShaderStages::FRAGMENT
+            | wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// A member of a uniform struct as seen by the shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Layout of a uniform struct, compared against the shader on every load.
+/// Padding fields are left out. Implement with `uniform_layout!`.
+pub trait UniformLayout {
+    fn fields() -> Vec<Field>;
+}
+
+pub fn size_of_field<T, F>(_: impl Fn(&T) -> &F) -> usize {
+    std::mem::size_of::<F>()
+}
+
+/// `uniform_layout!(MainUniforms { time, resolution })`
+macro_rules! uniform_layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::uniform::UniformLayout for $ty {
+            fn fields() -> Vec<$crate::uniform::Field> {
+                vec![$($crate::uniform::Field {
+                    name: stringify!($field).to_string(),
+                    offset: std::mem::offset_of!($ty, $field) as u32,
+                    size: $crate::uniform::size_of_field(|u: &$ty| &u.$field) as u32,
+                }),*]
+            }
+        }
+    };
+}
+pub(crate) use uniform_layout;
+
+impl<T> Uniform<T>
+where
+    T: UniformLayout,
+{
+    /// Checks the struct the shader binds at `group`/`binding` against `T`,
+    /// see `check_struct`.
+    pub fn check_layout(module: &naga::Module, group: u32, binding: u32) -> Result<(), String> {
+        let type_name = std::any::type_name::<T>().rsplit("::").next().unwrap_or_default();
+        check_struct(module, group, binding, type_name, &T::fields())
+    }
+}
+
+/// Checks the struct the shader binds at `group`/`binding` against
+/// `fields`, the layout of `type_name`. The shader may leave out trailing
+/// or unused members, but every member it declares must match a field in
+/// name, offset and size.
+pub fn check_struct(
+    module: &naga::Module,
+    group: u32,
+    binding: u32,
+    type_name: &str,
+    fields: &[Field],
+) -> Result<(), String> {
+    let Some(var) = module.global_variables.iter().map(|(_, v)| v).find(|v| {
+        v.binding
+            .as_ref()
+            .is_some_and(|b| b.group == group && b.binding == binding)
+    }) else {
+        // the shader doesn't use the uniforms at all
+        return Ok(());
+    };
+    let var_name = var.name.as_deref().unwrap_or("<unnamed>");
+    if var.space != naga::AddressSpace::Uniform {
+        return Err(format!(
+            "`{}` at @group({}) @binding({}) must be a `var<uniform>` of `{}`",
+            var_name, group, binding, type_name
+        ));
+    }
+    let naga::TypeInner::Struct { members, .. } = &module.types[var.ty].inner else {
+        return Err(format!(
+            "`{}` at @group({}) @binding({}) must be a struct matching `{}`",
+            var_name, group, binding, type_name
+        ));
+    };
+
+    let mut errors = Vec::new();
+    for member in members {
+        let name = member.name.as_deref().unwrap_or("<unnamed>");
+        let size = module.types[member.ty].inner.size(module.to_ctx());
+        match fields.iter().find(|f| f.name == name) {
+            None => errors.push(format!(
+                "  member `{}` (offset {}, size {}) is not a field of `{}`",
+                name, member.offset, size, type_name
+            )),
+            Some(field) if field.offset != member.offset || field.size != size => {
+                errors.push(format!(
+                    "  member `{}` is at offset {} with size {}, `{}` has it at offset {} with size {}",
+                    name, member.offset, size, type_name, field.offset, field.size
+                ))
+            }
+            Some(_) => (),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let expected = fields
+        .iter()
+        .map(|f| format!("  {:>3}  {} ({} bytes)", f.offset, f.name, f.size))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(format!(
+        "uniform `{}` at @group({}) @binding({}) does not match `{}`:\n{}\nexpected layout:\n{}",
+        var_name,
+        group,
+        binding,
+        type_name,
+        errors.join("\n"),
+        expected
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Vec<Field> {
+        let field = |name: &str, offset, size| Field {
+            name: name.to_string(),
+            offset,
+            size,
+        };
+        vec![
+            field("time", 0, 4),
+            field("resolution", 8, 8),
+            field("frame", 16, 4),
+        ]
+    }
+
+    fn check(members: &str) -> Result<(), String> {
+        let code = format!(
+            "struct Data {{ {} }}\n@group(2) @binding(0) var<uniform> u: Data;",
+            members
+        );
+        let module = naga::front::wgsl::parse_str(&code).unwrap();
+        check_struct(&module, 2, 0, "Data", &fields())
+    }
+
+    #[test]
+    fn trailing_members_may_be_left_out() {
+        assert_eq!(check("time: f32, resolution: vec2<f32>"), Ok(()));
+        assert_eq!(
+            check("time: f32, resolution: vec2<f32>, frame: u32"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unknown_member() {
+        let err = check("time: f32, resolution: vec2<f32>, frames: u32").unwrap_err();
+        assert_eq!(
+            err,
+            "uniform `u` at @group(2) @binding(0) does not match `Data`:\n\
+             \x20 member `frames` (offset 16, size 4) is not a field of `Data`\n\
+             expected layout:\n\
+             \x20   0  time (4 bytes)\n\
+             \x20   8  resolution (8 bytes)\n\
+             \x20  16  frame (4 bytes)"
+        );
+    }
+
+    #[test]
+    fn member_mismatch() {
+        let err = check("time: f32, resolution: vec3<f32>").unwrap_err();
+        assert!(
+            err.contains(
+                "  member `resolution` is at offset 16 with size 12, \
+                 `Data` has it at offset 8 with size 8\n"
+            ),
+            "{}",
+            err
+        );
+        // every member is reported, not just the first
+        let err = check("resolution: vec2<f32>, time: f32").unwrap_err();
+        assert!(
+            err.contains("member `resolution` is at offset 0"),
+            "{}",
+            err
+        );
+        assert!(err.contains("member `time` is at offset 8"), "{}", err);
+    }
+
+    #[test]
+    fn binding_kind() {
+        let code = "struct Data { time: f32 }\n\
+                    @group(2) @binding(0) var<storage> u: Data;";
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+        assert_eq!(
+            check_struct(&module, 2, 0, "Data", &fields()),
+            Err("`u` at @group(2) @binding(0) must be a `var<uniform>` of `Data`".to_string())
+        );
+        let code = "@group(2) @binding(0) var<uniform> u: vec4<f32>;";
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+        assert_eq!(
+            check_struct(&module, 2, 0, "Data", &fields()),
+            Err("`u` at @group(2) @binding(0) must be a struct matching `Data`".to_string())
+        );
+        // a shader that does not bind the uniforms at all
+        let module = naga::front::wgsl::parse_str("const x = 1.0;").unwrap();
+        assert_eq!(check_struct(&module, 2, 0, "Data", &fields()), Ok(()));
+    }
+    #[test]
+    fn builtin_shader_matches_main_uniforms() {
+        use crate::uniforms::uniforms::MainUniforms;
+
+        let code = include_str!("./shaders/sprite.wgsl");
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+        assert_eq!(Uniform::<MainUniforms>::check_layout(&module, 2, 0), Ok(()));
+    }
+}
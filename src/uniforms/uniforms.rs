@@ -1,21 +1,86 @@
+use crate::uniform::uniform_layout;
+
+/// Uniforms shared by every pass at `@group(2) @binding(0)`.
+///
+/// Laid out for the uniform address space (std140): `vec2` fields sit on
+/// 8 byte boundaries and `vec4` fields on 16, see the asserts below.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MainUniforms {
+    /// `iTime`, seconds since start.
     pub time: f32,
-    _pad: f32,
-    pub resulotion: [f32; 2],
+    /// `iTimeDelta`, seconds since the previous frame.
+    pub time_delta: f32,
+    pub resolution: [f32; 2],
+    /// Cursor position in window pixels, top-left origin.
     pub mouse_position: [f32; 2],
     pub zoom: [f32; 2],
+    /// `iFrame`
+    pub frame: i32,
+    /// `iFrameRate`
+    pub frame_rate: f32,
+    /// `iSampleRate`
+    pub sample_rate: f32,
+    _pad: f32,
+    /// `iMouse`: xy is the position while dragging and zw the click
+    /// position, bottom-left origin. z is negative once the button is
+    /// released and w only positive on the frame of the click.
+    pub mouse: [f32; 4],
+    /// `iDate`: year, month (0-11), day (1-31) and seconds since midnight.
+    pub date: [f32; 4],
+    /// Pixel offset of the rendered tile in the canvas, top-left origin.
+    /// Zero unless rendering a poster in tiles, where `resolution` is the
+    /// whole canvas, or a sub-pixel jitter while accumulating samples.
+    pub tile_offset: [f32; 2],
+    _pad_end: [f32; 2],
 }
 
+uniform_layout!(MainUniforms {
+    time,
+    time_delta,
+    resolution,
+    mouse_position,
+    zoom,
+    frame,
+    frame_rate,
+    sample_rate,
+    mouse,
+    date,
+    tile_offset,
+});
+
+const _: () = {
+    use std::mem::{offset_of, size_of};
+    assert!(offset_of!(MainUniforms, time) == 0);
+    assert!(offset_of!(MainUniforms, time_delta) == 4);
+    assert!(offset_of!(MainUniforms, resolution) == 8);
+    assert!(offset_of!(MainUniforms, mouse_position) == 16);
+    assert!(offset_of!(MainUniforms, zoom) == 24);
+    assert!(offset_of!(MainUniforms, frame) == 32);
+    assert!(offset_of!(MainUniforms, frame_rate) == 36);
+    assert!(offset_of!(MainUniforms, sample_rate) == 40);
+    assert!(offset_of!(MainUniforms, mouse) == 48);
+    assert!(offset_of!(MainUniforms, date) == 64);
+    assert!(offset_of!(MainUniforms, tile_offset) == 80);
+    assert!(size_of::<MainUniforms>() == 96);
+};
+
 impl Default for MainUniforms {
     fn default() -> Self {
         Self { 
             time: 0.0, 
-            resulotion: [10.0, 10.0], 
+            time_delta: 0.0,
+            resolution: [10.0, 10.0], 
             mouse_position: [0.0, 0.0],
             zoom: [0.0, 0.0],
-            _pad: 10.0,
+            frame: 0,
+            frame_rate: 0.0,
+            sample_rate: 44100.0,
+            _pad: 0.0,
+            mouse: [0.0; 4],
+            date: [0.0; 4],
+            tile_offset: [0.0; 2],
+            _pad_end: [0.0; 2],
         }
     }
 }
@@ -1,20 +1,25 @@
 use pollster::FutureExt;
 use winit::{
-    application::ApplicationHandler, event::{DeviceEvent, WindowEvent}, event_loop::ActiveEventLoop, window::{Window, WindowAttributes, WindowId}
+    application::ApplicationHandler, dpi::PhysicalSize, event::{DeviceEvent, DeviceId, WindowEvent}, event_loop::ActiveEventLoop, window::{WindowAttributes, WindowId}
 };
 
-use crate::{gpu::GpuState, input_manager::InputEvent};
+use crate::{cli::Options, gpu::GpuState, input_manager::InputEvent, record::Recorder};
 
 pub struct App {
     time: instant::Instant,
+    options: Options,
     state: Option<GpuState>,
+    /// Handed to the state once the window exists.
+    recorder: Option<Recorder>,
 }
 
-impl Default for App {
-    fn default() -> Self {
+impl App {
+    pub fn new(options: Options, recorder: Option<Recorder>) -> Self {
         Self {
             time: instant::Instant::now(),
+            options,
             state: None,
+            recorder,
         }
     }
 }
@@ -22,8 +27,10 @@ impl Default for App {
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
 
+        #[allow(unused_mut)]
         let mut attrs = WindowAttributes::default()
-            .with_title("shader_toy");
+            .with_title("shader_toy")
+            .with_inner_size(PhysicalSize::new(self.options.width, self.options.height));
 
         #[cfg(target_os = "windows")]
         {
@@ -39,9 +46,18 @@ impl ApplicationHandler for App {
         let window = event_loop.create_window(attrs).unwrap();
 
         self.time = instant::Instant::now();
-        let state = GpuState::new(window);
+        let mut state = GpuState::new(window, &self.options).block_on();
+        if let Some(recorder) = self.recorder.take() {
+            state.record(recorder);
+        }
+        if let Some(path) = &self.options.profile {
+            if let Err(err) = state.profile(path) {
+                eprintln!("error: {:#}", err);
+                std::process::exit(1);
+            }
+        }
 
-        self.state = Some(state.block_on());
+        self.state = Some(state);
     }
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         let state = if let Some(state) = &mut self.state {
@@ -54,8 +70,13 @@ impl ApplicationHandler for App {
             match event {
                 WindowEvent::CloseRequested => {
                     println!("The close button was pressed; stopping");
+                    state.finish_recording();
+                    state.finish_profile();
                     event_loop.exit();
                 }
+                WindowEvent::Resized(size) => {
+                    state.resize(size);
+                }
                 WindowEvent::RedrawRequested => {
                     let now = instant::Instant::now();
                     let dt = now - self.time;
@@ -70,4 +91,9 @@ impl ApplicationHandler for App {
             }
         }
     }
+    fn device_event(&mut self, _event_loop: &ActiveEventLoop, _id: DeviceId, event: DeviceEvent) {
+        if let Some(state) = &mut self.state {
+            state.input(InputEvent::Device(&event));
+        }
+    }
 }
@@ -0,0 +1,13 @@
+//! Renders the shaders in `tests/shaders` and compares them with the
+//! references next to them. `BLESS=1 cargo test` updates the references.
+
+use shader_toy::regression::{self, Config};
+
+#[test]
+fn shaders_match_references() {
+    let mut config = Config::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/shaders"));
+    config.failures = concat!(env!("CARGO_MANIFEST_DIR"), "/target/regression").into();
+    config.bless = std::env::var_os("BLESS").is_some();
+    let report = regression::run(&config).unwrap();
+    assert!(report.failures.is_empty(), "{}", report);
+}
@@ -0,0 +1,47 @@
+// Writes the uniforms into bands, catches changes to the `MainUniforms`
+// layout.
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+struct Uniforms {
+    time: f32,
+    time_delta: f32,
+    resolution: vec2<f32>,
+    mouse_position: vec2<f32>,
+    zoom: vec2<f32>,
+    frame: i32,
+    frame_rate: f32,
+    sample_rate: f32,
+    mouse: vec4<f32>,
+    date: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> u: Uniforms;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position * 2.0, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let band = i32(in.tex_coords.y * 4.0);
+    if band == 0 {
+        return vec4<f32>(fract(u.time), u.time_delta * 30.0, f32(u.frame % 64) / 64.0, 1.0);
+    } else if band == 1 {
+        return vec4<f32>(u.resolution / 256.0, u.sample_rate / 88200.0, 1.0);
+    } else if band == 2 {
+        return vec4<f32>((u.date.x - 1999.5), u.date.y / 12.0, u.date.z / 31.0, 1.0);
+    }
+    return vec4<f32>(in.tex_coords, u.date.w / 86400.0, 1.0);
+}